@@ -1,6 +1,9 @@
 use crate::{
     Args,
-    frontend::SourceView,
+    frontend::{
+        SourceView,
+        derivation::{MinimalInput, Fragment},
+    },
     grammar::{
         Grammar, StringId, NumbersetId, NumbersetType,
         Numberset, ContainerId, Container, ContainerType,
@@ -12,9 +15,9 @@ use crate::{
     emit_raw, emit_line,
 };
 use std::ops::Range;
+use std::collections::BTreeMap;
 use num_traits::{
     Num, cast::NumCast, bounds::Bounded,
-    ops::wrapping::{WrappingAdd, WrappingSub},
 };
 use std::fmt::Display;
 
@@ -32,6 +35,7 @@ fn emit_header(prod: &mut CodeFormatter, args: &Args, options: &ContainerOptions
   Global endianness: {}
   Global scheduling: {}
   Depth: {}
+  Profiling: {}
  ************************************/
  ",
         &args.grammar,
@@ -44,11 +48,13 @@ fn emit_header(prod: &mut CodeFormatter, args: &Args, options: &ContainerOptions
         match options.scheduling() {
             Scheduling::RoundRobin => "round-robin",
             Scheduling::Random => "random",
+            Scheduling::Adaptive => "adaptive",
         },
         match options.depth() {
             Depth::Unlimited => "unlimited".to_string(),
             Depth::Limited(limit) => format!("{}", limit),
-        }
+        },
+        args.profile,
     );
 }
 
@@ -86,6 +92,14 @@ fn emit_macros(prod: &mut CodeFormatter) {
 #define SEED 0x35c6be9ba2548264
 #endif
 
+// Parameters of oneofs with adaptive scheduling
+#ifndef ADAPTIVE_LEARNING_RATE
+#define ADAPTIVE_LEARNING_RATE 0.5
+#endif
+#ifndef ADAPTIVE_EXPLORATION
+#define ADAPTIVE_EXPLORATION 0.1
+#endif
+
 // Define endianness helper functions
 #define LITTLE_ENDIAN_16(x) htole16((uint16_t) (x))
 #define BIG_ENDIAN_16(x)    htobe16((uint16_t) (x))
@@ -208,7 +222,7 @@ fn numberset_c_type(typ: &NumbersetType) -> &str {
 
 fn emit_range_selection<R>(prod: &mut CodeFormatter, range: &Range<R>, c_type: &str, suffix: &str) 
 where
-    R: Display + Ord + Num + NumCast + Copy + Bounded + WrappingAdd + WrappingSub,
+    R: Display + Ord + Num + NumCast + Copy + Bounded,
 {
     let zero = R::from(0).unwrap();
     let one = R::from(1).unwrap();
@@ -225,19 +239,22 @@ where
     } else {
         emit_line!(prod, "uint64_t range_selector = rand();");
         
-        let delta: R = range.end.wrapping_sub(&range.start).wrapping_add(&one);
+        // The offset into the range is calculated with unsigned 64-bit arithmetic.
+        // For signed types this avoids overflows in the size of the range
+        // and negative remainders.
+        let delta = range.end.to_i128().unwrap() - range.start.to_i128().unwrap() + 1;
         
-        if delta.cmp(&zero) == std::cmp::Ordering::Equal {
-            emit_line!(prod, "return (({}) range_selector) + {};", c_type, start_format);
+        if delta > u64::MAX as i128 {
+            emit_line!(prod, "return ({}) range_selector;", c_type);
         } else {
-            emit_line!(prod, "return ((({}) range_selector) % {}{}) + {};", c_type, delta, suffix, start_format);
+            emit_line!(prod, "return ({}) ((uint64_t) {} + (range_selector % {}ULL));", c_type, start_format, delta);
         }
     }
 }
 
 fn emit_single_numberset<R>(prod: &mut CodeFormatter, numberset: &Numberset<R>, c_type: &str, suffix: &str)
 where
-    R: Display + Ord + Num + NumCast + Copy + Bounded + WrappingAdd + WrappingSub,
+    R: Display + Ord + Num + NumCast + Copy + Bounded,
 {
     if numberset.len() == 1 {
         emit_range_selection(prod, &numberset[0], c_type, suffix);
@@ -292,32 +309,208 @@ fn emit_numbersets(prod: &mut CodeFormatter, grammar: &Grammar) {
     }
 }
 
-fn container_func(id: &ContainerId) -> String {
-    format!("container_{}", id)
+/// Names of the C functions that implement the containers
+type FuncNames = BTreeMap<ContainerId, String>;
+
+fn container_func<'a>(names: &'a FuncNames, id: &ContainerId) -> &'a str {
+    names.get(id).unwrap()
 }
 
-fn emit_declarations(prod: &mut CodeFormatter, grammar: &Grammar) {
-    emit_raw!(prod, "\n// Forward declarations of containers\n");
+/// Profiling and tracing wrap every container in a function that takes measurements
+fn has_wrappers(args: &Args) -> bool {
+    args.profile || args.trace
+}
+
+/// With wrappers the container is defined under a different name
+/// and its original name belongs to the wrapper
+fn container_body_func(args: &Args, names: &FuncNames, id: &ContainerId) -> String {
+    if has_wrappers(args) {
+        format!("{}_body", container_func(names, id))
+    } else {
+        container_func(names, id).to_string()
+    }
+}
+
+/// By default functions are named after the container IDs. With --readable-names
+/// named structs get their name and anonymous containers the name of the struct
+/// they are defined in followed by the position of the variable.
+fn container_funcs(args: &Args, grammar: &Grammar, view: &SourceView) -> FuncNames {
+    let mut ret = FuncNames::new();
     
     for container in grammar.containers() {
-        match grammar.options().depth() {
-            Depth::Unlimited => {
-                emit_line!(prod, "static size_t {}(unsigned char*, size_t);", container_func(&container.id()));
-            },
-            Depth::Limited(_) => {
-                emit_line!(prod, "static size_t {}(unsigned char*, size_t, uint64_t);", container_func(&container.id()));
+        let id = container.id();
+        
+        if !args.readable_names {
+            ret.insert(id, format!("container_{}", id));
+            continue;
+        }
+        
+        let mut name = match container.name() {
+            Some(name) if !name.is_empty() => format!("struct_{}", sanitize_name(view.range(name))),
+            _ => {
+                // Parents have smaller IDs than the containers defined inside them
+                let parent = grammar.containers().find_map(|parent| {
+                    parent.variables().iter().position(|var| matches!(
+                        var.typ(),
+                        VariableType::ContainerRef(target) | VariableType::Oneof(target) if *target == id
+                    )).map(|idx| (parent.id(), idx))
+                });
+                let kind = match container.typ() {
+                    ContainerType::Struct => "struct",
+                    ContainerType::Oneof => "oneof",
+                };
+                
+                match parent.and_then(|(parent, idx)| ret.get(&parent).map(|name| (name, idx))) {
+                    Some((parent, idx)) => format!("{}_{}{}", parent, kind, idx),
+                    None => format!("container_{}", id),
+                }
             },
+        };
+        
+        // Non-ASCII names may collide after sanitization
+        if ret.values().any(|other| *other == name) {
+            name = format!("{}_{}", name, id);
+        }
+        
+        ret.insert(id, name);
+    }
+    
+    ret
+}
+
+fn extern_func(args: &Args, name: &str) -> String {
+    format!("{}extern_{}", args.prefix, name)
+}
+
+fn emit_declarations(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, names: &FuncNames) {
+    emit_raw!(prod, "\n// Forward declarations of containers\n");
+    
+    for container in grammar.containers() {
+        let mut funcs = vec![container_func(names, &container.id()).to_string()];
+        
+        if has_wrappers(args) {
+            funcs.push(container_body_func(args, names, &container.id()));
+        }
+        
+        for func in funcs {
+            match grammar.options().depth() {
+                Depth::Unlimited => {
+                    emit_line!(prod, "static size_t {}(unsigned char*, size_t);", func);
+                },
+                Depth::Limited(_) => {
+                    emit_line!(prod, "static size_t {}(unsigned char*, size_t, uint64_t);", func);
+                },
+            }
+        }
+    }
+    
+    let counters: Vec<&Container> = grammar.containers().filter(|c| owns_recursion_counter(c)).collect();
+    
+    if !counters.is_empty() {
+        emit_raw!(prod, "\n// Number of active instances of containers with a recursion limit\n");
+        
+        for container in counters {
+            emit_line!(prod, "static THREAD_LOCAL uint64_t {} = 0;", recursion_counter(&container.id()));
+        }
+    }
+}
+
+fn recursion_counter(id: &ContainerId) -> String {
+    format!("recursion_{}", id)
+}
+
+fn owns_recursion_counter(container: &Container) -> bool {
+    matches!(container.options().recursion(), Some((owner, _)) if *owner == container.id())
+}
+
+/// The condition under which variables may be expanded beyond their minimum,
+/// or None if there is no limit
+fn expansion_cond(options: &ContainerOptions) -> Option<String> {
+    let mut conds = Vec::new();
+    
+    if let Depth::Limited(limit) = options.depth() {
+        conds.push(format!("depth < {}ULL", limit));
+    }
+    
+    if let Some((owner, limit)) = options.recursion() {
+        conds.push(format!("{} < {}ULL", recursion_counter(owner), limit));
+    }
+    
+    if conds.is_empty() {
+        None
+    } else {
+        Some(conds.join(" && "))
+    }
+}
+
+fn emit_extern_declarations(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar) {
+    let names = grammar.extern_names();
+    
+    if !names.is_empty() {
+        emit_raw!(prod, "\n// External functions that must be supplied by the user\n");
+        
+        for name in names {
+            emit_line!(prod, "size_t {}(unsigned char*, size_t);", extern_func(args, name));
         }
     }
 }
 
-fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variable, options: &ContainerOptions) -> bool {
+/// If the remaining buffer is too small for the chosen length, use the largest
+/// length from the numberset that still fits instead of giving up on the variable
+fn emit_length_clamp(prod: &mut CodeFormatter, grammar: &Grammar, id: &NumbersetId, var_name: &str) {
+    let ranges = match grammar.numbersets().get(id).unwrap() {
+        NumbersetType::U32(v) => v,
+        _ => panic!("Length of bytearray is not a u32 numberset"),
+    };
+    
+    emit_line!(prod, "if (UNLIKELY(len < {})) {{", var_name);
+    prod.block_open();
+    
+    for (i, range) in ranges.iter().rev().enumerate() {
+        // A range starting at 0 always fits
+        if range.start == 0 {
+            if i > 0 {
+                emit_line!(prod, "}} else {{");
+                prod.block_open();
+            }
+            
+            emit_line!(prod, "{} = (len < {1}U) ? (uint32_t) len : {1}U;", var_name, range.end);
+            
+            if i > 0 {
+                prod.block_close();
+                emit_line!(prod, "}}");
+            }
+            
+            prod.block_close();
+            emit_line!(prod, "}}");
+            return;
+        }
+        
+        let keyword = if i == 0 { "if" } else { "} else if" };
+        
+        emit_line!(prod, "{} (len >= {}U) {{", keyword, range.start);
+        prod.block_open();
+        emit_line!(prod, "{} = (len < {1}U) ? (uint32_t) len : {1}U;", var_name, range.end);
+        prod.block_close();
+    }
+    
+    emit_line!(prod, "}} else {{");
+    prod.block_open();
+    emit_line!(prod, "goto container_end;");
+    prod.block_close();
+    emit_line!(prod, "}}");
+    
+    prod.block_close();
+    emit_line!(prod, "}}");
+}
+
+fn emit_variable(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, names: &FuncNames, variable: &Variable, options: &ContainerOptions) -> bool {
     let mut label_ref = false;
     
     if variable.options().optional() {
-        let depth_cond = match options.depth() {
-            Depth::Unlimited => "".to_string(),
-            Depth::Limited(limit) => format!("(depth < {}ULL) && ", limit),
+        let depth_cond = match expansion_cond(options) {
+            None => "".to_string(),
+            Some(cond) => format!("({}) && ", cond),
         };
         
         emit_line!(prod, "if ({}(rand() & 1)) {{", depth_cond);
@@ -325,13 +518,13 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
     }
     
     if let Some(id) = variable.options().repeats() {
-        match options.depth() {
-            Depth::Unlimited => {
+        match expansion_cond(options) {
+            None => {
                 emit_line!(prod, "uint32_t repeats_i = {}();", numberset_func(id));
             },
-            Depth::Limited(limit) => {
+            Some(cond) => {
                 emit_line!(prod, "uint32_t repeats_i;");
-                emit_line!(prod, "if (depth < {}UL) {{", limit);
+                emit_line!(prod, "if ({}) {{", cond);
                 prod.block_open();
                 emit_line!(prod, "repeats_i = {}();", numberset_func(id));
                 prod.block_close();
@@ -385,7 +578,7 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
                 },
             }
             
-            match options.endianness() {
+            match variable.options().endianness().unwrap_or(options.endianness()) {
                 Endianness::Little => {
                     emit_line!(prod, "*(uint16_t*)buf = LITTLE_ENDIAN_16(integer);");
                 },
@@ -417,7 +610,7 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
                 },
             }
             
-            match options.endianness() {
+            match variable.options().endianness().unwrap_or(options.endianness()) {
                 Endianness::Little => {
                     emit_line!(prod, "*(uint32_t*)buf = LITTLE_ENDIAN_32(integer);");
                 },
@@ -449,7 +642,7 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
                 },
             }
             
-            match options.endianness() {
+            match variable.options().endianness().unwrap_or(options.endianness()) {
                 Endianness::Little => {
                     emit_line!(prod, "*(uint64_t*)buf = LITTLE_ENDIAN_64(integer);");
                 },
@@ -477,13 +670,13 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
                     emit_line!(prod, "buf += sizeof({0}); len -= sizeof({0});", var_name);
                 },
                 BytearrayValue::Any(id) => {
-                    match options.depth() {
-                        Depth::Unlimited => {
+                    match expansion_cond(options) {
+                        None => {
                             emit_line!(prod, "uint32_t string_len = {}();", numberset_func(id));
                         },
-                        Depth::Limited(limit) => {
+                        Some(cond) => {
                             emit_line!(prod, "uint32_t string_len;");
-                            emit_line!(prod, "if (depth < {}UL) {{", limit);
+                            emit_line!(prod, "if ({}) {{", cond);
                             prod.block_open();
                             emit_line!(prod, "string_len = {}();", numberset_func(id));
                             prod.block_close();
@@ -495,11 +688,7 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
                         },
                     }
                                         
-                    emit_line!(prod, "if (UNLIKELY(len < string_len)) {{");
-                    prod.block_open();
-                    emit_line!(prod, "goto container_end;");
-                    prod.block_close();
-                    emit_line!(prod, "}}");
+                    emit_length_clamp(prod, grammar, id, "string_len");
                     emit_line!(prod, "random_buffer(buf, string_len, MASK_STRING);");
                     emit_line!(prod, "buf += string_len; len -= string_len;");
                 },
@@ -519,13 +708,13 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
                     emit_line!(prod, "buf += sizeof({0}); len -= sizeof({0});", var_name);
                 },
                 BytearrayValue::Any(id) => {
-                    match options.depth() {
-                        Depth::Unlimited => {
+                    match expansion_cond(options) {
+                        None => {
                             emit_line!(prod, "uint32_t bytes_len = {}();", numberset_func(id));
                         },
-                        Depth::Limited(limit) => {
+                        Some(cond) => {
                             emit_line!(prod, "uint32_t bytes_len;");
-                            emit_line!(prod, "if (depth < {}UL) {{", limit);
+                            emit_line!(prod, "if ({}) {{", cond);
                             prod.block_open();
                             emit_line!(prod, "bytes_len = {}();", numberset_func(id));
                             prod.block_close();
@@ -537,11 +726,7 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
                         },
                     }
                     
-                    emit_line!(prod, "if (UNLIKELY(len < bytes_len)) {{");
-                    prod.block_open();
-                    emit_line!(prod, "goto container_end;");
-                    prod.block_close();
-                    emit_line!(prod, "}}");
+                    emit_length_clamp(prod, grammar, id, "bytes_len");
                     emit_line!(prod, "random_buffer(buf, bytes_len, MASK_BYTES);");
                     emit_line!(prod, "buf += bytes_len; len -= bytes_len;");
                 },
@@ -550,10 +735,10 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
         VariableType::Oneof(id) => {
             match grammar.options().depth() {
                 Depth::Unlimited => {
-                    emit_line!(prod, "size_t container_len = {}(buf, len);", container_func(id));
+                    emit_line!(prod, "size_t container_len = {}(buf, len);", container_func(names, id));
                 },
                 Depth::Limited(_) => {
-                    emit_line!(prod, "size_t container_len = {}(buf, len, depth);", container_func(id));
+                    emit_line!(prod, "size_t container_len = {}(buf, len, depth);", container_func(names, id));
                 },
             }
             emit_line!(prod, "buf += container_len; len -= container_len;");
@@ -561,18 +746,42 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
         VariableType::ContainerRef(id) => {
             match grammar.options().depth() {
                 Depth::Unlimited => {
-                    emit_line!(prod, "size_t container_len = {}(buf, len);", container_func(id));
+                    emit_line!(prod, "size_t container_len = {}(buf, len);", container_func(names, id));
                 },
                 Depth::Limited(_) => {
-                    emit_line!(prod, "size_t container_len = {}(buf, len, depth + 1);", container_func(id));
+                    emit_line!(prod, "size_t container_len = {}(buf, len, depth + 1);", container_func(names, id));
                 },
             }
             emit_line!(prod, "buf += container_len; len -= container_len;");
         },
+        VariableType::Epsilon => {
+            emit_line!(prod, "// empty");
+        },
+        VariableType::ExternRef(name) => {
+            emit_line!(prod, "size_t extern_len = {}(buf, len);", extern_func(args, name));
+            emit_line!(prod, "if (extern_len > len) extern_len = len;");
+            emit_line!(prod, "buf += extern_len; len -= extern_len;");
+        },
         VariableType::ResolveContainerRef(_) => panic!("Encountered unresolved container reference"),
     }
     
     if variable.options().repeats().is_some() {
+        if let Some(id) = variable.options().separator() {
+            label_ref = true;
+            let var_name = string_var(id);
+            emit_line!(prod, "if (repeats_i) {{");
+            prod.block_open();
+            emit_line!(prod, "if (UNLIKELY(len < sizeof({}))) {{", var_name);
+            prod.block_open();
+            emit_line!(prod, "goto container_end;");
+            prod.block_close();
+            emit_line!(prod, "}}");
+            emit_line!(prod, "__builtin_memcpy_inline(buf, {0}, sizeof({0}));", var_name);
+            emit_line!(prod, "buf += sizeof({0}); len -= sizeof({0});", var_name);
+            prod.block_close();
+            emit_line!(prod, "}}");
+        }
+        
         prod.block_close();
         emit_line!(prod, "}}");
     }
@@ -585,21 +794,128 @@ fn emit_variable(prod: &mut CodeFormatter, grammar: &Grammar, variable: &Variabl
     label_ref
 }
 
-fn emit_oneof(prod: &mut CodeFormatter, grammar: &Grammar, container: &Container) {
+fn adaptive_weights(id: &ContainerId) -> String {
+    format!("oneof_weights_{}", id)
+}
+
+fn adaptive_hits(id: &ContainerId) -> String {
+    format!("oneof_hits_{}", id)
+}
+
+fn adaptive_oneofs(grammar: &Grammar) -> Vec<&Container> {
+    grammar.containers().filter(|c| c.typ() == ContainerType::Oneof && matches!(c.options().scheduling(), Scheduling::Adaptive)).collect()
+}
+
+fn coverage_counts(id: &ContainerId) -> String {
+    format!("oneof_counts_{}", id)
+}
+
+fn emit_self_coverage(prod: &mut CodeFormatter, args: &Args) {
+    emit_raw!(
+        prod,
+"
+// When enabled, oneofs select the variable that was selected the fewest times so far
+static THREAD_LOCAL int self_coverage_enabled = 0;
+
+void {}self_coverage(int enabled) {{
+    self_coverage_enabled = enabled;
+}}
+",
+        args.prefix
+    );
+}
+
+/// How an assertion refers to a container when it fails
+fn assertion_name(view: &SourceView, container: &Container) -> String {
+    match container.name() {
+        Some(name) if name.is_empty() => {
+            let (line, col) = view.lineinfo(name.start);
+            format!("anonymous struct in line {} column {}", line, col)
+        },
+        Some(name) => format!("struct {}", view.range(name)),
+        None => format!("oneof with ID {}", container.id()),
+    }
+}
+
+/// Check the max_len option of a container, unless the generator is compiled with NDEBUG
+fn emit_max_len_assertion(prod: &mut CodeFormatter, view: &SourceView, container: &Container) {
+    let limit = match container.options().max_len() {
+        Some((owner, limit)) if *owner == container.id() => *limit,
+        _ => return,
+    };
+    
+    emit_raw!(prod, "#ifndef NDEBUG\n");
+    emit_line!(prod, "if (UNLIKELY(original_len - len > {})) {{", limit);
+    prod.block_open();
+    emit_line!(prod, "fprintf(stderr, \"Assertion failed: {} generated %zu bytes but max_len is {}\\n\", original_len - len);", assertion_name(view, container), limit);
+    emit_line!(prod, "__builtin_abort();");
+    prod.block_close();
+    emit_line!(prod, "}}");
+    emit_raw!(prod, "#endif\n");
+}
+
+/// When the expansion budget is exhausted or the selected variable does not fit
+/// into the buffer, select the variable with the smallest minimal expansion instead
+fn emit_cheapest_fallback(prod: &mut CodeFormatter, grammar: &Grammar, container: &Container, container_sizes: &BTreeMap<ContainerId, usize>) {
+    let sizes: Vec<usize> = container.variables().iter().map(|var| grammar.minimal_variable_size(var, container_sizes)).collect();
+    let cheapest = grammar.cheapest_variable(container.id(), container_sizes);
+    
+    if sizes.iter().all(|size| *size == sizes[cheapest]) {
+        return;
+    }
+    
+    let sizes: Vec<String> = sizes.iter().map(|size| format!("{}ULL", size)).collect();
+    emit_line!(prod, "static const uint64_t oneof_min_sizes[{}] = {{{}}};", sizes.len(), sizes.join(", "));
+    
+    match expansion_cond(container.options()) {
+        None => {
+            emit_line!(prod, "if (len < oneof_min_sizes[oneof_selector]) {{");
+        },
+        Some(cond) => {
+            emit_line!(prod, "if (!({}) || len < oneof_min_sizes[oneof_selector]) {{", cond);
+        },
+    }
+    prod.block_open();
+    emit_line!(prod, "oneof_selector = {};", cheapest);
+    prod.block_close();
+    emit_line!(prod, "}}");
+}
+
+fn emit_oneof(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, names: &FuncNames, container: &Container, sizes: &BTreeMap<ContainerId, usize>, view: &SourceView) {
     let mut label_ref = false;
+    let num_vars = container.variables().len();
+    
+    // Adaptive oneofs remember their weights and which variables were
+    // selected during the current generation
+    if let Scheduling::Adaptive = container.options().scheduling() {
+        emit_raw!(prod, "static THREAD_LOCAL double {}[{}] = {{", adaptive_weights(&container.id()), num_vars);
+        for i in 0..num_vars {
+            emit_raw!(prod, "{}1.0", if i > 0 { ", " } else { "" });
+        }
+        emit_raw!(prod, "}};\n");
+        emit_line!(prod, "static THREAD_LOCAL unsigned char {}[{}];", adaptive_hits(&container.id()), num_vars);
+    }
+    
+    if args.self_coverage {
+        emit_line!(prod, "static THREAD_LOCAL uint64_t {}[{}];", coverage_counts(&container.id()), num_vars);
+    }
     
     match container.options().depth() {
         Depth::Unlimited => {
-            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len) {{", container_func(&container.id()));
+            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len) {{", container_body_func(args, names, &container.id()));
         },
         Depth::Limited(_) => {
-            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len, uint64_t depth) {{", container_func(&container.id()));
+            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len, uint64_t depth) {{", container_body_func(args, names, &container.id()));
         },
     }
     prod.block_open();
     
     emit_line!(prod, "size_t original_len = len;");
     
+    if owns_recursion_counter(container) {
+        emit_line!(prod, "{}++;", recursion_counter(&container.id()));
+    }
+    
     match container.options().scheduling() {
         Scheduling::Random => {
             emit_line!(prod, "uint64_t oneof_selector = rand() % {};", container.variables().len());
@@ -608,6 +924,66 @@ fn emit_oneof(prod: &mut CodeFormatter, grammar: &Grammar, container: &Container
             emit_line!(prod, "static THREAD_LOCAL uint64_t oneof_cursor = 0;");
             emit_line!(prod, "uint64_t oneof_selector = oneof_cursor++ % {};", container.variables().len());
         },
+        Scheduling::Adaptive => {
+            let weights = adaptive_weights(&container.id());
+            emit_line!(prod, "uint64_t oneof_selector = {} - 1;", num_vars);
+            emit_line!(prod, "if (random_unit() < ADAPTIVE_EXPLORATION) {{");
+            prod.block_open();
+            emit_line!(prod, "oneof_selector = rand() % {};", num_vars);
+            prod.block_close();
+            emit_line!(prod, "}} else {{");
+            prod.block_open();
+            emit_line!(prod, "double weight_sum = 0.0;");
+            emit_line!(prod, "for (uint64_t i = 0; i < {}; ++i) {{", num_vars);
+            prod.block_open();
+            emit_line!(prod, "weight_sum += {}[i];", weights);
+            prod.block_close();
+            emit_line!(prod, "}}");
+            emit_line!(prod, "double point = random_unit() * weight_sum;");
+            emit_line!(prod, "for (uint64_t i = 0; i < {} - 1; ++i) {{", num_vars);
+            prod.block_open();
+            emit_line!(prod, "if (point < {}[i]) {{", weights);
+            prod.block_open();
+            emit_line!(prod, "oneof_selector = i;");
+            emit_line!(prod, "break;");
+            prod.block_close();
+            emit_line!(prod, "}}");
+            emit_line!(prod, "point -= {}[i];", weights);
+            prod.block_close();
+            emit_line!(prod, "}}");
+            prod.block_close();
+            emit_line!(prod, "}}");
+        },
+    }
+    
+    if args.self_coverage {
+        let counts = coverage_counts(&container.id());
+        emit_line!(prod, "if (self_coverage_enabled) {{");
+        prod.block_open();
+        emit_line!(prod, "uint64_t candidate = rand() % {};", num_vars);
+        emit_line!(prod, "oneof_selector = candidate;");
+        emit_line!(prod, "for (uint64_t i = 1; i < {}; ++i) {{", num_vars);
+        prod.block_open();
+        emit_line!(prod, "candidate = (candidate + 1) % {};", num_vars);
+        emit_line!(prod, "if ({0}[candidate] < {0}[oneof_selector]) {{", counts);
+        prod.block_open();
+        emit_line!(prod, "oneof_selector = candidate;");
+        prod.block_close();
+        emit_line!(prod, "}}");
+        prod.block_close();
+        emit_line!(prod, "}}");
+        prod.block_close();
+        emit_line!(prod, "}}");
+    }
+    
+    emit_cheapest_fallback(prod, grammar, container, sizes);
+    
+    if let Scheduling::Adaptive = container.options().scheduling() {
+        emit_line!(prod, "{}[oneof_selector] = 1;", adaptive_hits(&container.id()));
+    }
+    
+    if args.self_coverage {
+        emit_line!(prod, "{}[oneof_selector]++;", coverage_counts(&container.id()));
     }
     
     emit_line!(prod, "switch(oneof_selector) {{");
@@ -616,7 +992,7 @@ fn emit_oneof(prod: &mut CodeFormatter, grammar: &Grammar, container: &Container
     for i in 0..container.variables().len() {
         emit_line!(prod, "case {}: {{", i);
         prod.block_open();
-        label_ref |= emit_variable(prod, grammar, &container.variables()[i], container.options());
+        label_ref |= emit_variable(prod, args, grammar, names, &container.variables()[i], container.options());
         emit_line!(prod, "break;");
         prod.block_close();
         emit_line!(prod, "}}");
@@ -634,21 +1010,25 @@ fn emit_oneof(prod: &mut CodeFormatter, grammar: &Grammar, container: &Container
     if label_ref {
         emit_line!(prod, "container_end:");
     }
+    if owns_recursion_counter(container) {
+        emit_line!(prod, "{}--;", recursion_counter(&container.id()));
+    }
+    emit_max_len_assertion(prod, view, container);
     emit_line!(prod, "return original_len - len;");
     
     prod.block_close();
     emit_line!(prod, "}}");
 }
 
-fn emit_struct(prod: &mut CodeFormatter, grammar: &Grammar, container: &Container, view: &SourceView) {
+fn emit_struct(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, names: &FuncNames, container: &Container, view: &SourceView) {
     let mut label_ref = false;
     
     match container.options().depth() {
         Depth::Unlimited => {
-            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len) {{", container_func(&container.id()));
+            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len) {{", container_body_func(args, names, &container.id()));
         },
         Depth::Limited(_) => {
-            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len, uint64_t depth) {{", container_func(&container.id()));
+            emit_line!(prod, "static size_t {}(unsigned char* buf, size_t len, uint64_t depth) {{", container_body_func(args, names, &container.id()));
         },
     }
     
@@ -666,10 +1046,14 @@ fn emit_struct(prod: &mut CodeFormatter, grammar: &Grammar, container: &Containe
     
     emit_line!(prod, "size_t original_len = len;");
     
+    if owns_recursion_counter(container) {
+        emit_line!(prod, "{}++;", recursion_counter(&container.id()));
+    }
+    
     for var in container.variables() {
         emit_line!(prod, "{{");
         prod.block_open();
-        label_ref |= emit_variable(prod, grammar, var, container.options());
+        label_ref |= emit_variable(prod, args, grammar, names, var, container.options());
         prod.block_close();
         emit_line!(prod, "}}");
     }
@@ -678,85 +1062,723 @@ fn emit_struct(prod: &mut CodeFormatter, grammar: &Grammar, container: &Containe
         emit_line!(prod, "container_end:");
     }
     
+    if owns_recursion_counter(container) {
+        emit_line!(prod, "{}--;", recursion_counter(&container.id()));
+    }
+    
+    emit_max_len_assertion(prod, view, container);
     emit_line!(prod, "return original_len - len;");
     
     prod.block_close();
     emit_line!(prod, "}}");
 }
 
-fn emit_containers(prod: &mut CodeFormatter, grammar: &Grammar, view: &SourceView) {
+fn emit_containers(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, names: &FuncNames, view: &SourceView) {
     emit_raw!(prod, "\n// Definition of containers\n");
     
-    for container in grammar.containers() {
+    let sizes = grammar.minimal_sizes();
+    
+    for (idx, container) in grammar.containers().enumerate() {
         match container.typ() {
-            ContainerType::Oneof => emit_oneof(prod, grammar, container),
-            ContainerType::Struct => emit_struct(prod, grammar, container, view),
+            ContainerType::Oneof => emit_oneof(prod, args, grammar, names, container, &sizes, view),
+            ContainerType::Struct => emit_struct(prod, args, grammar, names, container, view),
+        }
+        
+        if has_wrappers(args) {
+            emit_wrapper(prod, args, grammar, names, container, idx);
         }
     }
 }
 
-fn emit_entrypoint(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar) {
+fn emit_profile_helpers(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar) {
+    let num_containers = grammar.containers().count();
+    
+    emit_raw!(
+        prod,
+"
+// Cycle counters of the containers
+#include <stdio.h>
+#if defined(__x86_64__) || defined(__i386__)
+#define PROFILE_CLOCK() __builtin_ia32_rdtsc()
+#elif defined(__aarch64__)
+static inline uint64_t profile_clock() {{
+    uint64_t t;
+    __asm__ volatile (\"mrs %0, cntvct_el0\" : \"=r\" (t));
+    return t;
+}}
+#define PROFILE_CLOCK() profile_clock()
+#else
+#error \"Profiling is not supported on this architecture\"
+#endif
+
+static THREAD_LOCAL uint64_t profile_calls[{0}];
+static THREAD_LOCAL uint64_t profile_cycles[{0}];
+static THREAD_LOCAL uint64_t profile_active[{0}];
+",
+        num_containers,
+    );
+    
+    emit_raw!(prod, "static const uint64_t profile_ids[{}] = {{", num_containers);
+    for (idx, container) in grammar.containers().enumerate() {
+        emit_raw!(prod, "{}{}ULL", if idx > 0 { ", " } else { "" }, container.id());
+    }
+    emit_raw!(prod, "}};\n");
+    
     emit_raw!(
         prod,
 "
-// Entrypoint for the generator
-size_t {}generate(unsigned char* buf, size_t len) {{
-    if (UNLIKELY(!buf || !len)) {{
-        return 0;
+// Writes one line \"<container ID> <calls> <cycles>\" for every container that was called
+// into buf and returns the length of the complete table, which may exceed len
+size_t {1}profile(char* buf, size_t len) {{
+    size_t written = 0;
+    
+    for (size_t i = 0; i < {0}; ++i) {{
+        if (!profile_calls[i]) {{
+            continue;
+        }}
+        
+        int ret = snprintf(
+            (buf && written < len) ? buf + written : NULL,
+            (buf && written < len) ? len - written : 0,
+            \"%llu %llu %llu\\n\",
+            (unsigned long long) profile_ids[i],
+            (unsigned long long) profile_calls[i],
+            (unsigned long long) profile_cycles[i]
+        );
+        
+        if (ret > 0) {{
+            written += (size_t) ret;
+        }}
     }}
     
-    return {}(buf, len{});
+    return written;
 }}
 ",
+        num_containers,
         args.prefix,
-        container_func(grammar.root().unwrap()),
-        match grammar.options().depth() {
-            Depth::Unlimited => "",
-            Depth::Limited(_) => ", 1",
-        },
     );
 }
 
-fn write_source(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, view: &SourceView) {
-    emit_header(prod, args, grammar.options());
-    emit_includes(prod);
-    emit_macros(prod);
-    emit_rng(prod, args);
-    emit_helpers(prod);
-    emit_strings(prod, grammar);
-    emit_numbersets(prod, grammar);
-    emit_declarations(prod, grammar);
-    emit_containers(prod, grammar, view);
-    emit_entrypoint(prod, args, grammar);
-}
-
-fn write_header(prod: &mut CodeFormatter, args: &Args) {
+fn emit_trace_helpers(prod: &mut CodeFormatter, args: &Args) {
     emit_raw!(
         prod,
 "
-#ifndef __{0}GENERATOR_H
-#define __{0}GENERATOR_H
+// Byte ranges that the containers wrote during the last generation
+#include <stdio.h>
+#ifndef TRACE_CAPACITY
+#define TRACE_CAPACITY 4096
+#endif
 
-#include <stddef.h>
+struct trace_record {{
+    uint64_t id;
+    size_t start;
+    size_t end;
+}};
 
-size_t {0}generate(unsigned char* buf, size_t len);
-void {0}seed(size_t initial_seed);
+static THREAD_LOCAL struct trace_record trace_records[TRACE_CAPACITY];
+static THREAD_LOCAL size_t trace_len = 0;
+static THREAD_LOCAL unsigned char* trace_base = NULL;
 
-#endif /* __{0}GENERATOR_H */
-",
-        args.prefix
-    )
-}
+// Writes one line \"<container ID> <start> <end>\" for every container whose output in the last generated
+// input contains the byte at offset, from the outermost to the innermost container, into buf.
+// Returns the length of the complete list, which may exceed len.
+size_t {0}trace(size_t offset, char* buf, size_t len) {{
+    size_t written = 0;
+    size_t records = (trace_len < TRACE_CAPACITY) ? trace_len : TRACE_CAPACITY;
+    
+    for (size_t i = 0; i < records; ++i) {{
+        if (offset < trace_records[i].start || offset >= trace_records[i].end) {{
+            continue;
+        }}
+        
+        int ret = snprintf(
+            (buf && written < len) ? buf + written : NULL,
+            (buf && written < len) ? len - written : 0,
+            \"%llu %llu %llu\\n\",
+            (unsigned long long) trace_records[i].id,
+            (unsigned long long) trace_records[i].start,
+            (unsigned long long) trace_records[i].end
+        );
+        
+        if (ret > 0) {{
+            written += (size_t) ret;
+        }}
+    }}
+    
+    return written;
+}}
 
-fn c_stream(args: &Args) -> CodeFormatter {
-    if args.outfile.as_ref().unwrap().as_str() == "-" {
-        CodeFormatter::stdout()
-    } else {
-        CodeFormatter::file(&args.outfile.as_ref().unwrap())
-    }
-}
+// Streaming of container entries and exits to a file descriptor. Every record is a
+// little-endian 64-bit word (id << 1) | is_exit, every input starts with UINT64_MAX.
+#include <unistd.h>
 
-fn h_stream(args: &Args) -> CodeFormatter {
+static THREAD_LOCAL int trace_fd = -1;
+static THREAD_LOCAL unsigned char trace_stream_buf[4096];
+static THREAD_LOCAL size_t trace_stream_len = 0;
+
+static void trace_stream_flush(void) {{
+    size_t done = 0;
+    
+    while (trace_fd >= 0 && done < trace_stream_len) {{
+        ssize_t ret = write(trace_fd, trace_stream_buf + done, trace_stream_len - done);
+        
+        if (ret <= 0) {{
+            break;
+        }}
+        
+        done += (size_t) ret;
+    }}
+    
+    trace_stream_len = 0;
+}}
+
+static void trace_stream_record(uint64_t word) {{
+    if (LIKELY(trace_fd < 0)) {{
+        return;
+    }}
+    
+    if (trace_stream_len + 8 > sizeof(trace_stream_buf)) {{
+        trace_stream_flush();
+    }}
+    
+    for (int i = 0; i < 8; ++i) {{
+        trace_stream_buf[trace_stream_len++] = (unsigned char) (word >> (8 * i));
+    }}
+}}
+
+// Stream the trace of every following generation to fd, or stop streaming if fd is negative
+void {0}trace_stream(int fd) {{
+    trace_stream_flush();
+    trace_fd = fd;
+}}
+",
+        args.prefix,
+    );
+}
+
+/// Cycles are measured inclusively, i.e. they contain the cycles of all callees.
+/// Recursive calls are not measured again so that no cycle is counted twice.
+/// Containers are traced in the order they are called so that enclosing
+/// containers come before the containers inside them.
+fn emit_wrapper(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, names: &FuncNames, container: &Container, idx: usize) {
+    let (params, call_args) = match grammar.options().depth() {
+        Depth::Unlimited => ("unsigned char* buf, size_t len", "buf, len"),
+        Depth::Limited(_) => ("unsigned char* buf, size_t len, uint64_t depth", "buf, len, depth"),
+    };
+    
+    emit_line!(prod, "static size_t {}({}) {{", container_func(names, &container.id()), params);
+    prod.block_open();
+    
+    if args.trace {
+        emit_line!(prod, "trace_stream_record({}ULL << 1);", container.id());
+        emit_line!(prod, "size_t trace_idx = trace_len++;");
+        emit_line!(prod, "if (trace_idx < TRACE_CAPACITY) {{");
+        prod.block_open();
+        emit_line!(prod, "trace_records[trace_idx].id = {}ULL;", container.id());
+        emit_line!(prod, "trace_records[trace_idx].start = (size_t) (buf - trace_base);");
+        prod.block_close();
+        emit_line!(prod, "}}");
+    }
+    
+    if args.profile {
+        emit_line!(prod, "uint64_t start = PROFILE_CLOCK();");
+        emit_line!(prod, "profile_active[{}]++;", idx);
+    }
+    
+    emit_line!(prod, "size_t ret = {}({});", container_body_func(args, names, &container.id()), call_args);
+    
+    if args.profile {
+        emit_line!(prod, "if (--profile_active[{}] == 0) {{", idx);
+        prod.block_open();
+        emit_line!(prod, "profile_cycles[{}] += PROFILE_CLOCK() - start;", idx);
+        prod.block_close();
+        emit_line!(prod, "}}");
+        emit_line!(prod, "profile_calls[{}]++;", idx);
+    }
+    
+    if args.trace {
+        emit_line!(prod, "if (trace_idx < TRACE_CAPACITY) {{");
+        prod.block_open();
+        emit_line!(prod, "trace_records[trace_idx].end = trace_records[trace_idx].start + ret;");
+        prod.block_close();
+        emit_line!(prod, "}}");
+        emit_line!(prod, "trace_stream_record(({}ULL << 1) | 1);", container.id());
+    }
+    
+    emit_line!(prod, "return ret;");
+    prod.block_close();
+    emit_line!(prod, "}}");
+}
+
+fn emit_adaptive_helpers(prod: &mut CodeFormatter) {
+    emit_raw!(
+        prod,
+"
+// Helpers for oneofs with adaptive scheduling
+static double random_unit() {{
+    return (double) (rand() >> 11) / 9007199254740992.0;
+}}
+
+static void update_weights(double* weights, unsigned char* hits, size_t len, double score) {{
+    double weight_sum = 0.0;
+    
+    for (size_t i = 0; i < len; ++i) {{
+        if (hits[i]) {{
+            weights[i] *= 1.0 + ADAPTIVE_LEARNING_RATE * score;
+        }}
+        
+        weight_sum += weights[i];
+    }}
+    
+    // Normalize such that the weights don't overflow
+    for (size_t i = 0; i < len; ++i) {{
+        weights[i] = weights[i] * (double) len / weight_sum;
+    }}
+}}
+"
+    );
+}
+
+fn emit_reward(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar) {
+    let oneofs = adaptive_oneofs(grammar);
+    
+    if !oneofs.is_empty() {
+        emit_raw!(prod, "\nstatic void reset_adaptive_hits() {{\n");
+        prod.block_open();
+        for container in &oneofs {
+            emit_line!(prod, "__builtin_memset({0}, 0, sizeof({0}));", adaptive_hits(&container.id()));
+        }
+        prod.block_close();
+        emit_line!(prod, "}}");
+    }
+    
+    emit_raw!(prod, "\n// Reward the choices of adaptive oneofs made during the last generation with a score in [0, 1]\n");
+    emit_line!(prod, "void {}reward(double score) {{", args.prefix);
+    prod.block_open();
+    
+    if oneofs.is_empty() {
+        emit_line!(prod, "(void) score;");
+    } else {
+        emit_line!(prod, "if (score < 0.0) {{");
+        prod.block_open();
+        emit_line!(prod, "score = 0.0;");
+        prod.block_close();
+        emit_line!(prod, "}} else if (score > 1.0) {{");
+        prod.block_open();
+        emit_line!(prod, "score = 1.0;");
+        prod.block_close();
+        emit_line!(prod, "}}");
+        
+        for container in &oneofs {
+            emit_line!(prod, "update_weights({}, {}, {}, score);", adaptive_weights(&container.id()), adaptive_hits(&container.id()), container.variables().len());
+        }
+    }
+    
+    prod.block_close();
+    emit_line!(prod, "}}");
+}
+
+fn emit_dedup_helpers(prod: &mut CodeFormatter, window: u64) {
+    emit_raw!(
+        prod,
+"
+// Hashes of the last generated inputs to detect duplicates
+#ifndef DEDUP_RETRIES
+#define DEDUP_RETRIES 3
+#endif
+
+static THREAD_LOCAL uint64_t dedup_hashes[{0}];
+static THREAD_LOCAL size_t dedup_len = 0;
+static THREAD_LOCAL size_t dedup_next = 0;
+
+// Returns 1 if the input is identical to one of the last {0} inputs.
+// Otherwise the input is remembered and 0 is returned.
+static int dedup_check(unsigned char* buf, size_t len) {{
+    uint64_t hash = 0xcbf29ce484222325ULL;
+    
+    for (size_t i = 0; i < len; ++i) {{
+        hash = (hash ^ buf[i]) * 0x100000001b3ULL;
+    }}
+    
+    for (size_t i = 0; i < dedup_len; ++i) {{
+        if (dedup_hashes[i] == hash) {{
+            return 1;
+        }}
+    }}
+    
+    dedup_hashes[dedup_next] = hash;
+    dedup_next = (dedup_next + 1) % {0};
+    
+    if (dedup_len < {0}) {{
+        dedup_len++;
+    }}
+    
+    return 0;
+}}
+",
+        window,
+    );
+}
+
+/// State that has to be reset before every generation
+fn emit_generation_reset(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar) {
+    if !adaptive_oneofs(grammar).is_empty() {
+        emit_line!(prod, "reset_adaptive_hits();");
+    }
+    
+    if args.trace {
+        emit_line!(prod, "trace_base = buf;");
+        emit_line!(prod, "trace_len = 0;");
+        emit_line!(prod, "trace_stream_record(UINT64_MAX);");
+    }
+}
+
+/// Streamed traces are written out after every generation
+fn emit_generation_end(prod: &mut CodeFormatter, args: &Args) {
+    if args.trace {
+        emit_line!(prod, "trace_stream_flush();");
+    }
+}
+
+/// Names of the functions that start generation at one of several entrypoints.
+/// Empty if the grammar has only one entrypoint.
+fn entrypoint_funcs(args: &Args, grammar: &Grammar, view: &SourceView) -> Vec<(String, ContainerId)> {
+    let entrypoints = grammar.entrypoints();
+    
+    if entrypoints.len() < 2 {
+        return Vec::new();
+    }
+    
+    entrypoints.into_iter().map(|id| {
+        let name = grammar.container(id).unwrap().name().unwrap();
+        (format!("{}generate_{}", args.prefix, sanitize_name(view.range(name))), id)
+    }).collect()
+}
+
+fn emit_entrypoint(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, names: &FuncNames, view: &SourceView) {
+    let depth_arg = match grammar.options().depth() {
+        Depth::Unlimited => "",
+        Depth::Limited(_) => ", 1",
+    };
+    
+    emit_raw!(prod, "\n// Entrypoint for the generator\n");
+    emit_line!(prod, "size_t {}generate(unsigned char* buf, size_t len) {{", args.prefix);
+    prod.block_open();
+    emit_line!(prod, "if (UNLIKELY(!buf || !len)) {{");
+    prod.block_open();
+    emit_line!(prod, "return 0;");
+    prod.block_close();
+    emit_line!(prod, "}}");
+    
+    if args.dedup.is_some() {
+        // Duplicates are generated again with a different random path
+        emit_line!(prod, "size_t ret = 0;");
+        emit_line!(prod, "for (size_t attempt = 0; attempt <= DEDUP_RETRIES; ++attempt) {{");
+        prod.block_open();
+        emit_generation_reset(prod, args, grammar);
+        emit_line!(prod, "ret = {}(buf, len{});", container_func(names, grammar.entrypoint().unwrap()), depth_arg);
+        emit_line!(prod, "if (!dedup_check(buf, ret)) {{");
+        prod.block_open();
+        emit_line!(prod, "break;");
+        prod.block_close();
+        emit_line!(prod, "}}");
+        prod.block_close();
+        emit_line!(prod, "}}");
+        emit_generation_end(prod, args);
+        emit_line!(prod, "return ret;");
+    } else if args.trace {
+        emit_generation_reset(prod, args, grammar);
+        emit_line!(prod, "size_t ret = {}(buf, len{});", container_func(names, grammar.entrypoint().unwrap()), depth_arg);
+        emit_generation_end(prod, args);
+        emit_line!(prod, "return ret;");
+    } else {
+        emit_generation_reset(prod, args, grammar);
+        emit_line!(prod, "return {}(buf, len{});", container_func(names, grammar.entrypoint().unwrap()), depth_arg);
+    }
+    
+    prod.block_close();
+    emit_line!(prod, "}}");
+    
+    emit_raw!(prod, "\n// Generate a fragment that starts at the named struct with the given ID instead of the entrypoint\n");
+    emit_line!(prod, "size_t {}generate_from(size_t struct_id, unsigned char* buf, size_t len) {{", args.prefix);
+    prod.block_open();
+    emit_line!(prod, "if (UNLIKELY(!buf || !len)) {{");
+    prod.block_open();
+    emit_line!(prod, "return 0;");
+    prod.block_close();
+    emit_line!(prod, "}}");
+    
+    emit_generation_reset(prod, args, grammar);
+    emit_line!(prod, "size_t ret = 0;");
+    emit_line!(prod, "switch (struct_id) {{");
+    prod.block_open();
+    
+    for container in grammar.containers() {
+        if !matches!(container.name(), Some(name) if !name.is_empty()) {
+            continue;
+        }
+        
+        emit_line!(
+            prod,
+            "case {}: ret = {}(buf, len{}); break;",
+            container.id(),
+            container_func(names, &container.id()),
+            depth_arg,
+        );
+    }
+    
+    emit_line!(prod, "default: break;");
+    prod.block_close();
+    emit_line!(prod, "}}");
+    emit_generation_end(prod, args);
+    emit_line!(prod, "return ret;");
+    prod.block_close();
+    emit_line!(prod, "}}");
+    
+    let funcs = entrypoint_funcs(args, grammar, view);
+    
+    if !funcs.is_empty() {
+        emit_raw!(prod, "\n// One function per entrypoint given on the command line\n");
+    }
+    
+    for (func, id) in funcs {
+        emit_line!(prod, "size_t {}(unsigned char* buf, size_t len) {{", func);
+        prod.block_open();
+        emit_line!(prod, "return {}generate_from({}, buf, len);", args.prefix, id);
+        prod.block_close();
+        emit_line!(prod, "}}");
+    }
+}
+
+fn min_input_var(idx: usize) -> String {
+    format!("min_input_{}", idx)
+}
+
+fn emit_min_input(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar) {
+    let min_input = MinimalInput::from_grammar(grammar);
+    
+    if min_input.fragments().iter().any(|f| matches!(f, Fragment::Literal(bts) if !bts.is_empty())) {
+        emit_raw!(prod, "\n// Fragments of the smallest input\n");
+    }
+    
+    for (idx, fragment) in min_input.fragments().iter().enumerate() {
+        if let Fragment::Literal(bts) = fragment {
+            if bts.is_empty() {
+                continue;
+            }
+            
+            emit_raw!(prod, "static const unsigned char {}[{}] = {{", min_input_var(idx), bts.len());
+            
+            for b in &bts[..bts.len() - 1] {
+                emit_raw!(prod, "{:#02x}, ", b);
+            }
+            emit_raw!(prod, "{:#02x}", bts[bts.len() - 1]);
+            
+            emit_raw!(prod, "}};\n");
+        }
+    }
+    
+    emit_raw!(prod, "\n// Writes the smallest input into buf, returns 0 if buf is too small\n");
+    emit_line!(prod, "size_t {}min_input(unsigned char* buf, size_t len) {{", args.prefix);
+    prod.block_open();
+    emit_line!(prod, "unsigned char* start = buf;");
+    
+    if min_input.fragments().is_empty() {
+        emit_line!(prod, "(void) len;");
+    }
+    
+    emit_line!(prod, "if (UNLIKELY(!buf)) {{");
+    prod.block_open();
+    emit_line!(prod, "return 0;");
+    prod.block_close();
+    emit_line!(prod, "}}");
+    
+    for (idx, fragment) in min_input.fragments().iter().enumerate() {
+        match fragment {
+            Fragment::Literal(bts) => {
+                if bts.is_empty() {
+                    continue;
+                }
+                
+                let var_name = min_input_var(idx);
+                emit_line!(prod, "if (UNLIKELY(len < sizeof({}))) {{", var_name);
+                prod.block_open();
+                emit_line!(prod, "return 0;");
+                prod.block_close();
+                emit_line!(prod, "}}");
+                emit_line!(prod, "__builtin_memcpy(buf, {0}, sizeof({0}));", var_name);
+                emit_line!(prod, "buf += sizeof({0}); len -= sizeof({0});", var_name);
+            },
+            Fragment::Native(width, value) => {
+                emit_line!(prod, "if (UNLIKELY(len < {})) {{", width);
+                prod.block_open();
+                emit_line!(prod, "return 0;");
+                prod.block_close();
+                emit_line!(prod, "}}");
+                
+                match width {
+                    1 => emit_line!(prod, "*buf = {}U;", value),
+                    2 => emit_line!(prod, "*(uint16_t*)buf = {}U;", value),
+                    4 => emit_line!(prod, "*(uint32_t*)buf = {}UL;", value),
+                    _ => emit_line!(prod, "*(uint64_t*)buf = {}ULL;", value),
+                }
+                
+                emit_line!(prod, "buf += {0}; len -= {0};", width);
+            },
+            Fragment::Extern(name) => {
+                emit_line!(prod, "{{");
+                prod.block_open();
+                emit_line!(prod, "size_t extern_len = {}(buf, len);", extern_func(args, name));
+                emit_line!(prod, "if (extern_len > len) extern_len = len;");
+                emit_line!(prod, "buf += extern_len; len -= extern_len;");
+                prod.block_close();
+                emit_line!(prod, "}}");
+            },
+        }
+    }
+    
+    emit_line!(prod, "return (size_t) (buf - start);");
+    prod.block_close();
+    emit_line!(prod, "}}");
+}
+
+fn write_source(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, view: &SourceView) {
+    emit_header(prod, args, grammar.options());
+    emit_includes(prod);
+    emit_macros(prod);
+    emit_rng(prod, args);
+    emit_helpers(prod);
+    emit_strings(prod, grammar);
+    emit_numbersets(prod, grammar);
+    let names = container_funcs(args, grammar, view);
+    
+    emit_declarations(prod, args, grammar, &names);
+    emit_extern_declarations(prod, args, grammar);
+    
+    if !adaptive_oneofs(grammar).is_empty() {
+        emit_adaptive_helpers(prod);
+    }
+    
+    if args.profile {
+        emit_profile_helpers(prod, args, grammar);
+    }
+    
+    if args.trace {
+        emit_trace_helpers(prod, args);
+    }
+    
+    if args.self_coverage {
+        emit_self_coverage(prod, args);
+    }
+    
+    if let Some(window) = args.dedup {
+        emit_dedup_helpers(prod, window);
+    }
+    
+    if grammar.containers().any(|container| matches!(container.options().max_len(), Some((owner, _)) if *owner == container.id())) {
+        emit_raw!(prod, "\n// Assertions of max_len options print to stderr\n#ifndef NDEBUG\n#include <stdio.h>\n#endif\n");
+    }
+    
+    emit_containers(prod, args, grammar, &names, view);
+    emit_reward(prod, args, grammar);
+    emit_entrypoint(prod, args, grammar, &names, view);
+    emit_min_input(prod, args, grammar);
+}
+
+/// Turn a struct name into something that can be used in a C identifier
+fn sanitize_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn emit_struct_ids(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, view: &SourceView) {
+    let mut seen = Vec::<String>::new();
+    
+    emit_raw!(prod, "\n// IDs of all named structs\n");
+    
+    for container in grammar.containers() {
+        if let Some(name) = container.name() {
+            if name.is_empty() {
+                continue;
+            }
+            
+            let mut constant = format!("{}STRUCT_{}", args.prefix.to_uppercase(), sanitize_name(view.range(name)));
+            
+            // Non-ASCII names may collide after sanitization
+            if seen.contains(&constant) {
+                constant = format!("{}_{}", constant, container.id());
+            }
+            
+            emit_raw!(prod, "#define {} {}\n", constant, container.id());
+            seen.push(constant);
+        }
+    }
+}
+
+fn write_header(prod: &mut CodeFormatter, args: &Args, grammar: &Grammar, view: &SourceView) {
+    emit_raw!(
+        prod,
+"
+#ifndef __{0}GENERATOR_H
+#define __{0}GENERATOR_H
+
+#include <stddef.h>
+",
+        args.prefix
+    );
+    
+    emit_struct_ids(prod, args, grammar, view);
+    
+    emit_raw!(
+        prod,
+"
+size_t {0}generate(unsigned char* buf, size_t len);
+size_t {0}generate_from(size_t struct_id, unsigned char* buf, size_t len);
+void {0}seed(size_t initial_seed);
+void {0}reward(double score);
+size_t {0}min_input(unsigned char* buf, size_t len);
+{1}{2}{3}{4}{5}
+#endif /* __{0}GENERATOR_H */
+",
+        args.prefix,
+        if args.profile {
+            format!("size_t {}profile(char* buf, size_t len);\n", args.prefix)
+        } else {
+            String::new()
+        },
+        if args.self_coverage {
+            format!("void {}self_coverage(int enabled);\n", args.prefix)
+        } else {
+            String::new()
+        },
+        if args.trace {
+            format!("size_t {0}trace(size_t offset, char* buf, size_t len);\nvoid {0}trace_stream(int fd);\n", args.prefix)
+        } else {
+            String::new()
+        },
+        entrypoint_funcs(args, grammar, view).into_iter().map(|(func, _)| format!("size_t {}(unsigned char* buf, size_t len);\n", func)).collect::<String>(),
+        if grammar.extern_names().is_empty() {
+            String::new()
+        } else {
+            let mut decls = String::from("\n// External functions that must be supplied by the user\n");
+            
+            for name in grammar.extern_names() {
+                decls.push_str(&format!("size_t {}(unsigned char* buf, size_t len);\n", extern_func(args, name)));
+            }
+            
+            decls
+        },
+    )
+}
+
+fn c_stream(args: &Args) -> CodeFormatter {
+    if args.outfile.as_ref().unwrap().as_str() == "-" {
+        CodeFormatter::stdout()
+    } else {
+        CodeFormatter::file(&args.outfile.as_ref().unwrap())
+    }
+}
+
+fn h_stream(args: &Args) -> CodeFormatter {
     if args.outfile.as_ref().unwrap().as_str() == "-" {
         CodeFormatter::stdout()
     } else {
@@ -764,9 +1786,92 @@ fn h_stream(args: &Args) -> CodeFormatter {
     }
 }
 
+/// Names of all functions that the generator exports
+fn exported_names(args: &Args, grammar: &Grammar, view: &SourceView) -> Vec<String> {
+    let mut ret: Vec<String> = ["generate", "generate_from", "seed", "reward", "min_input"].iter().map(|name| format!("{}{}", args.prefix, name)).collect();
+    
+    if args.profile {
+        ret.push(format!("{}profile", args.prefix));
+    }
+    
+    if args.self_coverage {
+        ret.push(format!("{}self_coverage", args.prefix));
+    }
+    
+    if args.trace {
+        ret.push(format!("{}trace", args.prefix));
+        ret.push(format!("{}trace_stream", args.prefix));
+    }
+    
+    for container in grammar.containers() {
+        for var in container.variables() {
+            if let VariableType::ExternRef(name) = var.typ() {
+                let func = extern_func(args, name);
+                
+                if !ret.contains(&func) {
+                    ret.push(func);
+                }
+            }
+        }
+    }
+    
+    ret.extend(entrypoint_funcs(args, grammar, view).into_iter().map(|(func, _)| func));
+    ret
+}
+
+/// Make sure that the prefix results in valid C identifiers that don't clash with each other.
+/// A prefix always ends with '_' and the names without a prefix are neither C keywords nor
+/// functions of the C library, so no prefix can cause a clash with them.
+fn check_prefix(args: &Args, grammar: &Grammar, view: &SourceView) -> Result<(), String> {
+    let prefix = &args.prefix;
+    let suggestion = match sanitize_name(prefix.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit())) {
+        name if name.trim_matches('_').is_empty() => "gen_".to_string(),
+        name => name,
+    };
+    
+    if prefix.chars().any(|c| !c.is_ascii_alphanumeric() && c != '_') || prefix.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("The prefix '{}' is not a valid C identifier. Try '{}' instead", prefix, suggestion));
+    }
+    
+    if prefix.starts_with('_') {
+        return Err(format!("Identifiers starting with '_' are reserved in C so the prefix '{}' cannot be used. Try '{}' instead", prefix, suggestion));
+    }
+    
+    let names = exported_names(args, grammar, view);
+    
+    for (i, name) in names.iter().enumerate() {
+        // The functions of several entrypoints are named after their structs
+        if names[..i].contains(name) {
+            return Err(format!("The exported function '{}' would be defined twice. Rename the struct that is passed to --entrypoint", name));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Generator in C with a header that declares its API
+pub struct CBackend;
+impl super::Backend for CBackend {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+    
+    fn outputs(&self, outfile: &str) -> Vec<String> {
+        vec![outfile.to_string(), format!("{}.h", outfile)]
+    }
+    
+    fn check(&self, args: &Args, grammar: &Grammar, view: &SourceView) -> Result<(), String> {
+        check_prefix(args, grammar, view)
+    }
+    
+    fn render(&self, args: &Args, grammar: &Grammar, view: &SourceView) {
+        compile_grammar(args, grammar, view);
+    }
+}
+
 pub fn compile_grammar(args: &Args, grammar: &Grammar, view: &SourceView) {
     let mut outfile = h_stream(args);
-    write_header(&mut outfile, args);
+    write_header(&mut outfile, args, grammar, view);
     let mut outfile = c_stream(args);
     write_source(&mut outfile, args, grammar, view);
 }
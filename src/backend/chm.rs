@@ -0,0 +1,272 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::ops::Range;
+use crate::{
+    frontend::{
+        SourceView,
+        keywords,
+    },
+    grammar::{
+        Grammar, Container, ContainerType, ContainerOptions, HasOptions,
+        Variable, VariableType, IntegerValue, BytearrayValue,
+        NumbersetId, NumbersetType, StringId,
+        Endianness, Scheduling, Depth,
+    },
+};
+
+/// Indentation of one block level
+const INDENT: &str = "    ";
+
+fn endianness_value(endianness: &Endianness) -> &'static str {
+    match endianness {
+        Endianness::Little => "little",
+        Endianness::Big => "big",
+        Endianness::Native => "native",
+    }
+}
+
+fn scheduling_value(scheduling: &Scheduling) -> &'static str {
+    match scheduling {
+        Scheduling::RoundRobin => "round-robin",
+        Scheduling::Random => "random",
+        Scheduling::Adaptive => "adaptive",
+    }
+}
+
+fn format_ranges<T: Display + PartialEq>(ranges: &[Range<T>]) -> String {
+    let mut ret = Vec::new();
+    
+    for range in ranges {
+        if range.start == range.end {
+            ret.push(format!("{}", range.start));
+        } else {
+            ret.push(format!("{}{}{}", range.start, keywords::RANGE_OP, range.end));
+        }
+    }
+    
+    ret.join(", ")
+}
+
+fn format_numberset(grammar: &Grammar, id: NumbersetId) -> String {
+    match grammar.numbersets().get(&id).unwrap() {
+        NumbersetType::U8(v) => format_ranges(v),
+        NumbersetType::I8(v) => format_ranges(v),
+        NumbersetType::U16(v) => format_ranges(v),
+        NumbersetType::I16(v) => format_ranges(v),
+        NumbersetType::U32(v) => format_ranges(v),
+        NumbersetType::I32(v) => format_ranges(v),
+        NumbersetType::U64(v) => format_ranges(v),
+        NumbersetType::I64(v) => format_ranges(v),
+    }
+}
+
+/// Literal for a `string` variable or a separator
+fn format_string(grammar: &Grammar, id: StringId) -> Vec<u8> {
+    let mut ret = keywords::STRING_DELIM.as_bytes().to_vec();
+    
+    for b in grammar.strings().get(&id).unwrap() {
+        match *b {
+            b'\\' => ret.extend_from_slice(b"\\\\"),
+            b'"' => ret.extend_from_slice(b"\\\""),
+            b'$' => ret.extend_from_slice(b"\\$"),
+            b'\r' => ret.extend_from_slice(b"\\r"),
+            b'\n' => ret.extend_from_slice(b"\\n"),
+            b'\t' => ret.extend_from_slice(b"\\t"),
+            0 => ret.extend_from_slice(b"\\0"),
+            7 => ret.extend_from_slice(b"\\a"),
+            8 => ret.extend_from_slice(b"\\b"),
+            11 => ret.extend_from_slice(b"\\v"),
+            12 => ret.extend_from_slice(b"\\f"),
+            b => ret.push(b),
+        }
+    }
+    
+    ret.extend_from_slice(keywords::STRING_DELIM.as_bytes());
+    ret
+}
+
+/// Literal for a `bytes` variable
+fn format_hex_string(grammar: &Grammar, id: StringId) -> Vec<u8> {
+    let hex: Vec<String> = grammar.strings().get(&id).unwrap().iter().map(|b| format!("{:02X}", b)).collect();
+    format!("{}{}{}{}", keywords::HEX_STRING_PREFIX, keywords::STRING_DELIM, hex.join(" "), keywords::STRING_DELIM).into_bytes()
+}
+
+fn is_named(container: &Container) -> bool {
+    matches!(container.name(), Some(name) if !name.is_empty())
+}
+
+/// Options of a container that differ from the options of the block it is defined in
+fn write_options<W: Write>(stream: &mut W, indent: &str, container: &Container, outer: &ContainerOptions) -> std::io::Result<()> {
+    let options = container.options();
+    
+    if endianness_value(options.endianness()) != endianness_value(outer.endianness()) {
+        writeln!(stream, "{}{} {} {} {}{}", indent, keywords::OPTION, keywords::OPTION_ENDIANNESS, keywords::ASSIGNMENT, endianness_value(options.endianness()), keywords::TERMINATE_STATEMENT)?;
+    }
+    
+    if scheduling_value(options.scheduling()) != scheduling_value(outer.scheduling()) {
+        writeln!(stream, "{}{} {} {} {}{}", indent, keywords::OPTION, keywords::OPTION_SCHEDULING, keywords::ASSIGNMENT, scheduling_value(options.scheduling()), keywords::TERMINATE_STATEMENT)?;
+    }
+    
+    if let Some((owner, limit)) = options.recursion() {
+        if *owner == container.id() {
+            writeln!(stream, "{}{} {} {} {}{}", indent, keywords::OPTION, keywords::OPTION_RECURSION, keywords::ASSIGNMENT, limit, keywords::TERMINATE_STATEMENT)?;
+        }
+    }
+    
+    if let Some((owner, limit)) = options.max_len() {
+        if *owner == container.id() {
+            writeln!(stream, "{}{} {} {} {}{}", indent, keywords::OPTION, keywords::OPTION_MAX_LEN, keywords::ASSIGNMENT, limit, keywords::TERMINATE_STATEMENT)?;
+        }
+    }
+    
+    Ok(())
+}
+
+fn write_block<W: Write>(stream: &mut W, view: &SourceView, grammar: &Grammar, container: &Container, outer: &ContainerOptions, depth: usize) -> std::io::Result<()> {
+    let indent = INDENT.repeat(depth);
+    
+    writeln!(stream, "{}", keywords::BLOCK_OPEN)?;
+    write_options(stream, &indent, container, outer)?;
+    
+    for var in container.variables() {
+        write_variable(stream, view, grammar, var, container.options(), depth)?;
+    }
+    
+    write!(stream, "{}{}", INDENT.repeat(depth - 1), keywords::BLOCK_CLOSE)
+}
+
+fn integer_type(typ: &VariableType) -> Option<(&'static str, &IntegerValue)> {
+    match typ {
+        VariableType::U8(value) => Some((keywords::TYPE_U8, value)),
+        VariableType::I8(value) => Some((keywords::TYPE_I8, value)),
+        VariableType::U16(value) => Some((keywords::TYPE_U16, value)),
+        VariableType::I16(value) => Some((keywords::TYPE_I16, value)),
+        VariableType::U32(value) => Some((keywords::TYPE_U32, value)),
+        VariableType::I32(value) => Some((keywords::TYPE_I32, value)),
+        VariableType::U64(value) => Some((keywords::TYPE_U64, value)),
+        VariableType::I64(value) => Some((keywords::TYPE_I64, value)),
+        _ => None,
+    }
+}
+
+/// Variable names are not part of the grammar so all variables are called `_`
+fn write_variable<W: Write>(stream: &mut W, view: &SourceView, grammar: &Grammar, var: &Variable, outer: &ContainerOptions, depth: usize) -> std::io::Result<()> {
+    write!(stream, "{}", INDENT.repeat(depth))?;
+    
+    if var.options().optional() {
+        write!(stream, "{} ", keywords::VAROPT_OPTIONAL)?;
+    }
+    
+    if let Some(id) = var.options().repeats() {
+        write!(stream, "{} {} ", keywords::VAROPT_REPEATS, format_numberset(grammar, *id))?;
+    }
+    
+    if let Some(id) = var.options().separator() {
+        write!(stream, "{} ", keywords::VAROPT_SEPARATED)?;
+        stream.write_all(&format_string(grammar, *id))?;
+        write!(stream, " ")?;
+    }
+    
+    write!(stream, "_{} ", keywords::VAR_TYPE_SEP)?;
+    
+    match var.typ() {
+        VariableType::Epsilon => write!(stream, "{}", keywords::TYPE_EPSILON)?,
+        VariableType::String(value) |
+        VariableType::Bytes(value) => {
+            let is_binary = matches!(var.typ(), VariableType::Bytes(_));
+            write!(stream, "{} {} ", if is_binary { keywords::TYPE_BYTES } else { keywords::TYPE_STRING }, keywords::ASSIGNMENT)?;
+            
+            match value {
+                BytearrayValue::Any(id) => write!(stream, "{}", format_numberset(grammar, *id))?,
+                BytearrayValue::Literal(id) if is_binary => stream.write_all(&format_hex_string(grammar, *id))?,
+                BytearrayValue::Literal(id) => stream.write_all(&format_string(grammar, *id))?,
+            }
+        },
+        VariableType::ContainerRef(id) |
+        VariableType::Oneof(id) => {
+            let container = grammar.container(*id).unwrap();
+            
+            if is_named(container) {
+                write!(stream, "{}", view.range(container.name().unwrap()))?;
+            } else {
+                match container.typ() {
+                    ContainerType::Struct => write!(stream, "{} ", keywords::CONTAINER)?,
+                    ContainerType::Oneof => write!(stream, "{} ", keywords::TYPE_ONEOF)?,
+                }
+                
+                write_block(stream, view, grammar, container, outer, depth + 1)?;
+            }
+        },
+        VariableType::ExternRef(name) => write!(stream, "{}", name)?,
+        VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
+        typ => {
+            let (name, value) = integer_type(typ).unwrap();
+            write!(stream, "{}", name)?;
+            
+            if let Some(endianness) = var.options().endianness() {
+                let suffix = match endianness {
+                    Endianness::Little => keywords::ENDIANNESS_LITTLE,
+                    Endianness::Big => keywords::ENDIANNESS_BIG,
+                    Endianness::Native => keywords::ENDIANNESS_NATIVE,
+                };
+                write!(stream, "{}{}", keywords::VAR_TYPE_SEP, suffix)?;
+            }
+            
+            if let IntegerValue::FromSet(id) = value {
+                write!(stream, " {} {}", keywords::ASSIGNMENT, format_numberset(grammar, *id))?;
+            }
+        },
+    }
+    
+    writeln!(stream, "{}", keywords::TERMINATE_STATEMENT)
+}
+
+/// Write the global options and the entrypoint of the grammar
+pub fn write_preamble<W: Write>(stream: &mut W, view: &SourceView, grammar: &Grammar) -> std::io::Result<()> {
+    let options = grammar.options();
+    
+    writeln!(stream, "{} {} {} {}{}", keywords::OPTION, keywords::OPTION_ENDIANNESS, keywords::ASSIGNMENT, endianness_value(options.endianness()), keywords::TERMINATE_STATEMENT)?;
+    writeln!(stream, "{} {} {} {}{}", keywords::OPTION, keywords::OPTION_SCHEDULING, keywords::ASSIGNMENT, scheduling_value(options.scheduling()), keywords::TERMINATE_STATEMENT)?;
+    
+    match options.depth() {
+        Depth::Unlimited => writeln!(stream, "{} {} {} {}{}", keywords::OPTION, keywords::OPTION_DEPTH, keywords::ASSIGNMENT, keywords::DEPTH_UNLIMITED, keywords::TERMINATE_STATEMENT)?,
+        Depth::Limited(limit) => writeln!(stream, "{} {} {} {}{}", keywords::OPTION, keywords::OPTION_DEPTH, keywords::ASSIGNMENT, limit, keywords::TERMINATE_STATEMENT)?,
+    }
+    
+    let entrypoint = grammar.container(*grammar.entrypoint().unwrap()).unwrap();
+    let entrypoint_name = view.range(entrypoint.name().unwrap());
+    
+    if entrypoint_name != keywords::ROOT_CONTAINER {
+        writeln!(stream, "{} {}{}", keywords::ENTRYPOINT, entrypoint_name, keywords::TERMINATE_STATEMENT)?;
+    }
+    
+    Ok(())
+}
+
+/// Write a named struct with all anonymous structs and oneofs it contains
+pub fn write_struct<W: Write>(stream: &mut W, view: &SourceView, grammar: &Grammar, container: &Container) -> std::io::Result<()> {
+    write!(stream, "{} {} ", keywords::CONTAINER, view.range(container.name().unwrap()))?;
+    write_block(stream, view, grammar, container, grammar.options(), 1)?;
+    writeln!(stream)
+}
+
+/// Named structs that are reachable from the entrypoint, in the order of their definition
+pub fn named_structs(grammar: &Grammar) -> Vec<&Container> {
+    let reachable = grammar.reachable_containers();
+    grammar.containers().filter(|container| is_named(container) && reachable.contains(&container.id())).collect()
+}
+
+/// Write the grammar as it is after parsing and all transformations in the syntax of .chm files.
+/// Only the structs that are reachable from the entrypoint are written, anonymous structs and oneofs
+/// are written inline at every place they are referenced.
+pub fn write_grammar<W: Write>(stream: &mut W, view: &SourceView, grammar: &Grammar, grammar_name: &str) -> std::io::Result<()> {
+    writeln!(stream, "// Normalized version of {}", grammar_name)?;
+    write_preamble(stream, view, grammar)?;
+    
+    for container in named_structs(grammar) {
+        writeln!(stream)?;
+        write_struct(stream, view, grammar, container)?;
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+use crate::grammar::{
+    Grammar, HasOptions,
+    VariableType, IntegerValue, BytearrayValue,
+    NumbersetId, NumbersetType, Endianness,
+};
+
+/// Start and end of every range of a numberset as bit patterns
+fn range_bounds(grammar: &Grammar, id: NumbersetId) -> Vec<u64> {
+    fn convert<T: Copy + Into<i128>>(ranges: &[std::ops::Range<T>]) -> Vec<u64> {
+        ranges.iter().flat_map(|range| [range.start.into() as u64, range.end.into() as u64]).collect()
+    }
+    
+    match grammar.numbersets().get(&id).unwrap() {
+        NumbersetType::U8(ranges) => convert(ranges),
+        NumbersetType::I8(ranges) => convert(ranges),
+        NumbersetType::U16(ranges) => convert(ranges),
+        NumbersetType::I16(ranges) => convert(ranges),
+        NumbersetType::U32(ranges) => convert(ranges),
+        NumbersetType::I32(ranges) => convert(ranges),
+        NumbersetType::U64(ranges) => convert(ranges),
+        NumbersetType::I64(ranges) => convert(ranges),
+    }
+}
+
+fn encode(value: u64, width: usize, endianness: &Endianness) -> Vec<u8> {
+    let big = match endianness {
+        Endianness::Little => false,
+        Endianness::Big => true,
+        Endianness::Native => cfg!(target_endian = "big"),
+    };
+    
+    if big {
+        value.to_be_bytes()[8 - width..].to_vec()
+    } else {
+        value.to_le_bytes()[..width].to_vec()
+    }
+}
+
+/// Collect the tokens of a dictionary: all string and bytes literals and separators and the
+/// bounds of the ranges of multi-byte integers in their byte order. Single bytes are left out
+/// because fuzzers try them anyway.
+pub fn dictionary_tokens(grammar: &Grammar) -> BTreeSet<Vec<u8>> {
+    let mut ret = BTreeSet::new();
+    
+    for id in grammar.reachable_containers() {
+        let container = grammar.container(id).unwrap();
+        
+        for var in container.variables() {
+            if let Some(id) = var.options().separator() {
+                ret.insert(grammar.strings().get(id).unwrap().clone());
+            }
+            
+            let width = match var.typ() {
+                VariableType::String(BytearrayValue::Literal(id)) |
+                VariableType::Bytes(BytearrayValue::Literal(id)) => {
+                    ret.insert(grammar.strings().get(id).unwrap().clone());
+                    continue;
+                },
+                VariableType::U16(_) | VariableType::I16(_) => 2,
+                VariableType::U32(_) | VariableType::I32(_) => 4,
+                VariableType::U64(_) | VariableType::I64(_) => 8,
+                _ => continue,
+            };
+            
+            let id = match var.typ() {
+                VariableType::U16(IntegerValue::FromSet(id)) |
+                VariableType::I16(IntegerValue::FromSet(id)) |
+                VariableType::U32(IntegerValue::FromSet(id)) |
+                VariableType::I32(IntegerValue::FromSet(id)) |
+                VariableType::U64(IntegerValue::FromSet(id)) |
+                VariableType::I64(IntegerValue::FromSet(id)) => id,
+                _ => continue,
+            };
+            let endianness = var.options().endianness().unwrap_or(container.options().endianness());
+            
+            for value in range_bounds(grammar, *id) {
+                ret.insert(encode(value, width, endianness));
+            }
+        }
+    }
+    
+    ret.retain(|token| token.len() > 1);
+    ret
+}
+
+/// Write a dictionary in the format of AFL++ and libFuzzer, one quoted token per line
+pub fn write_dictionary<W: Write>(stream: &mut W, grammar: &Grammar, grammar_name: &str) -> std::io::Result<()> {
+    writeln!(stream, "# Tokens of {}", grammar_name)?;
+    
+    for token in dictionary_tokens(grammar) {
+        let mut line = String::from("\"");
+        
+        for b in token {
+            match b {
+                b'"' | b'\\' => line.push_str(&format!("\\x{:02X}", b)),
+                0x20..=0x7E => line.push(b as char),
+                _ => line.push_str(&format!("\\x{:02X}", b)),
+            }
+        }
+        
+        line.push('"');
+        writeln!(stream, "{}", line)?;
+    }
+    
+    Ok(())
+}
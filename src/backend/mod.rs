@@ -1,5 +1,39 @@
+use crate::{
+    Args,
+    frontend::SourceView,
+    grammar::Grammar,
+};
 
 #[allow(non_snake_case)]
 pub mod C;
+pub mod chm;
+pub mod dict;
 
 mod formatter;
+
+/// An output language of the translator.
+/// A new language implements this trait and adds itself to `BACKENDS`.
+pub trait Backend: Sync {
+    /// Name that selects the backend with --backend
+    fn name(&self) -> &'static str;
+    
+    /// Paths of all files that `render` creates when the user passes `outfile` to -o.
+    /// They are checked before anything gets written.
+    fn outputs(&self, outfile: &str) -> Vec<String>;
+    
+    /// Reject arguments that would lead to a broken generator. Called before anything gets written.
+    fn check(&self, _args: &Args, _grammar: &Grammar, _view: &SourceView) -> Result<(), String> {
+        Ok(())
+    }
+    
+    /// Write the generator for the grammar
+    fn render(&self, args: &Args, grammar: &Grammar, view: &SourceView);
+}
+
+pub static BACKENDS: &[&dyn Backend] = &[
+    &C::CBackend,
+];
+
+pub fn find_backend(name: &str) -> Option<&'static dyn Backend> {
+    BACKENDS.iter().copied().find(|backend| backend.name() == name)
+}
@@ -0,0 +1,110 @@
+use std::io::Write;
+use std::sync::OnceLock;
+use crate::frontend::{SourceView, SourceRange};
+
+/// Path of the grammar when messages are written as JSON, set once by --message-format json
+pub static JSON_MESSAGES: OnceLock<String> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about the grammar in a form that editors and CI tools can consume
+pub struct Diagnostic {
+    pub severity: Severity,
+    
+    /// Stable identifier of the kind of message
+    pub code: &'static str,
+    
+    pub message: String,
+    
+    /// The part of the grammar the message is about, if it is about a single place
+    pub span: Option<SourceRange>,
+}
+impl Diagnostic {
+    pub fn error(code: &'static str, message: String, span: Option<SourceRange>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message,
+            span,
+        }
+    }
+    
+    pub fn warning(code: &'static str, message: String, span: Option<SourceRange>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code,
+            message,
+            span,
+        }
+    }
+    
+    /// Write the message as a single line of JSON. Lines and columns start at 1
+    /// and `length` counts characters. Without a span these three fields are null.
+    pub fn write_json<W: Write>(&self, stream: &mut W, file: &str, view: Option<&SourceView>) -> std::io::Result<()> {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        
+        let (line, column, length) = match (&self.span, view) {
+            (Some(span), Some(view)) => {
+                let (line, column) = view.lineinfo(span.start);
+                (line.to_string(), column.to_string(), span.len().to_string())
+            },
+            _ => ("null".to_string(), "null".to_string(), "null".to_string()),
+        };
+        
+        writeln!(
+            stream,
+            "{{\"file\":{},\"line\":{},\"column\":{},\"length\":{},\"severity\":\"{}\",\"code\":\"{}\",\"message\":{}}}",
+            json_string(file),
+            line,
+            column,
+            length,
+            severity,
+            self.code,
+            json_string(&self.message),
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    
+    ret.push('"');
+    ret
+}
+
+/// Write the diagnostics as JSON to stderr if --message-format json was given.
+/// Returns whether they were written.
+pub fn emit_json(diagnostics: &[Diagnostic], view: Option<&SourceView>) -> bool {
+    match JSON_MESSAGES.get() {
+        Some(file) => {
+            let mut stream = std::io::stderr();
+            
+            for diagnostic in diagnostics {
+                let _ = diagnostic.write_json(&mut stream, file, view);
+            }
+            
+            true
+        },
+        None => false,
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use crate::{
+    frontend::SourceView,
+    grammar::Grammar,
+    backend::chm,
+};
+
+/// Name under which the global options and the entrypoint are compared.
+/// It can't clash with the name of a struct.
+const PREAMBLE: &str = "(options)";
+
+/// Normalized text of the preamble and of every named struct that is reachable from the entrypoint
+fn sections(view: &SourceView, grammar: &Grammar) -> BTreeMap<String, Vec<String>> {
+    let mut ret = BTreeMap::new();
+    
+    let mut buf = Vec::new();
+    chm::write_preamble(&mut buf, view, grammar).unwrap();
+    ret.insert(PREAMBLE.to_string(), String::from_utf8_lossy(&buf).lines().map(str::to_string).collect());
+    
+    for container in chm::named_structs(grammar) {
+        let mut buf = Vec::new();
+        chm::write_struct(&mut buf, view, grammar, container).unwrap();
+        ret.insert(view.range(container.name().unwrap()).to_string(), String::from_utf8_lossy(&buf).lines().map(str::to_string).collect());
+    }
+    
+    ret
+}
+
+/// Lines that were removed from `old` and added in `new`, in the order of a longest common subsequence
+fn diff_lines<'a>(old: &'a [String], new: &'a [String]) -> Vec<(char, &'a str)> {
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                std::cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+    
+    let mut ret = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ret.push((' ', old[i].as_str()));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ret.push(('-', old[i].as_str()));
+            i += 1;
+        } else {
+            ret.push(('+', new[j].as_str()));
+            j += 1;
+        }
+    }
+    
+    ret
+}
+
+/// Compare two grammars after parsing and all transformations, struct by struct.
+/// Structs are matched by name, anonymous structs and oneofs are part of the struct
+/// that contains them. Returns the number of structs that were added, removed or changed.
+pub fn write_diff<W: Write>(stream: &mut W, old: (&SourceView, &Grammar), new: (&SourceView, &Grammar)) -> std::io::Result<usize> {
+    let old = sections(old.0, old.1);
+    let new = sections(new.0, new.1);
+    let mut ret = 0;
+    
+    for (name, lines) in &old {
+        if !new.contains_key(name) {
+            writeln!(stream, "removed {}", name)?;
+            
+            for line in lines {
+                writeln!(stream, "  - {}", line)?;
+            }
+            
+            ret += 1;
+        }
+    }
+    
+    for (name, lines) in &new {
+        match old.get(name) {
+            None => {
+                writeln!(stream, "added {}", name)?;
+                
+                for line in lines {
+                    writeln!(stream, "  + {}", line)?;
+                }
+                
+                ret += 1;
+            },
+            Some(old_lines) if old_lines != lines => {
+                writeln!(stream, "changed {}", name)?;
+                
+                for (op, line) in diff_lines(old_lines, lines) {
+                    writeln!(stream, "  {} {}", op, line)?;
+                }
+                
+                ret += 1;
+            },
+            Some(_) => {},
+        }
+    }
+    
+    Ok(ret)
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::grammar::{
+    Grammar, ContainerId, ContainerType, HasOptions,
+    Variable, VariableType, IntegerValue, BytearrayValue,
+    NumbersetId, NumbersetType, Endianness,
+};
+
+/// Byte that fills strings of arbitrary content in the minimal input
+const STRING_FILL: u8 = b'A';
+
+/// Byte that fills bytearrays of arbitrary content in the minimal input
+const BYTES_FILL: u8 = 0;
+
+/// A piece of the minimal input
+pub enum Fragment {
+    /// Bytes that are the same on every platform
+    Literal(Vec<u8>),
+    
+    /// An integer of the given width in bytes that has to be written in host byte order
+    Native(usize, u64),
+    
+    /// Output of an external function
+    Extern(String),
+}
+
+/// The smallest input that can be derived from the entrypoint
+pub struct MinimalInput {
+    fragments: Vec<Fragment>,
+}
+impl MinimalInput {
+    pub fn from_grammar(grammar: &Grammar) -> Self {
+        let mut derivation = Derivation::new(grammar, grammar.minimal_sizes());
+        derivation.container(*grammar.entrypoint().unwrap());
+        
+        Self {
+            fragments: derivation.fragments,
+        }
+    }
+    
+    pub fn fragments(&self) -> &[Fragment] {
+        &self.fragments
+    }
+}
+
+/// A variable of a container, identified by its position
+type Step = (ContainerId, usize);
+
+/// Shortest chain of variables that leads from the entrypoint to the container `target`
+fn path_to(grammar: &Grammar, target: ContainerId) -> Option<Vec<Step>> {
+    let root = *grammar.entrypoint().unwrap();
+    let mut parents = BTreeMap::<ContainerId, Step>::new();
+    let mut visited = BTreeSet::from([root]);
+    let mut queue = std::collections::VecDeque::from([root]);
+    
+    while let Some(id) = queue.pop_front() {
+        if id == target {
+            let mut path = Vec::new();
+            let mut current = id;
+            
+            while let Some(step) = parents.get(&current) {
+                path.push(*step);
+                current = step.0;
+            }
+            
+            path.reverse();
+            return Some(path);
+        }
+        
+        for (idx, var) in grammar.container(id).unwrap().variables().iter().enumerate() {
+            if let VariableType::ContainerRef(next) | VariableType::Oneof(next) = var.typ() {
+                if visited.insert(*next) {
+                    parents.insert(*next, (id, idx));
+                    queue.push_back(*next);
+                }
+            }
+        }
+    }
+    
+    None
+}
+
+/// A small set of inputs that together select every variable of every reachable oneof at least once.
+/// The first input is the minimal input, every further input is the minimal input except for the
+/// choices that lead to a variable that was not selected before.
+pub struct CoveringInputs {
+    inputs: Vec<Vec<Fragment>>,
+}
+impl CoveringInputs {
+    pub fn from_grammar(grammar: &Grammar) -> Self {
+        let sizes = grammar.minimal_sizes();
+        let root = *grammar.entrypoint().unwrap();
+        let mut selected = BTreeSet::<Step>::new();
+        let mut inputs = Vec::new();
+        
+        let mut derivation = Derivation::new(grammar, sizes.clone());
+        derivation.container(root);
+        selected.extend(derivation.selected);
+        inputs.push(derivation.fragments);
+        
+        for id in grammar.reachable_containers() {
+            let oneof = grammar.container(id).unwrap();
+            
+            if oneof.typ() != ContainerType::Oneof {
+                continue;
+            }
+            
+            for idx in 0..oneof.variables().len() {
+                if selected.contains(&(id, idx)) {
+                    continue;
+                }
+                
+                let mut path = path_to(grammar, id).unwrap();
+                path.push((id, idx));
+                
+                let mut derivation = Derivation::new(grammar, sizes.clone());
+                derivation.forced_container(&path);
+                selected.extend(derivation.selected);
+                inputs.push(derivation.fragments);
+            }
+        }
+        
+        Self {
+            inputs,
+        }
+    }
+    
+    pub fn inputs(&self) -> &[Vec<Fragment>] {
+        &self.inputs
+    }
+}
+
+/// Optional variables are left out, repeated variables are repeated as few times
+/// as possible and oneofs expand the variable with the smallest minimal size.
+/// Numbers take the smallest value of their numberset.
+struct Derivation<'a> {
+    grammar: &'a Grammar,
+    sizes: BTreeMap<ContainerId, usize>,
+    fragments: Vec<Fragment>,
+    selected: BTreeSet<Step>,
+}
+impl<'a> Derivation<'a> {
+    fn new(grammar: &'a Grammar, sizes: BTreeMap<ContainerId, usize>) -> Self {
+        Self {
+            grammar,
+            sizes,
+            fragments: Vec::new(),
+            selected: BTreeSet::new(),
+        }
+    }
+    
+    fn push_literal(&mut self, bytes: &[u8]) {
+        if let Some(Fragment::Literal(buf)) = self.fragments.last_mut() {
+            buf.extend_from_slice(bytes);
+        } else {
+            self.fragments.push(Fragment::Literal(bytes.to_vec()));
+        }
+    }
+    
+    fn push_integer(&mut self, value: u64, width: usize, endianness: &Endianness) {
+        match endianness {
+            Endianness::Little => self.push_literal(&value.to_le_bytes()[..width]),
+            Endianness::Big => self.push_literal(&value.to_be_bytes()[8 - width..]),
+            Endianness::Native => self.fragments.push(Fragment::Native(width, value)),
+        }
+    }
+    
+    /// Bit pattern of the smallest number in a numberset
+    fn smallest_number(&self, id: NumbersetId) -> u64 {
+        match self.grammar.numbersets().get(&id).unwrap() {
+            NumbersetType::U8(v) => v[0].start as u64,
+            NumbersetType::I8(v) => v[0].start as u8 as u64,
+            NumbersetType::U16(v) => v[0].start as u64,
+            NumbersetType::I16(v) => v[0].start as u16 as u64,
+            NumbersetType::U32(v) => v[0].start as u64,
+            NumbersetType::I32(v) => v[0].start as u32 as u64,
+            NumbersetType::U64(v) => v[0].start,
+            NumbersetType::I64(v) => v[0].start as u64,
+        }
+    }
+    
+    fn variable(&mut self, var: &Variable, endianness: &Endianness) {
+        self.forced_variable(var, endianness, &[]);
+    }
+    
+    /// Expand a variable minimally, except that the first expansion
+    /// of the referenced container follows `path` if it is not empty
+    fn forced_variable(&mut self, var: &Variable, endianness: &Endianness, path: &[Step]) {
+        let mut count = self.grammar.minimal_variable_count(var);
+        let endianness = var.options().endianness().unwrap_or(endianness);
+        
+        if !path.is_empty() {
+            count = count.max(1);
+        }
+        
+        for i in 0..count {
+            if i > 0 {
+                if let Some(id) = var.options().separator() {
+                    let separator = self.grammar.strings().get(id).unwrap();
+                    self.push_literal(separator);
+                }
+            }
+            
+            match var.typ() {
+                VariableType::Epsilon => {},
+                VariableType::U8(integer) |
+                VariableType::I8(integer) |
+                VariableType::U16(integer) |
+                VariableType::I16(integer) |
+                VariableType::U32(integer) |
+                VariableType::I32(integer) |
+                VariableType::U64(integer) |
+                VariableType::I64(integer) => {
+                    let width = match var.typ() {
+                        VariableType::U8(_) | VariableType::I8(_) => 1,
+                        VariableType::U16(_) | VariableType::I16(_) => 2,
+                        VariableType::U32(_) | VariableType::I32(_) => 4,
+                        _ => 8,
+                    };
+                    let value = match integer {
+                        IntegerValue::FromSet(id) => self.smallest_number(*id),
+                        IntegerValue::Any => 0,
+                    };
+                    
+                    self.push_integer(value, width, endianness);
+                },
+                VariableType::String(bytearray) |
+                VariableType::Bytes(bytearray) => match bytearray {
+                    BytearrayValue::Any(id) => {
+                        let fill = if let VariableType::String(_) = var.typ() {
+                            STRING_FILL
+                        } else {
+                            BYTES_FILL
+                        };
+                        let len = self.grammar.get_numberset_bound(*id, false);
+                        self.push_literal(&vec![fill; len]);
+                    },
+                    BytearrayValue::Literal(id) => {
+                        let string = self.grammar.strings().get(id).unwrap();
+                        self.push_literal(string);
+                    },
+                },
+                VariableType::ContainerRef(_) |
+                VariableType::Oneof(_) if i == 0 && !path.is_empty() => self.forced_container(path),
+                VariableType::ContainerRef(id) => self.container(*id),
+                VariableType::Oneof(id) => {
+                    let oneof = self.grammar.container(*id).unwrap();
+                    let idx = self.grammar.cheapest_variable(*id, &self.sizes);
+                    self.selected.insert((*id, idx));
+                    self.variable(&oneof.variables()[idx], oneof.options().endianness());
+                },
+                VariableType::ExternRef(name) => self.fragments.push(Fragment::Extern(name.clone())),
+                VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
+            }
+        }
+    }
+    
+    fn container(&mut self, id: ContainerId) {
+        let container = self.grammar.container(id).unwrap();
+        
+        for var in container.variables() {
+            self.variable(var, container.options().endianness());
+        }
+    }
+    
+    /// Expand the container of the first step with the variable of the first step
+    /// following the rest of the path and everything else minimal
+    fn forced_container(&mut self, path: &[Step]) {
+        let (id, forced) = path[0];
+        let container = self.grammar.container(id).unwrap();
+        let endianness = container.options().endianness();
+        
+        match container.typ() {
+            ContainerType::Oneof => {
+                self.selected.insert((id, forced));
+                self.forced_variable(&container.variables()[forced], endianness, &path[1..]);
+            },
+            ContainerType::Struct => {
+                for (idx, var) in container.variables().iter().enumerate() {
+                    if idx == forced {
+                        self.forced_variable(var, endianness, &path[1..]);
+                    } else {
+                        self.variable(var, endianness);
+                    }
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use crate::frontend::keywords;
+
+/// Indentation of one block level
+const INDENT: &str = "    ";
+
+/// Where the scanner is at the end of a line
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Code,
+    
+    /// Inside a block comment with the given nesting level
+    Comment(usize),
+    
+    /// Inside a string literal, raw or not
+    String(bool),
+}
+
+fn is_identifier_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Scan a single line and return the change of the block level and the state at the end of the line
+fn scan_line(line: &[u8], mut state: State) -> (isize, State) {
+    let mut level = 0;
+    let mut i = 0;
+    
+    while i < line.len() {
+        let rest = &line[i..];
+        
+        match state {
+            State::Comment(nesting) => {
+                if rest.starts_with(keywords::COMMENT_OPEN.as_bytes()) {
+                    state = State::Comment(nesting + 1);
+                    i += keywords::COMMENT_OPEN.len();
+                } else if rest.starts_with(keywords::COMMENT_CLOSE.as_bytes()) {
+                    state = if nesting == 1 { State::Code } else { State::Comment(nesting - 1) };
+                    i += keywords::COMMENT_CLOSE.len();
+                } else {
+                    i += 1;
+                }
+            },
+            State::String(raw) => {
+                if !raw && rest[0] == b'\\' {
+                    i += 2;
+                } else {
+                    if rest.starts_with(keywords::STRING_DELIM.as_bytes()) {
+                        state = State::Code;
+                    }
+                    i += 1;
+                }
+            },
+            State::Code => {
+                if rest.starts_with(keywords::LINE_COMMENT.as_bytes()) {
+                    break;
+                } else if rest.starts_with(keywords::COMMENT_OPEN.as_bytes()) {
+                    state = State::Comment(1);
+                    i += keywords::COMMENT_OPEN.len();
+                } else if rest.starts_with(keywords::STRING_DELIM.as_bytes()) {
+                    let raw = i > 0
+                        && line[..i].ends_with(keywords::RAW_STRING_PREFIX.as_bytes())
+                        && (i == keywords::RAW_STRING_PREFIX.len() || !is_identifier_char(line[i - keywords::RAW_STRING_PREFIX.len() - 1]));
+                    state = State::String(raw);
+                    i += keywords::STRING_DELIM.len();
+                } else if rest.starts_with(keywords::CHAR_DELIM.as_bytes()) {
+                    // Skip the whole character literal so that '{' and '}' don't count
+                    let mut j = i + 1;
+                    
+                    while j < line.len() && line[j] != b'\'' {
+                        j += if line[j] == b'\\' { 2 } else { 1 };
+                    }
+                    
+                    i = j + 1;
+                } else {
+                    if rest.starts_with(keywords::BLOCK_OPEN.as_bytes()) {
+                        level += 1;
+                    } else if rest.starts_with(keywords::BLOCK_CLOSE.as_bytes()) {
+                        level -= 1;
+                    }
+                    i += 1;
+                }
+            },
+        }
+    }
+    
+    (level, state)
+}
+
+/// Part of a line of code
+#[derive(Clone, PartialEq)]
+enum Piece {
+    /// Identifier, keyword, number or range
+    Word(String),
+    
+    Punct(char),
+    
+    /// String or character literal that is copied as it is
+    Literal(String),
+    
+    Comment(String),
+}
+
+/// Whether `c` is part of a word when followed by `next`. Ranges like `-5..-1` are a single word.
+fn is_word_char(c: char, next: Option<char>) -> bool {
+    !c.is_ascii() || is_identifier_char(c as u8) || c == '.' || c == '$'
+        || (c == '-' && matches!(next, Some(next) if next.is_ascii_digit()))
+}
+
+/// Index of the end of the literal that starts at `start` with `delim`
+fn literal_end(chars: &[char], start: usize, delim: char, raw: bool) -> usize {
+    let mut i = start + 1;
+    
+    while i < chars.len() {
+        if !raw && chars[i] == '\\' {
+            i += 2;
+        } else if chars[i] == delim {
+            return i + 1;
+        } else {
+            i += 1;
+        }
+    }
+    
+    chars.len()
+}
+
+/// Split a line that starts outside of comments and strings into pieces.
+/// Returns every piece with whether whitespace came before it.
+fn split_line(line: &str) -> Vec<(Piece, bool)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut ret = Vec::<(Piece, bool)>::new();
+    let mut space = false;
+    let mut i = 0;
+    
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().take(2).collect();
+        
+        if c.is_whitespace() {
+            space = true;
+            i += 1;
+            continue;
+        }
+        
+        let (piece, end) = if rest == keywords::LINE_COMMENT {
+            (Piece::Comment(chars[i..].iter().collect::<String>().trim_end().to_string()), chars.len())
+        } else if rest == keywords::COMMENT_OPEN {
+            let mut nesting = 0;
+            let mut j = i;
+            
+            while j < chars.len() {
+                let pair: String = chars[j..].iter().take(2).collect();
+                
+                if pair == keywords::COMMENT_OPEN {
+                    nesting += 1;
+                    j += 2;
+                } else if pair == keywords::COMMENT_CLOSE {
+                    nesting -= 1;
+                    j += 2;
+                    
+                    if nesting == 0 {
+                        break;
+                    }
+                } else {
+                    j += 1;
+                }
+            }
+            
+            let end = j.min(chars.len());
+            (Piece::Comment(chars[i..end].iter().collect()), end)
+        } else if c == '"' || c == '\'' {
+            // Raw and hex strings keep their prefix
+            let prefix = match ret.last() {
+                Some((Piece::Word(word), _)) if !space && c == '"' && (word == keywords::RAW_STRING_PREFIX || word == keywords::HEX_STRING_PREFIX) => Some(word.clone()),
+                _ => None,
+            };
+            let raw = prefix.as_deref() == Some(keywords::RAW_STRING_PREFIX);
+            let end = literal_end(&chars, i, c, raw);
+            let mut literal: String = chars[i..end].iter().collect();
+            
+            if let Some(prefix) = prefix {
+                literal.insert_str(0, &prefix);
+                space = ret.pop().unwrap().1;
+            }
+            
+            (Piece::Literal(literal), end)
+        } else if is_word_char(c, chars.get(i + 1).copied()) {
+            let mut j = i + 1;
+            
+            while j < chars.len() && is_word_char(chars[j], chars.get(j + 1).copied()) {
+                j += 1;
+            }
+            
+            (Piece::Word(chars[i..j].iter().collect()), j)
+        } else {
+            (Piece::Punct(c), i + 1)
+        };
+        
+        // Join ranges like `1 .. 5` into a single word
+        match (ret.last_mut(), &piece) {
+            (Some((Piece::Word(prev), _)), Piece::Word(word)) if prev.ends_with(keywords::RANGE_OP) || word.starts_with(keywords::RANGE_OP) => prev.push_str(word),
+            _ => ret.push((piece, space)),
+        }
+        
+        space = false;
+        i = end;
+    }
+    
+    ret
+}
+
+/// Value of an integer literal in any of the number formats of the grammar
+fn parse_integer(text: &str) -> Option<i128> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if let Some(oct) = digits.strip_prefix("0o") {
+        i128::from_str_radix(oct, 8).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i128::from_str_radix(bin, 2).ok()?
+    } else if digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    
+    Some(if negative { -value } else { value })
+}
+
+/// Bounds of a numberset entry that is a number or a range of numbers
+fn parse_entry(text: &str) -> Option<(i128, i128)> {
+    match text.split_once(keywords::RANGE_OP) {
+        Some((start, end)) => Some((parse_integer(start)?, parse_integer(end)?)),
+        None => parse_integer(text).map(|value| (value, value)),
+    }
+}
+
+/// Sort the entries of numbersets that only consist of numbers and ranges and remove entries that are written twice.
+/// The parser merges the entries of a numberset anyway, so their order doesn't matter.
+/// Numbersets with characters, constants or exclusions are left as they are.
+fn normalize_numbersets(pieces: &mut Vec<(Piece, bool)>) {
+    let assignment = Piece::Punct(keywords::ASSIGNMENT.chars().next().unwrap());
+    let delim = Piece::Punct(keywords::NUMBERSET_DELIM.chars().next().unwrap());
+    let terminator = Piece::Punct(keywords::TERMINATE_STATEMENT.chars().next().unwrap());
+    let mut i = 0;
+    
+    while i < pieces.len() {
+        if pieces[i].0 != assignment {
+            i += 1;
+            continue;
+        }
+        
+        let start = i + 1;
+        let end = match pieces[start..].iter().position(|(piece, _)| *piece == terminator) {
+            Some(len) => start + len,
+            None => return,
+        };
+        let mut entries = Vec::new();
+        
+        for (j, (piece, _)) in pieces[start..end].iter().enumerate() {
+            match piece {
+                Piece::Word(word) if j % 2 == 0 => match parse_entry(word) {
+                    Some(bounds) => entries.push((bounds, word.clone())),
+                    None => break,
+                },
+                piece if j % 2 == 1 && *piece == delim => {},
+                _ => break,
+            }
+        }
+        
+        if entries.len() > 1 && entries.len() * 2 - 1 == end - start {
+            entries.sort_by_key(|(bounds, _)| *bounds);
+            entries.dedup();
+            
+            let mut normalized = Vec::new();
+            
+            for (j, (_, word)) in entries.into_iter().enumerate() {
+                if j > 0 {
+                    normalized.push((delim.clone(), false));
+                }
+                
+                normalized.push((Piece::Word(word), true));
+            }
+            
+            pieces.splice(start..end, normalized);
+        }
+        
+        i = start;
+    }
+}
+
+/// Whether a space separates two pieces in canonical form
+fn needs_space(prev: &Piece, cur: &Piece, had_space: bool) -> bool {
+    let punct = |piece: &Piece, s: &str| matches!(piece, Piece::Punct(c) if s.contains(*c));
+    let is_endianness = |piece: &Piece| matches!(
+        piece,
+        Piece::Word(word) if word == keywords::ENDIANNESS_LITTLE || word == keywords::ENDIANNESS_BIG || word == keywords::ENDIANNESS_NATIVE
+    );
+    
+    match (prev, cur) {
+        (_, Piece::Comment(comment)) if comment.starts_with(keywords::LINE_COMMENT) => true,
+        (Piece::Comment(_), _) | (_, Piece::Comment(_)) => had_space,
+        (_, cur) if punct(cur, ";,)") => false,
+        (prev, cur) if punct(prev, "(") || punct(cur, "(") => false,
+        (prev, cur) if punct(prev, ":") => !is_endianness(cur),
+        (_, cur) if punct(cur, ":") => false,
+        (prev, cur) if punct(prev, "=,;{}") || punct(cur, "={}") => true,
+        (prev, _) if punct(prev, "!") => false,
+        (Piece::Word(word), _) if word.ends_with(keywords::RANGE_OP) => false,
+        (_, Piece::Word(word)) if word.starts_with(keywords::RANGE_OP) => false,
+        _ => had_space,
+    }
+}
+
+/// Canonical whitespace within a line that starts outside of comments and strings
+fn normalize_line(line: &str) -> String {
+    let mut pieces = split_line(line);
+    normalize_numbersets(&mut pieces);
+    
+    let mut ret = String::new();
+    let mut prev: Option<&Piece> = None;
+    
+    for (piece, had_space) in &pieces {
+        if let Some(prev) = prev {
+            if needs_space(prev, piece, *had_space) {
+                ret.push(' ');
+            }
+        }
+        
+        match piece {
+            Piece::Word(text) | Piece::Literal(text) | Piece::Comment(text) => ret.push_str(text),
+            Piece::Punct(c) => ret.push(*c),
+        }
+        
+        prev = Some(piece);
+    }
+    
+    ret
+}
+
+/// Re-indent a grammar with four spaces per block level, remove trailing whitespace
+/// and collapse runs of empty lines into a single one.
+/// Within a line, whitespace between tokens is made canonical, e.g. `a:u8=1 ..5 ;` becomes `a: u8 = 1..5;`,
+/// and numbersets that consist only of numbers and ranges are sorted with duplicates removed.
+/// Comments and the contents of string literals are kept as they are, so formatting never
+/// changes the meaning of a grammar. The variables of oneofs are not sorted because their order
+/// matters for round-robin scheduling.
+pub fn format_grammar(source: &str) -> String {
+    let mut ret = String::new();
+    let mut level: isize = 0;
+    let mut state = State::Code;
+    let mut pending_empty = false;
+    
+    for line in source.lines() {
+        let (delta, next_state) = scan_line(line.as_bytes(), state);
+        
+        if let State::Code = state {
+            let content = line.trim();
+            
+            if content.is_empty() {
+                pending_empty = !ret.is_empty();
+            } else {
+                if pending_empty {
+                    ret.push('\n');
+                    pending_empty = false;
+                }
+                
+                let indent = if content.starts_with(keywords::BLOCK_CLOSE) { level - 1 } else { level };
+                ret.push_str(&INDENT.repeat(indent.max(0) as usize));
+                ret.push_str(&normalize_line(content));
+                ret.push('\n');
+            }
+        } else {
+            // Continuation of a comment or string
+            if pending_empty {
+                ret.push('\n');
+                pending_empty = false;
+            }
+            
+            if let State::String(_) = state {
+                ret.push_str(line);
+            } else {
+                ret.push_str(line.trim_end());
+            }
+            ret.push('\n');
+        }
+        
+        level += delta;
+        state = next_state;
+    }
+    
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_grammar;
+    
+    #[test]
+    fn whitespace_within_lines() {
+        let source = "struct Root{\na:u32:be=1 .. 3 ;\nb : u8=!0 , 'a' ..'z';\n  c:string=r\"a  b\"  ;   // keep   this\n/* a   b */ d : bytes = x\"7F  45\";\n}\n";
+        let expected = "struct Root {\n    a: u32:be = 1..3;\n    b: u8 = !0, 'a'..'z';\n    c: string = r\"a  b\"; // keep   this\n    /* a   b */ d: bytes = x\"7F  45\";\n}\n";
+        assert_eq!(format_grammar(source), expected);
+    }
+    
+    #[test]
+    fn numbersets_are_sorted() {
+        let source = "struct Root {\n    a: i8 = 5, 0x1..0x3 ,-10, 5, -3 .. -1;\n    b: u8 = 3, $X, 1;\n}\n";
+        let expected = "struct Root {\n    a: i8 = -10, -3..-1, 0x1..0x3, 5;\n    b: u8 = 3, $X, 1;\n}\n";
+        assert_eq!(format_grammar(source), expected);
+    }
+    
+    #[test]
+    fn oneofs_keep_their_order() {
+        let source = "struct Root {\n    o: oneof {\n        b: u8 = 2;\n        a: u8 = 1;\n    };\n}\n";
+        assert_eq!(format_grammar(source), source);
+    }
+    
+    #[test]
+    fn formatting_is_idempotent() {
+        let source = "option scheduling=round-robin;\nstruct Root{\nrepeats 1..5 separated \",\" x : i8 = 3, -3 .. -1;\noptional   y:u8;\n}\n";
+        let formatted = format_grammar(source);
+        assert_eq!(format_grammar(&formatted), formatted);
+    }
+}
@@ -1,10 +1,14 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
 use petgraph::{
     graph::{Graph, NodeIndex},
-    algo::has_path_connecting,
+    algo::{has_path_connecting, tarjan_scc},
     Direction,
 };
-use crate::grammar::{ContainerId, Grammar, ContainerType};
+use crate::{
+    frontend::SourceView,
+    grammar::{ContainerId, Grammar, ContainerType},
+};
 
 pub struct GrammarGraph<'a> {
     grammar: &'a Grammar,
@@ -25,7 +29,7 @@ impl<'a> GrammarGraph<'a> {
         }
         
         for (start_id, start_idx) in container_map.iter() {
-            for dest_id in grammar.container_callees(*start_id, true) {
+            for dest_id in grammar.container_callees(*start_id, None) {
                 let dest_idx = container_map.get(&dest_id).unwrap();
                 graph.add_edge(*start_idx, *dest_idx, ());
             }
@@ -51,8 +55,10 @@ impl<'a> GrammarGraph<'a> {
             }
         }
         
+        let sizes = grammar.minimal_sizes();
+        
         for (start_id, start_idx) in container_map.iter() {
-            for dest_id in grammar.container_callees(*start_id, false) {
+            for dest_id in grammar.container_callees(*start_id, Some(&sizes)) {
                 let dest_idx = container_map.get(&dest_id).unwrap();
                 graph.add_edge(*start_idx, *dest_idx, ());
             }
@@ -70,14 +76,15 @@ impl<'a> GrammarGraph<'a> {
     pub fn unreachable_containers(&self) -> Vec<ContainerId> {
         let mut ret = Vec::new();
         
-        let root = self.grammar.root().unwrap();
-        let root_idx = self.container_map.get(root).unwrap();
+        let mut roots = vec![*self.grammar.root().unwrap()];
+        roots.extend(self.grammar.entrypoints());
+        let root_idxs: Vec<NodeIndex> = roots.iter().map(|id| *self.container_map.get(id).unwrap()).collect();
         
         for id in self.grammar.container_ids() {
             if self.grammar.container(*id).unwrap().typ() == ContainerType::Struct {
                 let target_idx = self.container_map.get(id).unwrap();
                 
-                if !has_path_connecting(&self.graph, *root_idx, *target_idx, None) {
+                if !root_idxs.iter().any(|root_idx| has_path_connecting(&self.graph, *root_idx, *target_idx, None)) {
                     ret.push(id.clone());
                 }
             }
@@ -99,4 +106,69 @@ impl<'a> GrammarGraph<'a> {
         
         None
     }
+    
+    /// Groups of structs that can reach each other, i.e. the strongly connected
+    /// components of the graph that contain a cycle. Every group is sorted by ID.
+    pub fn recursive_groups(&self) -> Vec<Vec<ContainerId>> {
+        let mut ret = Vec::new();
+        
+        for component in tarjan_scc(&self.graph) {
+            let is_cycle = component.len() > 1 || self.graph.contains_edge(component[0], component[0]);
+            
+            if is_cycle {
+                let mut group: Vec<ContainerId> = component.iter().map(|idx| *self.graph.node_weight(*idx).unwrap()).collect();
+                group.sort();
+                ret.push(group);
+            }
+        }
+        
+        ret.sort();
+        ret
+    }
+    
+    /// Write the structs that are reachable from the entrypoint and the structs they use
+    /// as a Graphviz digraph. Oneofs are not part of the graph, a struct points directly
+    /// to the structs in its oneofs. Structs that are part of a cycle are drawn in red.
+    pub fn write_dot<W: Write>(&self, stream: &mut W, view: &SourceView) -> std::io::Result<()> {
+        let reachable = self.grammar.reachable_containers();
+        let recursive: BTreeSet<ContainerId> = self.recursive_groups().into_iter().flatten().collect();
+        let root = self.grammar.entrypoint().unwrap();
+        
+        writeln!(stream, "digraph grammar {{")?;
+        writeln!(stream, "    node [shape=box];")?;
+        
+        for (id, idx) in &self.container_map {
+            if !reachable.contains(id) {
+                continue;
+            }
+            
+            let name = self.grammar.container(*id).unwrap().name().unwrap();
+            let label = if name.is_empty() {
+                let (line, col) = view.lineinfo(name.start);
+                format!("anonymous struct\\nline {} column {}", line, col)
+            } else {
+                view.range(name).to_string()
+            };
+            
+            let mut attributes = vec![format!("label=\"{}\"", label)];
+            
+            if id == root {
+                attributes.push("penwidth=2".to_string());
+            }
+            
+            if recursive.contains(id) {
+                attributes.push("color=red".to_string());
+            }
+            
+            writeln!(stream, "    n{} [{}];", id, attributes.join(", "))?;
+            
+            let callees: BTreeSet<ContainerId> = self.graph.neighbors_directed(*idx, Direction::Outgoing).map(|nb| *self.graph.node_weight(nb).unwrap()).collect();
+            
+            for callee in callees {
+                writeln!(stream, "    n{} -> n{};", id, callee)?;
+            }
+        }
+        
+        writeln!(stream, "}}")
+    }
 }
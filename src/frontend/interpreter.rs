@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use crate::grammar::{
+    Grammar, ContainerId, ContainerType, ContainerOptions, HasOptions,
+    Variable, VariableType, IntegerValue, BytearrayValue,
+    NumbersetId, NumbersetType, Endianness, Scheduling, Depth,
+};
+
+/// Same default seed as the generated C code
+const SEED: u64 = 0x35c6be9ba2548264;
+
+fn pick_range<T: Copy + Into<i128>>(ranges: &[std::ops::Range<T>], r1: u64, r2: u64) -> u64 {
+    let range = &ranges[(r1 % ranges.len() as u64) as usize];
+    let (start, end) = (range.start.into(), range.end.into());
+    let width = (end - start + 1) as u128;
+    (start + (r2 as u128 % width) as i128) as u64
+}
+
+/// Generates inputs by walking the grammar directly instead of compiling it to C first.
+/// It makes the same kind of decisions as the generated code: oneofs follow their scheduling,
+/// adaptive oneofs select uniformly at random since there is no feedback, and the `depth`
+/// and `recursion` options as well as a full buffer lead to minimal expansion.
+/// Like the generated code it panics when a container generates more bytes than its `max_len` allows.
+/// The inputs are not identical to the ones of a generator with the same seed.
+pub struct Interpreter<'a> {
+    grammar: &'a Grammar,
+    sizes: BTreeMap<ContainerId, usize>,
+    state: u64,
+    round_robin: BTreeMap<ContainerId, usize>,
+    active: BTreeMap<ContainerId, usize>,
+    max_len: usize,
+}
+impl<'a> Interpreter<'a> {
+    pub fn new(grammar: &'a Grammar, seed: u64, max_len: usize) -> Self {
+        Self {
+            grammar,
+            sizes: grammar.minimal_sizes(),
+            state: if seed == 0 { SEED } else { seed },
+            round_robin: BTreeMap::new(),
+            active: BTreeMap::new(),
+            max_len,
+        }
+    }
+    
+    pub fn generate(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.container(*self.grammar.entrypoint().unwrap(), 1, &mut out);
+        out.truncate(self.max_len);
+        out
+    }
+    
+    /// xorshift64 like the generated code
+    fn rand(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+    
+    /// Bit pattern of a random number from a numberset
+    fn number(&mut self, id: NumbersetId) -> u64 {
+        let (r1, r2) = (self.rand(), self.rand());
+        
+        match self.grammar.numbersets().get(&id).unwrap() {
+            NumbersetType::U8(v) => pick_range(v, r1, r2),
+            NumbersetType::I8(v) => pick_range(v, r1, r2),
+            NumbersetType::U16(v) => pick_range(v, r1, r2),
+            NumbersetType::I16(v) => pick_range(v, r1, r2),
+            NumbersetType::U32(v) => pick_range(v, r1, r2),
+            NumbersetType::I32(v) => pick_range(v, r1, r2),
+            NumbersetType::U64(v) => pick_range(v, r1, r2),
+            NumbersetType::I64(v) => pick_range(v, r1, r2),
+        }
+    }
+    
+    /// Whether variables of a block with the given options may be expanded beyond their minimum
+    fn expandable(&self, options: &ContainerOptions, depth: usize, out: &[u8]) -> bool {
+        if let Depth::Limited(limit) = options.depth() {
+            if depth >= *limit {
+                return false;
+            }
+        }
+        
+        if let Some((owner, limit)) = options.recursion() {
+            if self.active.get(owner).copied().unwrap_or(0) >= *limit {
+                return false;
+            }
+        }
+        
+        out.len() < self.max_len
+    }
+    
+    fn push_integer(out: &mut Vec<u8>, value: u64, width: usize, endianness: &Endianness) {
+        match endianness {
+            Endianness::Little => out.extend_from_slice(&value.to_le_bytes()[..width]),
+            Endianness::Big => out.extend_from_slice(&value.to_be_bytes()[8 - width..]),
+            Endianness::Native => if cfg!(target_endian = "little") {
+                out.extend_from_slice(&value.to_le_bytes()[..width]);
+            } else {
+                out.extend_from_slice(&value.to_be_bytes()[8 - width..]);
+            },
+        }
+    }
+    
+    fn variable(&mut self, var: &Variable, options: &ContainerOptions, depth: usize, out: &mut Vec<u8>) {
+        let expandable = self.expandable(options, depth, out);
+        
+        if var.options().optional() && (!expandable || self.rand() & 1 == 0) {
+            return;
+        }
+        
+        let count = match var.options().repeats() {
+            Some(id) if expandable => self.number(*id) as usize,
+            Some(_) => self.grammar.minimal_variable_count(var),
+            None => 1,
+        };
+        let endianness = var.options().endianness().unwrap_or(options.endianness());
+        
+        for i in 0..count {
+            if i > 0 {
+                if let Some(id) = var.options().separator() {
+                    out.extend_from_slice(self.grammar.strings().get(id).unwrap());
+                }
+            }
+            
+            match var.typ() {
+                VariableType::Epsilon => {},
+                VariableType::U8(integer) |
+                VariableType::I8(integer) |
+                VariableType::U16(integer) |
+                VariableType::I16(integer) |
+                VariableType::U32(integer) |
+                VariableType::I32(integer) |
+                VariableType::U64(integer) |
+                VariableType::I64(integer) => {
+                    let width = match var.typ() {
+                        VariableType::U8(_) | VariableType::I8(_) => 1,
+                        VariableType::U16(_) | VariableType::I16(_) => 2,
+                        VariableType::U32(_) | VariableType::I32(_) => 4,
+                        _ => 8,
+                    };
+                    let value = match integer {
+                        IntegerValue::FromSet(id) => self.number(*id),
+                        IntegerValue::Any => self.rand(),
+                    };
+                    
+                    Self::push_integer(out, value, width, endianness);
+                },
+                VariableType::String(bytearray) |
+                VariableType::Bytes(bytearray) => match bytearray {
+                    BytearrayValue::Any(id) => {
+                        // Strings consist of 7-bit characters like in the generated code
+                        let mask = if let VariableType::String(_) = var.typ() { 0x7F } else { 0xFF };
+                        let len = if expandable {
+                            self.number(*id) as usize
+                        } else {
+                            self.grammar.get_numberset_bound(*id, false)
+                        };
+                        
+                        for _ in 0..len {
+                            let byte = self.rand() as u8 & mask;
+                            out.push(byte);
+                        }
+                    },
+                    BytearrayValue::Literal(id) => out.extend_from_slice(self.grammar.strings().get(id).unwrap()),
+                },
+                VariableType::ContainerRef(id) |
+                VariableType::Oneof(id) => self.container(*id, depth + 1, out),
+                // The output of external functions is not available here
+                VariableType::ExternRef(_) => {},
+                VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
+            }
+        }
+    }
+    
+    fn select(&mut self, id: ContainerId, depth: usize, out: &[u8]) -> usize {
+        let container = self.grammar.container(id).unwrap();
+        let num_vars = container.variables().len();
+        
+        if !self.expandable(container.options(), depth, out) {
+            return self.grammar.cheapest_variable(id, &self.sizes);
+        }
+        
+        match container.options().scheduling() {
+            Scheduling::RoundRobin => {
+                let next = self.round_robin.entry(id).or_insert(0);
+                let ret = *next;
+                *next = (*next + 1) % num_vars;
+                ret
+            },
+            Scheduling::Random |
+            Scheduling::Adaptive => (self.rand() % num_vars as u64) as usize,
+        }
+    }
+    
+    fn container(&mut self, id: ContainerId, depth: usize, out: &mut Vec<u8>) {
+        let container = self.grammar.container(id).unwrap();
+        let owns_counter = matches!(container.options().recursion(), Some((owner, _)) if *owner == id);
+        
+        if owns_counter {
+            *self.active.entry(id).or_insert(0) += 1;
+        }
+        
+        let start = out.len();
+        
+        match container.typ() {
+            ContainerType::Struct => {
+                for var in container.variables() {
+                    self.variable(var, container.options(), depth, out);
+                }
+            },
+            ContainerType::Oneof => {
+                let idx = self.select(id, depth, out);
+                self.variable(&container.variables()[idx], container.options(), depth, out);
+            },
+        }
+        
+        if owns_counter {
+            *self.active.get_mut(&id).unwrap() -= 1;
+        }
+        
+        // Same assertion as in the generated code
+        if let Some((owner, limit)) = container.options().max_len() {
+            if *owner == id && out.len() - start > *limit {
+                panic!("Assertion failed: container {} generated {} bytes but max_len is {}", id, out.len() - start, limit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Interpreter;
+    use crate::frontend::{Lexer, Parser, SourceView};
+    use crate::grammar::Grammar;
+    
+    fn parse(source: &str) -> Grammar {
+        let view = SourceView::new(source);
+        let tokens = Lexer::new(&view).lex().unwrap();
+        Parser::new(&view, &tokens).parse().unwrap()
+    }
+    
+    #[test]
+    fn max_len_holds() {
+        let grammar = parse("struct Root { option max_len = 4; a: string = \"ab\"; b: bytes = 1..2; }");
+        let mut interpreter = Interpreter::new(&grammar, 1, 4096);
+        
+        for _ in 0..100 {
+            assert!(interpreter.generate().len() <= 4);
+        }
+    }
+    
+    #[test]
+    #[should_panic(expected = "max_len is 3")]
+    fn max_len_is_asserted() {
+        let grammar = parse("struct Root { option max_len = 3; a: string = \"ab\"; b: bytes = 2..4; }");
+        let mut interpreter = Interpreter::new(&grammar, 1, 4096);
+        interpreter.generate();
+    }
+}
@@ -1,24 +1,44 @@
 
 pub const CONTAINER: &str = "struct";
 pub const OPTION: &str = "option";
+pub const ALIAS: &str = "alias";
+pub const ENTRYPOINT: &str = "entrypoint";
+pub const SKIP: &str = "skip";
+pub const DEFINE: &str = "define";
+pub const CONSTANT_PREFIX: &str = "$";
+pub const CONSTANT_OPEN: &str = "${";
+pub const CONSTANT_CLOSE: &str = "}";
 pub const COMMENT_OPEN: &str = "/*";
 pub const COMMENT_CLOSE: &str = "*/";
+pub const LINE_COMMENT: &str = "//";
 pub const TERMINATE_STATEMENT: &str = ";";
 pub const ASSIGNMENT: &str = "=";
 pub const BLOCK_OPEN: &str = "{";
 pub const BLOCK_CLOSE: &str = "}";
 pub const VAROPT_OPTIONAL: &str = "optional";
 pub const VAROPT_REPEATS: &str = "repeats";
+pub const VAROPT_SEPARATED: &str = "separated";
 pub const RANGE_OP: &str = "..";
 pub const NUMBERSET_DELIM: &str = ",";
+pub const NUMBERSET_EXCLUDE: &str = "!";
 pub const VAR_TYPE_SEP: &str = ":";
+pub const ENDIANNESS_LITTLE: &str = "le";
+pub const ENDIANNESS_BIG: &str = "be";
+pub const ENDIANNESS_NATIVE: &str = "native";
 pub const STRING_DELIM: &str = "\"";
+pub const RAW_STRING_PREFIX: &str = "r";
+pub const HEX_STRING_PREFIX: &str = "x";
+pub const EMBED_FILE: &str = "file";
+pub const ARGS_OPEN: &str = "(";
+pub const ARGS_CLOSE: &str = ")";
 pub const CHAR_DELIM: &str = "'";
 pub const DEPTH_UNLIMITED: &str = "unlimited";
 
 pub const OPTION_ENDIANNESS: &str = "endianness";
 pub const OPTION_SCHEDULING: &str = "scheduling";
 pub const OPTION_DEPTH: &str = "depth";
+pub const OPTION_RECURSION: &str = "recursion";
+pub const OPTION_MAX_LEN: &str = "max_len";
 
 pub const TYPE_U8: &str = "u8";
 pub const TYPE_I8: &str = "i8";
@@ -32,5 +52,6 @@ pub const TYPE_ONEOF: &str = "oneof";
 pub const TYPE_STRING: &str = "string";
 pub const TYPE_BYTES: &str = "bytes";
 pub const TYPE_CHAR: &str = "char";
+pub const TYPE_EPSILON: &str = "eps";
 
 pub const ROOT_CONTAINER: &str = "Root";
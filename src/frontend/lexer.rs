@@ -44,6 +44,18 @@ pub enum Token {
     /// An option was set in a block
     OptionDef(usize, SourceRange, SourceRange),
     
+    /// A new name was given to an existing container
+    Alias(usize, SourceRange, SourceRange),
+    
+    /// The container where generation starts
+    Entrypoint(usize, SourceRange),
+    
+    /// A container that gets removed from the grammar
+    Skip(usize, SourceRange),
+    
+    /// A named constant: name and value. String values include their quotes.
+    Define(usize, SourceRange, SourceRange),
+    
     /// A variable definition follows
     VariableStart(usize),
     
@@ -59,12 +71,18 @@ pub enum Token {
     /// The variables repeats option has ended
     VariableRepeatEnd,
     
+    /// The repetitions of the variable are separated by the following string
+    VariableSeparator(usize),
+    
     /// A numberset follows
     NumbersetStart(usize),
     
     /// No more numberset entries follow
     NumbersetEnd,
     
+    /// The next numberset entry shall be excluded from the numberset
+    NumbersetExclude(usize),
+    
     /// A range of integers was specified
     IntegerRange(SourceRange, SourceRange),
     
@@ -77,9 +95,21 @@ pub enum Token {
     /// Sets the type of the currently active variable
     VariableType(SourceRange),
     
+    /// Sets the byte order of the currently active variable
+    VariableEndianness(SourceRange),
+    
     /// A string literal was specified
     String(SourceRange),
     
+    /// A raw string literal was specified where backslashes are not interpreted
+    RawString(SourceRange),
+    
+    /// A string of hex digits was specified
+    HexString(SourceRange),
+    
+    /// The path of a file whose contents become a string literal
+    File(SourceRange),
+    
     /// A variable has an assigned value
     VariableValueStart(usize),
     
@@ -102,18 +132,28 @@ pub enum TokenId {
     ContainerOpen,
     ContainerClose,
     OptionDef,
+    Alias,
+    Entrypoint,
+    Skip,
+    Define,
     VariableStart,
     VariableEnd,
     VariableOptional,
     VariableRepeatStart,
     VariableRepeatEnd,
+    VariableSeparator,
     NumbersetStart,
     NumbersetEnd,
+    NumbersetExclude,
     IntegerRange,
     Integer,
     Character,
     VariableType,
+    VariableEndianness,
     String,
+    RawString,
+    HexString,
+    File,
     VariableValueStart,
     VariableValueEnd,
     BlockOpen,
@@ -126,18 +166,28 @@ impl TokenId {
             TokenId::ContainerOpen => "a new container",
             TokenId::ContainerClose => "the end of the container",
             TokenId::OptionDef => "an option definition",
+            TokenId::Alias => "an alias",
+            TokenId::Entrypoint => "an entrypoint",
+            TokenId::Skip => "a skip directive",
+            TokenId::Define => "a constant definition",
             TokenId::VariableStart => "the start of a variable",
             TokenId::VariableEnd => "the end of the variable definition",
             TokenId::VariableOptional => "the optional flag for a variable",
             TokenId::VariableRepeatStart => "the repeats flag for a variable",
             TokenId::VariableRepeatEnd => "the end of the repeats option",
+            TokenId::VariableSeparator => "the separator for a repeated variable",
             TokenId::NumbersetStart => "the start of a numberset",
             TokenId::NumbersetEnd => "the end of the numberset",
+            TokenId::NumbersetExclude => "an exclusion in a numberset",
             TokenId::IntegerRange => "a number range",
             TokenId::Integer => "a number",
             TokenId::Character => "a character",
             TokenId::VariableType => "the type of a variable",
+            TokenId::VariableEndianness => "the endianness of a variable",
             TokenId::String => "a string",
+            TokenId::RawString => "a raw string",
+            TokenId::HexString => "a hex string",
+            TokenId::File => "a file path",
             TokenId::VariableValueStart => "a value of the variable",
             TokenId::VariableValueEnd => "the end of the value",
             TokenId::BlockOpen => "the opening of a block",
@@ -153,18 +203,28 @@ impl Token {
             Token::ContainerOpen(_, _) => TokenId::ContainerOpen,
             Token::ContainerClose => TokenId::ContainerClose,
             Token::OptionDef(_,_,_) => TokenId::OptionDef,
+            Token::Alias(_,_,_) => TokenId::Alias,
+            Token::Entrypoint(_,_) => TokenId::Entrypoint,
+            Token::Skip(_,_) => TokenId::Skip,
+            Token::Define(_,_,_) => TokenId::Define,
             Token::VariableStart(_) => TokenId::VariableStart,
             Token::VariableEnd => TokenId::VariableEnd,
             Token::VariableOptional(_) => TokenId::VariableOptional,
             Token::VariableRepeatStart(_) => TokenId::VariableRepeatStart,
             Token::VariableRepeatEnd => TokenId::VariableRepeatEnd,
+            Token::VariableSeparator(_) => TokenId::VariableSeparator,
             Token::NumbersetStart(_) => TokenId::NumbersetStart,
             Token::NumbersetEnd => TokenId::NumbersetEnd,
+            Token::NumbersetExclude(_) => TokenId::NumbersetExclude,
             Token::IntegerRange(_,_) => TokenId::IntegerRange,
             Token::Integer(_) => TokenId::Integer,
             Token::Character(_) => TokenId::Character,
             Token::VariableType(_) => TokenId::VariableType,
+            Token::VariableEndianness(_) => TokenId::VariableEndianness,
             Token::String(_) => TokenId::String,
+            Token::RawString(_) => TokenId::RawString,
+            Token::HexString(_) => TokenId::HexString,
+            Token::File(_) => TokenId::File,
             Token::VariableValueStart(_) => TokenId::VariableValueStart,
             Token::VariableValueEnd => TokenId::VariableValueEnd,
             Token::BlockOpen(_) => TokenId::BlockOpen,
@@ -178,18 +238,28 @@ impl Token {
             Token::ContainerOpen(pos, _) => Some(*pos),
             Token::ContainerClose => None,
             Token::OptionDef(pos,_,_) => Some(*pos),
+            Token::Alias(pos,_,_) |
+            Token::Define(pos,_,_) => Some(*pos),
+            Token::Entrypoint(pos,_) |
+            Token::Skip(pos,_) => Some(*pos),
             Token::VariableStart(pos) => Some(*pos),
             Token::VariableEnd => None,
             Token::VariableOptional(pos) => Some(*pos),
             Token::VariableRepeatStart(pos) => Some(*pos),
             Token::VariableRepeatEnd => None,
+            Token::VariableSeparator(pos) => Some(*pos),
             Token::NumbersetStart(pos) => Some(*pos),
             Token::NumbersetEnd => None,
+            Token::NumbersetExclude(pos) => Some(*pos),
             Token::IntegerRange(range,_) => Some(range.start),
             Token::Integer(range) => Some(range.start),
             Token::Character(range) => Some(range.start),
             Token::VariableType(range) => Some(range.start),
+            Token::VariableEndianness(range) => Some(range.start),
             Token::String(range) => Some(range.start),
+            Token::RawString(range) => Some(range.start),
+            Token::HexString(range) => Some(range.start),
+            Token::File(range) => Some(range.start),
             Token::VariableValueStart(pos) => Some(*pos),
             Token::VariableValueEnd => None,
             Token::BlockOpen(pos) => Some(*pos),
@@ -335,6 +405,17 @@ fn is_integer(s: &str) -> bool {
     }
 }
 
+/// charset for the content of hex strings
+#[inline]
+fn is_hex_digit(s: &str) -> bool {
+    if s.len() == 1 {
+        let c = s.as_bytes()[0];
+        c.is_ascii_hexdigit()
+    } else {
+        false
+    }
+}
+
 /// charset for chars: everything printable, escape sequences are allowed!
 #[inline]
 fn is_char(s: &str) -> bool {
@@ -372,15 +453,35 @@ impl<'a> Lexer<'a> {
             else if self.scanner.peek(keywords::OPTION) {
                 self.parse_option(&mut tokens)?;
             }
+            // Is it an alias ?
+            else if self.scanner.peek(keywords::ALIAS) {
+                self.parse_alias(&mut tokens)?;
+            }
+            // Is it an entrypoint ?
+            else if self.scanner.peek(keywords::ENTRYPOINT) {
+                self.parse_entrypoint(&mut tokens)?;
+            }
+            // Is it a skip directive ?
+            else if self.scanner.peek(keywords::SKIP) {
+                self.parse_skip(&mut tokens)?;
+            }
+            // Is it a constant ?
+            else if self.scanner.peek(keywords::DEFINE) {
+                self.parse_define(&mut tokens)?;
+            }
             // Is it a comment ?
             else if self.scanner.peek(keywords::COMMENT_OPEN) {
                 self.parse_comment()?;
             }
+            // Is it a line comment ?
+            else if self.scanner.peek(keywords::LINE_COMMENT) {
+                self.parse_line_comment()?;
+            }
             // then it must be whitespace
             else if self.scanner.skip(&mut is_whitespace) == 0 {
                 return Err(LexerError::ExpectedKeyword(
                     self.scanner.cursor,
-                    format!("{} OR {}", keywords::OPTION, keywords::CONTAINER),
+                    format!("{} OR {} OR {} OR {} OR {} OR {}", keywords::OPTION, keywords::CONTAINER, keywords::ALIAS, keywords::ENTRYPOINT, keywords::SKIP, keywords::DEFINE),
                 ));
             }
         }
@@ -418,6 +519,15 @@ impl<'a> Lexer<'a> {
         ))
     }
     
+    fn parse_line_comment(&mut self) -> Result<(), LexerError> {
+        self.scanner.expect(keywords::LINE_COMMENT)?;
+        
+        // Everything until the end of the line belongs to the comment
+        self.scanner.skip(&mut |s| s != "\n" && s != "\r\n" && s != "\r");
+        
+        Ok(())
+    }
+    
     fn parse_option(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
         let identifier_start;
         let identifier_end;
@@ -487,6 +597,187 @@ impl<'a> Lexer<'a> {
         Ok(())
     }
     
+    fn parse_alias(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
+        let alias_start = self.scanner.cursor;
+        
+        self.scanner.expect(keywords::ALIAS)?;
+        
+        // At least one whitespace required after keyword
+        if self.scanner.skip(&mut is_whitespace_nonl) == 0 {
+            return Err(LexerError::MissingWhitespace(
+                self.scanner.cursor
+            ));
+        }
+        
+        // First comes the new name
+        let new_start = self.scanner.cursor;
+        let new_end = match self.scanner.skip(&mut is_identifier) {
+            0 => {
+                return Err(LexerError::ExpectedIdentifier(
+                    self.scanner.cursor
+                ));
+            },
+            len => new_start + len,
+        };
+        
+        // Both names are separated by whitespace
+        if self.scanner.skip(&mut is_whitespace_nonl) == 0 {
+            return Err(LexerError::MissingWhitespace(
+                self.scanner.cursor
+            ));
+        }
+        
+        // Then the name of the existing container
+        let old_start = self.scanner.cursor;
+        let old_end = match self.scanner.skip(&mut is_identifier) {
+            0 => {
+                return Err(LexerError::ExpectedIdentifier(
+                    self.scanner.cursor
+                ));
+            },
+            len => old_start + len,
+        };
+        
+        // after the name whitespaces may follow
+        self.scanner.skip(&mut is_whitespace_nonl);
+        
+        // and an alias ends with ';'
+        self.scanner.expect(keywords::TERMINATE_STATEMENT)?;
+        
+        tokens.push(
+            Token::Alias(
+                alias_start,
+                SourceRange::new(new_start, new_end),
+                SourceRange::new(old_start, old_end),
+            )
+        );
+        
+        Ok(())
+    }
+    
+    /// Directives of the form `<keyword> <name>;`
+    fn parse_name_directive(&mut self, keyword: &'static str) -> Result<(usize, SourceRange), LexerError> {
+        let directive_start = self.scanner.cursor;
+        
+        self.scanner.expect(keyword)?;
+        
+        // At least one whitespace required after keyword
+        if self.scanner.skip(&mut is_whitespace_nonl) == 0 {
+            return Err(LexerError::MissingWhitespace(
+                self.scanner.cursor
+            ));
+        }
+        
+        // Then the name of the container
+        let name_start = self.scanner.cursor;
+        let name_end = match self.scanner.skip(&mut is_identifier) {
+            0 => {
+                return Err(LexerError::ExpectedIdentifier(
+                    self.scanner.cursor
+                ));
+            },
+            len => name_start + len,
+        };
+        
+        // after the name whitespaces may follow
+        self.scanner.skip(&mut is_whitespace_nonl);
+        
+        // and the directive ends with ';'
+        self.scanner.expect(keywords::TERMINATE_STATEMENT)?;
+        
+        Ok((directive_start, SourceRange::new(name_start, name_end)))
+    }
+    
+    fn parse_entrypoint(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
+        let (start, name) = self.parse_name_directive(keywords::ENTRYPOINT)?;
+        tokens.push(Token::Entrypoint(start, name));
+        Ok(())
+    }
+    
+    fn parse_skip(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
+        let (start, name) = self.parse_name_directive(keywords::SKIP)?;
+        tokens.push(Token::Skip(start, name));
+        Ok(())
+    }
+    
+    fn parse_define(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
+        let define_start = self.scanner.cursor;
+        
+        self.scanner.expect(keywords::DEFINE)?;
+        
+        // At least one whitespace required after keyword
+        if self.scanner.skip(&mut is_whitespace_nonl) == 0 {
+            return Err(LexerError::MissingWhitespace(
+                self.scanner.cursor
+            ));
+        }
+        
+        // Then the name of the constant
+        let name_start = self.scanner.cursor;
+        let name_end = match self.scanner.skip(&mut is_identifier) {
+            0 => {
+                return Err(LexerError::ExpectedIdentifier(
+                    self.scanner.cursor
+                ));
+            },
+            len => name_start + len,
+        };
+        
+        // Name and value are separated by whitespace
+        if self.scanner.skip(&mut is_whitespace_nonl) == 0 {
+            return Err(LexerError::MissingWhitespace(
+                self.scanner.cursor
+            ));
+        }
+        
+        // The value is either a string literal or an integer
+        let value = if self.scanner.peek(keywords::STRING_DELIM) {
+            let value_start = self.scanner.cursor;
+            self.parse_string_literal(&mut Vec::new())?;
+            SourceRange::new(value_start, self.scanner.cursor)
+        } else {
+            let value_start = self.scanner.cursor;
+            match self.scanner.skip(&mut is_integer) {
+                0 => {
+                    return Err(LexerError::ExpectedLiteral(
+                        self.scanner.cursor,
+                        "string OR integer".to_string(),
+                    ));
+                },
+                len => SourceRange::new(value_start, value_start + len),
+            }
+        };
+        
+        // after the value whitespaces may follow
+        self.scanner.skip(&mut is_whitespace_nonl);
+        
+        // and a definition ends with ';'
+        self.scanner.expect(keywords::TERMINATE_STATEMENT)?;
+        
+        tokens.push(
+            Token::Define(define_start, SourceRange::new(name_start, name_end), value)
+        );
+        
+        Ok(())
+    }
+    
+    /// An integer in a numberset is either a literal or the name of a constant
+    fn skip_integer(&mut self) -> usize {
+        if self.scanner.peek(keywords::CONSTANT_PREFIX) {
+            self.scanner.forward(keywords::CONSTANT_PREFIX.len());
+            
+            match self.scanner.skip(&mut is_identifier) {
+                0 => {
+                    self.scanner.revert(keywords::CONSTANT_PREFIX.len());
+                    0
+                },
+                len => keywords::CONSTANT_PREFIX.len() + len,
+            }
+        } else {
+            self.scanner.skip(&mut is_integer)
+        }
+    }
+    
     fn parse_container(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
         let name_start;
         let name_end;
@@ -563,6 +854,10 @@ impl<'a> Lexer<'a> {
             else if self.scanner.peek(keywords::COMMENT_OPEN) {
                 self.parse_comment()?;
             }
+            // Is it a line comment ?
+            else if self.scanner.peek(keywords::LINE_COMMENT) {
+                self.parse_line_comment()?;
+            }
             // Otherwise it must be a variable definition
             else {
                 self.parse_variable_definition(tokens)?;
@@ -616,6 +911,27 @@ impl<'a> Lexer<'a> {
                     ));
                 }
             }
+            // separator for repetitions ?
+            else if self.scanner.peek(keywords::VAROPT_SEPARATED) {
+                tokens.push(Token::VariableSeparator(self.scanner.cursor));
+                self.scanner.forward(keywords::VAROPT_SEPARATED.len());
+                
+                // white space must follow the keyword
+                if self.scanner.skip(&mut is_whitespace_nonl) == 0 {
+                    return Err(LexerError::MissingWhitespace(
+                        self.scanner.cursor
+                    ));
+                }
+                
+                self.parse_string_literal(tokens)?;
+                
+                // white space must follow after the separator
+                if self.scanner.skip(&mut is_whitespace_nonl) == 0 {
+                    return Err(LexerError::MissingWhitespace(
+                        self.scanner.cursor
+                    ));
+                }
+            }
             // No known option, assume its a variable name
             else {
                 break;
@@ -652,6 +968,23 @@ impl<'a> Lexer<'a> {
         
         tokens.push(Token::VariableType(SourceRange::new(type_start, type_end)));
         
+        // The type may be followed by an endianness annotation
+        if self.scanner.peek(keywords::VAR_TYPE_SEP) {
+            self.scanner.forward(keywords::VAR_TYPE_SEP.len());
+            
+            let endianness_start = self.scanner.cursor;
+            let endianness_end = match self.scanner.skip(&mut is_identifier) {
+                0 => {
+                    return Err(LexerError::ExpectedIdentifier(
+                        self.scanner.cursor
+                    ));
+                },
+                len => endianness_start + len,
+            };
+            
+            tokens.push(Token::VariableEndianness(SourceRange::new(endianness_start, endianness_end)));
+        }
+        
         // Optionally whitespaces may follow the type
         self.scanner.skip(&mut is_whitespace_nonl);
         
@@ -666,12 +999,18 @@ impl<'a> Lexer<'a> {
             // After an equals sign we either expect a string literal or a numberset
             if self.scanner.peek(keywords::STRING_DELIM) {
                 self.parse_string_literal(tokens)?;
-            } else if self.scanner.check(&mut |s| s == keywords::CHAR_DELIM || is_integer(s)) {
+            } else if self.scanner.peek(keywords::RAW_STRING_PREFIX) {
+                self.parse_raw_string_literal(tokens)?;
+            } else if self.scanner.peek(keywords::HEX_STRING_PREFIX) {
+                self.parse_hex_string_literal(tokens)?;
+            } else if self.scanner.peek(keywords::EMBED_FILE) {
+                self.parse_file_literal(tokens)?;
+            } else if self.scanner.check(&mut |s| s == keywords::CHAR_DELIM || s == keywords::NUMBERSET_EXCLUDE || s == keywords::CONSTANT_PREFIX || is_integer(s)) {
                 self.parse_numberset(tokens)?;
             } else {
                 return Err(LexerError::ExpectedLiteral(
                     self.scanner.cursor,
-                    "string OR numberset".to_string(),
+                    "string OR file OR numberset".to_string(),
                 ));
             }
             
@@ -705,6 +1044,13 @@ impl<'a> Lexer<'a> {
         tokens.push(Token::NumbersetStart(self.scanner.cursor));
         
         while !self.scanner.done() {
+            // Shall the entry be excluded ?
+            if self.scanner.peek(keywords::NUMBERSET_EXCLUDE) {
+                tokens.push(Token::NumbersetExclude(self.scanner.cursor));
+                self.scanner.forward(keywords::NUMBERSET_EXCLUDE.len());
+                self.scanner.skip(&mut is_whitespace_nonl);
+            }
+            
             // Do we have a simple char ?
             if self.scanner.peek(keywords::CHAR_DELIM) {
                 let (char_start, char_end) = self.parse_char_literal()?;
@@ -729,7 +1075,7 @@ impl<'a> Lexer<'a> {
             // Otherwise we must have a number
             else {
                 let number_start = self.scanner.cursor;
-                let number_end = match self.scanner.skip(&mut is_integer) {
+                let number_end = match self.skip_integer() {
                     0 => {
                         return Err(LexerError::InvalidNumber(
                             number_start
@@ -745,7 +1091,7 @@ impl<'a> Lexer<'a> {
                     self.scanner.skip(&mut is_whitespace_nonl);
                     
                     let limit_start = self.scanner.cursor;
-                    let limit_end = match self.scanner.skip(&mut is_integer) {
+                    let limit_end = match self.skip_integer() {
                         0 => {
                             return Err(LexerError::InvalidNumber(
                                 limit_start
@@ -844,6 +1190,56 @@ impl<'a> Lexer<'a> {
         
         Ok(())
     }
+    
+    fn parse_raw_string_literal(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
+        self.scanner.expect(keywords::RAW_STRING_PREFIX)?;
+        self.scanner.expect(keywords::STRING_DELIM)?;
+        
+        // Everything up to the next '"' belongs to the string, escape sequences don't exist here
+        let string_start = self.scanner.cursor;
+        let string_end = string_start + self.scanner.skip(&mut |s| s != keywords::STRING_DELIM && is_char(s));
+        
+        tokens.push(Token::RawString(SourceRange::new(string_start, string_end)));
+        
+        self.scanner.expect(keywords::STRING_DELIM)?;
+        
+        Ok(())
+    }
+    
+    fn parse_hex_string_literal(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
+        self.scanner.expect(keywords::HEX_STRING_PREFIX)?;
+        self.scanner.expect(keywords::STRING_DELIM)?;
+        
+        // Hex digits may be grouped by whitespaces, the parser checks that they form complete bytes
+        let string_start = self.scanner.cursor;
+        let string_end = string_start + self.scanner.skip(&mut |s| is_hex_digit(s) || is_whitespace_nonl(s));
+        
+        tokens.push(Token::HexString(SourceRange::new(string_start, string_end)));
+        
+        self.scanner.expect(keywords::STRING_DELIM)?;
+        
+        Ok(())
+    }
+    
+    fn parse_file_literal(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexerError> {
+        self.scanner.expect(keywords::EMBED_FILE)?;
+        self.scanner.skip(&mut is_whitespace_nonl);
+        self.scanner.expect(keywords::ARGS_OPEN)?;
+        self.scanner.skip(&mut is_whitespace_nonl);
+        self.scanner.expect(keywords::STRING_DELIM)?;
+        
+        // The path is taken verbatim like a raw string
+        let path_start = self.scanner.cursor;
+        let path_end = path_start + self.scanner.skip(&mut |s| s != keywords::STRING_DELIM && is_char(s));
+        
+        tokens.push(Token::File(SourceRange::new(path_start, path_end)));
+        
+        self.scanner.expect(keywords::STRING_DELIM)?;
+        self.scanner.skip(&mut is_whitespace_nonl);
+        self.scanner.expect(keywords::ARGS_CLOSE)?;
+        
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -909,6 +1305,21 @@ mod tests {
         Lexer::new(&view).lex().unwrap();
     }
     
+    #[test]
+    fn line_comments() {
+        let input = "// comment\nstruct x{// comment\nx:y; // comment\n// comment\n}// comment";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn line_comment_hides_code() {
+        let input = "struct x{x:y; // }";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
     #[test]
     #[should_panic]
     fn option_no_assignment() {
@@ -961,6 +1372,13 @@ mod tests {
         Lexer::new(&view).lex().unwrap();
     }
     
+    #[test]
+    fn valid_numberset_exclusion() {
+        let input = "struct x{x:u8=0..255,!0x0a, ! '\"';y:u8=!0;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
     #[test]
     fn valid_numberset_chars() {
         let input = "struct x{x:u16='A','SS','D','FF';}";
@@ -990,6 +1408,66 @@ mod tests {
         Lexer::new(&view).lex().unwrap();
     }
     
+    #[test]
+    fn valid_raw_string_literal() {
+        let input = "struct x{x:string=r\"C:\\Windows\\\";y:bytes=r\"\\d+\\.\\d+\";}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn unclosed_raw_string_literal() {
+        let input = "struct x{x:string=r\"\\;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn valid_hex_string_literal() {
+        let input = "struct x{x:bytes=x\"DE AD be ef\";y:bytes=x\"7f454c46\";}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn invalid_hex_string_literal() {
+        let input = "struct x{x:bytes=x\"DE AD GG\";}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn valid_file_literal() {
+        let input = "struct x{x:bytes=file(\"payload.bin\");y:bytes=file ( \"../a b.txt\" );}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn unclosed_file_literal() {
+        let input = "struct x{x:bytes=file(\"payload.bin\";}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn variable_endianness() {
+        let input = "struct x{x:u32:be=1..5;y:u16:le;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn variable_endianness_missing() {
+        let input = "struct x{x:u32:=1..5;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
     #[test]
     fn duplicate_variable_flags() {
         let input = "struct x{optional optional optional repeats 3 repeats 4 x:x;}";
@@ -997,6 +1475,80 @@ mod tests {
         Lexer::new(&view).lex().unwrap();
     }
     
+    #[test]
+    fn separated_repeats() {
+        let input = "struct x{repeats 1..10 separated \", \" x:u8;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn separated_missing_string() {
+        let input = "struct x{repeats 1..10 separated x:u8;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn valid_alias() {
+        let input = "alias Root Request;\nstruct Request{x:u8;}alias  Other\tRequest ;";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn incomplete_alias() {
+        let input = "alias Root;struct Request{x:u8;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn valid_entrypoint() {
+        let input = "entrypoint Request;struct Request{x:u8;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn valid_skip() {
+        let input = "skip Legacy ;struct Root{x:u8;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn skip_without_name() {
+        let input = "skip;struct Root{x:u8;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn valid_define() {
+        let input = "define PORT 0x1f90;define HOST \"localhost\";struct Root{x:u16=$PORT;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    #[should_panic]
+    fn define_without_value() {
+        let input = "define PORT;struct Root{x:u8;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
+    #[test]
+    fn constant_in_numberset_range() {
+        let input = "struct Root{repeats $MIN..$MAX x:u8=!$MIN;}";
+        let view = SourceView::new(input);
+        Lexer::new(&view).lex().unwrap();
+    }
+    
     #[test]
     fn multiple_structs() {
         let input = "struct x{}struct x{}";
@@ -7,6 +7,9 @@ mod range;
 pub mod keywords;
 pub mod graph;
 pub mod stats;
+pub mod derivation;
+pub mod interpreter;
+pub mod format;
 pub use lexer::{Lexer, LexerError};
 pub use parser::{Parser, ParserError};
 pub use source_view::{SourceView, SourceRange};
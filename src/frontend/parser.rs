@@ -18,9 +18,13 @@ use crate::{
     },
 };
 use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 use num_traits::{
     Num,
     cast::NumCast,
+    bounds::Bounded,
 };
 
 #[derive(Debug)]
@@ -40,9 +44,15 @@ pub enum ParserError {
     InvalidString(SourceRange, String),
     NoRoot,
     UnresolvedRef(SourceRange),
+    UnresolvedRefs(Vec<SourceRange>),
+    UnknownStruct(String),
     EmptyBlock(usize),
     IllegalContainerName(SourceRange),
     NonLocalOption(SourceRange),
+    NonGlobalOption(SourceRange),
+    PrunedEntrypoint(String),
+    DuplicateConstantName(SourceRange),
+    UnknownConstant(SourceRange),
     IllegalOptionValue(SourceRange),
 }
 
@@ -125,55 +135,380 @@ fn hex_to_dec(c: u8) -> u8 {
     }
 }
 
+/// Sort the ranges of a numberset and merge overlapping or adjacent ranges
+fn minimize_ranges<T>(ranges: &mut Vec<Range<T>>)
+where
+    T: Num + Copy + core::cmp::Ord,
+{
+    ranges.sort_by_key(|range| (range.start, range.end));
+    
+    let mut i = 0;
+    while i < ranges.len() - 1 {
+        if ranges[i] == ranges[i + 1] {
+            ranges.remove(i + 1);
+            i = i.wrapping_sub(1)
+        } else if ranges[i].end >= ranges[i + 1].start || ranges[i].end + T::one() == ranges[i + 1].start {
+            // combine adjacent ranges
+            let a = ranges.remove(i);
+            let b = ranges.remove(i);
+            ranges.insert(i, Range::new(a.start, std::cmp::max(a.end, b.end)));
+            i = i.wrapping_sub(1)
+        }
+        
+        i = i.wrapping_add(1);
+    }
+}
+
+/// Remove all numbers in `excluded` from the (inclusive) ranges in `ranges`
+fn subtract_ranges<T>(ranges: &[Range<T>], excluded: &[Range<T>]) -> Vec<Range<T>>
+where
+    T: Num + Copy + core::cmp::Ord,
+{
+    let mut ret = ranges.to_vec();
+    
+    for exclusion in excluded {
+        let mut remaining = Vec::new();
+        
+        for range in ret {
+            if exclusion.end < range.start || exclusion.start > range.end {
+                remaining.push(range);
+                continue;
+            }
+            
+            if exclusion.start > range.start {
+                remaining.push(Range::new(range.start, exclusion.start - T::one()));
+            }
+            
+            if exclusion.end < range.end {
+                remaining.push(Range::new(exclusion.end + T::one(), range.end));
+            }
+        }
+        
+        ret = remaining;
+    }
+    
+    ret
+}
+
+/// Names that cannot be given to a container
+fn is_reserved_name(name: &str) -> bool {
+    matches!(
+        name,
+        keywords::CONTAINER |
+        keywords::TYPE_U8 |
+        keywords::TYPE_I8 |
+        keywords::TYPE_U16 |
+        keywords::TYPE_I16 |
+        keywords::TYPE_U32 |
+        keywords::TYPE_I32 |
+        keywords::TYPE_U64 |
+        keywords::TYPE_I64 |
+        keywords::TYPE_ONEOF |
+        keywords::TYPE_STRING |
+        keywords::TYPE_BYTES |
+        keywords::TYPE_CHAR |
+        keywords::TYPE_EPSILON
+    )
+}
+
 pub struct Parser<'a> {
     scanner: TokenScanner<'a>,
     options_stack: Vec<ContainerOptions>,
+    undefined_allowed: Vec<String>,
+    aliases: Vec<(&'a str, ContainerId)>,
+    constants: Vec<(&'a str, SourceRange)>,
+    used_constants: RefCell<BTreeSet<&'a str>>,
+    overridden_constants: Vec<String>,
+    focus: Option<String>,
+    entrypoints: Vec<String>,
+    prune: Vec<String>,
+    include_dir: PathBuf,
+    files: Vec<PathBuf>,
 }
 impl<'a> Parser<'a> {
     pub fn new(view: &'a SourceView, tokens: &'a [Token]) -> Self {
         Self {
             scanner: TokenScanner::new(view, tokens),
             options_stack: Vec::<ContainerOptions>::new(),
+            undefined_allowed: Vec::new(),
+            aliases: Vec::new(),
+            constants: Vec::new(),
+            used_constants: RefCell::new(BTreeSet::new()),
+            overridden_constants: Vec::new(),
+            focus: None,
+            entrypoints: Vec::new(),
+            prune: Vec::new(),
+            include_dir: PathBuf::new(),
+            files: Vec::new(),
+        }
+    }
+    
+    /// References to undefined structs that match `pattern` don't result in an error
+    /// but in a call to an external function. A trailing '*' in the pattern
+    /// matches any suffix.
+    pub fn allow_undefined(&mut self, pattern: &str) {
+        self.undefined_allowed.push(pattern.to_string());
+    }
+    
+    /// Directory that relative paths in `file()` literals are resolved against
+    pub fn include_dir(&mut self, dir: &Path) {
+        self.include_dir = dir.to_path_buf();
+    }
+    
+    /// Allow the constant `name` to be defined twice, the last definition wins.
+    /// This is how constants from the command line replace the ones of the grammar.
+    pub fn override_constant(&mut self, name: &str) {
+        self.overridden_constants.push(name.to_string());
+    }
+    
+    /// Use the struct `name` as the root, regardless of the entrypoint in the grammar.
+    /// Structs that are given after the first one become additional entrypoints.
+    pub fn entrypoint(&mut self, name: &str) {
+        self.entrypoints.push(name.to_string());
+    }
+    
+    /// Start generation at the struct `name` instead of the root.
+    /// All other structs keep their IDs.
+    pub fn focus(&mut self, name: &str) {
+        self.focus = Some(name.to_string());
+    }
+    
+    /// Remove the struct `name` and everything that is only reachable through it
+    pub fn prune(&mut self, name: &str) {
+        self.prune.push(name.to_string());
+    }
+    
+    fn is_undefined_allowed(&self, name: &str) -> bool {
+        for pattern in &self.undefined_allowed {
+            let matches = if let Some(prefix) = pattern.strip_suffix('*') {
+                name.starts_with(prefix)
+            } else {
+                name == pattern
+            };
+            
+            if matches {
+                return true;
+            }
         }
+        
+        false
     }
     
     pub fn parse(&mut self) -> Result<Grammar, ParserError> {
         let mut grammar = Grammar::new();
         
+        // Constants may be used before their definition
+        self.collect_constants()?;
+        
         // Before any containers appear a user might define some global options
-        *grammar.options_mut() = self.parse_options_list()?;
+        *grammar.options_mut() = self.parse_options_list(None)?;
+        
+        // Now only containers, aliases, constants, the entrypoint and skip directives may follow
+        let mut aliases = Vec::<(SourceRange, SourceRange)>::new();
+        let mut entrypoint: Option<SourceRange> = None;
+        let mut skips = Vec::<SourceRange>::new();
         
-        // Now only containers may follow
         while !self.scanner.done() {
-            let container = self.parse_container(&mut grammar)?;
-            grammar.add_container(container);
+            match self.scanner.current() {
+                Some(Token::Alias(_, new, old)) => {
+                    aliases.push((new.clone(), old.clone()));
+                    self.scanner.forward(1);
+                },
+                Some(Token::Entrypoint(pos, name)) => {
+                    if entrypoint.is_some() {
+                        return Err(ParserError::InvalidKeyword(
+                            SourceRange::new(*pos, pos + keywords::ENTRYPOINT.len()),
+                            "Multiple entrypoints are not allowed".to_string(),
+                        ));
+                    }
+                    
+                    entrypoint = Some(name.clone());
+                    self.scanner.forward(1);
+                },
+                Some(Token::Skip(_, name)) => {
+                    skips.push(name.clone());
+                    self.scanner.forward(1);
+                },
+                Some(Token::Define(_, _, _)) => {
+                    self.scanner.forward(1);
+                },
+                _ => {
+                    let container = self.parse_container(&mut grammar)?;
+                    grammar.add_container(container);
+                },
+            }
         }
         
         assert_eq!(self.options_stack.len(), 1);
         
-        // Find the root container
-        if let Some(id) = self.find_container(&grammar, keywords::ROOT_CONTAINER) {
+        self.resolve_aliases(&grammar, &aliases)?;
+        
+        // Find the root container. The commandline overrides the grammar.
+        if let Some((name, extra)) = self.entrypoints.split_first() {
+            if let Some(id) = self.find_container(&grammar, name) {
+                grammar.set_root(id);
+            } else {
+                return Err(ParserError::UnknownStruct(name.clone()));
+            }
+            
+            for name in extra {
+                if let Some(id) = self.find_container(&grammar, name) {
+                    grammar.add_entrypoint(id);
+                } else {
+                    return Err(ParserError::UnknownStruct(name.clone()));
+                }
+            }
+        } else if let Some(name) = &entrypoint {
+            if let Some(id) = self.find_container(&grammar, self.scanner.get_source(name)) {
+                grammar.set_root(id);
+            } else {
+                return Err(ParserError::UnresolvedRef(name.clone()));
+            }
+        } else if let Some(id) = self.find_container(&grammar, keywords::ROOT_CONTAINER) {
             grammar.set_root(id);
         } else {
             return Err(ParserError::NoRoot);
         }
         
-        // Resolve container references
+        // Resolve container references and report all references that cannot be resolved at once
+        let mut unresolved = Vec::new();
+        
         for (container_id, var, name) in grammar.unresolved_names() {
             let source = self.scanner.get_source(&name);
             
-            let target = if let Some(id) = self.find_container(&grammar, source) {
-                id
+            if let Some(id) = self.find_container(&grammar, source) {
+                grammar.container_mut(container_id).unwrap().resolve_reference(var, id);
+            } else if self.is_undefined_allowed(source) {
+                grammar.container_mut(container_id).unwrap().resolve_extern(var, source.to_string());
             } else {
-                return Err(ParserError::UnresolvedRef(name.clone()));
-            };
-            
-            grammar.container_mut(container_id).unwrap().resolve_reference(var, target);
+                unresolved.push(name);
+            }
+        }
+        
+        if !unresolved.is_empty() {
+            unresolved.sort_by_key(|name| name.start);
+            return Err(ParserError::UnresolvedRefs(unresolved));
+        }
+        
+        self.prune_containers(&mut grammar, &skips)?;
+        
+        if let Some(name) = &self.focus {
+            if let Some(id) = self.find_container(&grammar, name) {
+                grammar.set_focus(id);
+            } else {
+                return Err(ParserError::UnknownStruct(name.clone()));
+            }
         }
         
         Ok(grammar)
     }
     
+    fn collect_constants(&mut self) -> Result<(), ParserError> {
+        for token in self.scanner.tokens {
+            if let Token::Define(_, name, value) = token {
+                let source = self.scanner.get_source(name);
+                
+                if let Some(idx) = self.constants.iter().position(|(other, _)| *other == source) {
+                    if !self.overridden_constants.iter().any(|other| other == source) {
+                        return Err(ParserError::DuplicateConstantName(name.clone()));
+                    }
+                    
+                    self.constants.remove(idx);
+                }
+                
+                self.constants.push((source, value.clone()));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn find_constant(&self, name: &str) -> Option<&SourceRange> {
+        let (name, value) = self.constants.iter().find(|(other, _)| *other == name)?;
+        self.used_constants.borrow_mut().insert(name);
+        Some(value)
+    }
+    
+    /// Paths of the files that `file()` literals refer to, whether they could be read or not.
+    /// Only meaningful after parse().
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+    
+    /// Names of the constants that were defined but never used.
+    /// Only meaningful after parse().
+    pub fn unused_constants(&self) -> Vec<SourceRange> {
+        let used = self.used_constants.borrow();
+        
+        self.scanner.tokens.iter().filter_map(|token| match token {
+            Token::Define(_, name, _) if !used.contains(self.scanner.get_source(name)) => Some(name.clone()),
+            _ => None,
+        }).collect()
+    }
+    
+    /// String constants are stored with their quotes
+    fn is_string_constant(&self, value: &SourceRange) -> bool {
+        self.scanner.get_source(value).starts_with(keywords::STRING_DELIM)
+    }
+    
+    /// If `literal` refers to a constant, return the location of its value instead
+    fn resolve_integer(&self, literal: &SourceRange) -> Result<SourceRange, ParserError> {
+        let source = self.scanner.get_source(literal);
+        
+        if let Some(name) = source.strip_prefix(keywords::CONSTANT_PREFIX) {
+            match self.find_constant(name) {
+                Some(value) if self.is_string_constant(value) => Err(ParserError::InvalidKeyword(
+                    literal.clone(),
+                    format!("Constant '{}' is not an integer", name),
+                )),
+                Some(value) => Ok(value.clone()),
+                None => Err(ParserError::UnknownConstant(literal.clone())),
+            }
+        } else {
+            Ok(literal.clone())
+        }
+    }
+    
+    fn prune_containers(&mut self, grammar: &mut Grammar, skips: &[SourceRange]) -> Result<(), ParserError> {
+        let mut ids = Vec::new();
+        
+        for name in skips {
+            match self.find_container(grammar, self.scanner.get_source(name)) {
+                Some(id) => ids.push((id, self.scanner.get_source(name).to_string())),
+                None => {
+                    return Err(ParserError::UnresolvedRef(name.clone()));
+                },
+            }
+        }
+        
+        for name in &self.prune {
+            match self.find_container(grammar, name) {
+                Some(id) => ids.push((id, name.clone())),
+                None => {
+                    return Err(ParserError::UnknownStruct(name.clone()));
+                },
+            }
+        }
+        
+        if ids.is_empty() {
+            return Ok(());
+        }
+        
+        for (id, name) in &ids {
+            if grammar.root() == Some(id) || grammar.entrypoints().contains(id) {
+                return Err(ParserError::PrunedEntrypoint(name.clone()));
+            }
+        }
+        
+        let ids: Vec<ContainerId> = ids.into_iter().map(|(id, _)| id).collect();
+        grammar.prune(&ids);
+        
+        // Aliases of removed structs vanish with them
+        self.aliases.retain(|(_, id)| grammar.container(*id).is_some());
+        
+        Ok(())
+    }
+    
     fn find_container(&self, grammar: &Grammar, dest: &str) -> Option<ContainerId> {
         for container in grammar.containers() {
             if let Some(name) = container.name() {
@@ -183,10 +518,61 @@ impl<'a> Parser<'a> {
             }
         }
         
+        for (name, id) in &self.aliases {
+            if *name == dest {
+                return Some(*id);
+            }
+        }
+        
         None
     }
     
-    fn parse_options_list(&mut self) -> Result<ContainerOptions, ParserError> {
+    fn resolve_aliases(&mut self, grammar: &Grammar, aliases: &[(SourceRange, SourceRange)]) -> Result<(), ParserError> {
+        for (i, (new, _)) in aliases.iter().enumerate() {
+            let source = self.scanner.get_source(new);
+            
+            if is_reserved_name(source) {
+                return Err(ParserError::IllegalContainerName(new.clone()));
+            }
+            
+            // An alias must neither shadow a struct nor another alias
+            if self.find_container(grammar, source).is_some() ||
+               aliases[..i].iter().any(|(other, _)| self.scanner.get_source(other) == source) {
+                return Err(ParserError::DuplicateContainerName(new.clone()));
+            }
+        }
+        
+        // Aliases may refer to other aliases, so follow each chain until a
+        // struct is found. A chain longer than the number of aliases is a cycle.
+        for (new, old) in aliases {
+            let mut target = old;
+            let mut hops = 0;
+            
+            let id = loop {
+                let source = self.scanner.get_source(target);
+                
+                if let Some(id) = self.find_container(grammar, source) {
+                    break id;
+                }
+                
+                match aliases.iter().find(|(other, _)| self.scanner.get_source(other) == source) {
+                    Some((_, next)) if hops < aliases.len() => {
+                        target = next;
+                        hops += 1;
+                    },
+                    _ => {
+                        return Err(ParserError::UnresolvedRef(old.clone()));
+                    },
+                }
+            };
+            
+            self.aliases.push((self.scanner.get_source(new), id));
+        }
+        
+        Ok(())
+    }
+    
+    fn parse_options_list(&mut self, owner: Option<ContainerId>) -> Result<ContainerOptions, ParserError> {
         let is_global = self.options_stack.is_empty();
         
         let mut ret = if let Some(elem) = self.options_stack.last() {
@@ -215,6 +601,7 @@ impl<'a> Parser<'a> {
                             let value = match self.scanner.get_source(value) {
                                 "round-robin" => Scheduling::RoundRobin,
                                 "random" => Scheduling::Random,
+                                "adaptive" => Scheduling::Adaptive,
                                 _ => {
                                     return Err(ParserError::UnknownOptionValue(value.clone()));
                                 }
@@ -244,6 +631,36 @@ impl<'a> Parser<'a> {
                             
                             ret.set_depth(value);
                         },
+                        keywords::OPTION_RECURSION => {
+                            let owner = match owner {
+                                Some(owner) => owner,
+                                None => {
+                                    return Err(ParserError::NonGlobalOption(key.clone()));
+                                },
+                            };
+                            
+                            match self.scanner.get_source(value).parse::<usize>() {
+                                Ok(limit) if limit > 0 => ret.set_recursion(owner, limit),
+                                _ => {
+                                    return Err(ParserError::IllegalOptionValue(value.clone()));
+                                },
+                            }
+                        },
+                        keywords::OPTION_MAX_LEN => {
+                            let owner = match owner {
+                                Some(owner) => owner,
+                                None => {
+                                    return Err(ParserError::NonGlobalOption(key.clone()));
+                                },
+                            };
+                            
+                            match self.scanner.get_source(value).parse::<usize>() {
+                                Ok(limit) => ret.set_max_len(owner, limit),
+                                _ => {
+                                    return Err(ParserError::IllegalOptionValue(value.clone()));
+                                },
+                            }
+                        },
                         _ => {
                             return Err(ParserError::UnknownOptionName(key.clone()));
                         },
@@ -267,23 +684,8 @@ impl<'a> Parser<'a> {
                 let source = self.scanner.get_source(&name);
                 
                 /* check that name isn't a keyword */
-                match source {
-                    keywords::CONTAINER |
-                    keywords::TYPE_U8 |
-                    keywords::TYPE_I8 |
-                    keywords::TYPE_U16 |
-                    keywords::TYPE_I16 |
-                    keywords::TYPE_U32 |
-                    keywords::TYPE_I32 |
-                    keywords::TYPE_U64 |
-                    keywords::TYPE_I64 |
-                    keywords::TYPE_ONEOF |
-                    keywords::TYPE_STRING |
-                    keywords::TYPE_BYTES |
-                    keywords::TYPE_CHAR => {
-                        return Err(ParserError::IllegalContainerName(name.clone()));
-                    },
-                    _ => {},
+                if is_reserved_name(source) {
+                    return Err(ParserError::IllegalContainerName(name.clone()));
                 }
                 
                 /* check if name already exists */
@@ -320,7 +722,7 @@ impl<'a> Parser<'a> {
         };
         
         // Options may be overwritten in a block
-        *container.options_mut() = self.parse_options_list()?;
+        *container.options_mut() = self.parse_options_list(Some(container.id()))?;
         
         // After options variables must follow
         while let Some(token) = self.scanner.current() {
@@ -364,6 +766,7 @@ impl<'a> Parser<'a> {
         // Parse variable options
         let mut had_optional = false;
         let mut had_repeats = false;
+        let mut separator_pos = None;
         let mut var_opts = VariableOptions::default();
         
         while let Some(token) = self.scanner.current() {
@@ -393,6 +796,22 @@ impl<'a> Parser<'a> {
                     var_opts.set_repeats(id);
                     had_repeats = true;
                 },
+                Token::VariableSeparator(pos) => {
+                    let pos = *pos;
+                    
+                    if separator_pos.is_some() {
+                        return Err(ParserError::InvalidKeyword(
+                            SourceRange::new(pos, pos + keywords::VAROPT_SEPARATED.len()),
+                            "Multiple occurences of variable options not allowed".to_string(),
+                        ));
+                    }
+                    
+                    self.scanner.forward(1);
+                    let id = self.parse_string_literal(grammar, false)?;
+                    var_opts.set_separator(id);
+                    separator_pos = Some(pos);
+                    continue;
+                },
                 _ => {
                     break;
                 },
@@ -401,6 +820,15 @@ impl<'a> Parser<'a> {
             self.scanner.forward(1);
         }
         
+        if let Some(pos) = separator_pos {
+            if !had_repeats {
+                return Err(ParserError::InvalidKeyword(
+                    SourceRange::new(pos, pos + keywords::VAROPT_SEPARATED.len()),
+                    format!("A separator can only be used together with '{}'", keywords::VAROPT_REPEATS),
+                ));
+            }
+        }
+        
         let type_name = match self.scanner.expect(TokenId::VariableType)? {
             Token::VariableType(name) => {
                 name.clone()
@@ -408,12 +836,50 @@ impl<'a> Parser<'a> {
             _ => unreachable!(),
         };
         
+        if let Some(Token::VariableEndianness(endianness)) = self.scanner.current() {
+            match self.scanner.get_source(&type_name) {
+                keywords::TYPE_U8 |
+                keywords::TYPE_I8 |
+                keywords::TYPE_U16 |
+                keywords::TYPE_I16 |
+                keywords::TYPE_U32 |
+                keywords::TYPE_I32 |
+                keywords::TYPE_U64 |
+                keywords::TYPE_I64 |
+                keywords::TYPE_CHAR => {},
+                _ => {
+                    return Err(ParserError::InvalidKeyword(
+                        type_name.clone(),
+                        "Only number types can have an endianness".to_string(),
+                    ));
+                },
+            }
+            
+            let value = match self.scanner.get_source(endianness) {
+                keywords::ENDIANNESS_LITTLE => Endianness::Little,
+                keywords::ENDIANNESS_BIG => Endianness::Big,
+                keywords::ENDIANNESS_NATIVE => Endianness::Native,
+                _ => {
+                    return Err(ParserError::InvalidKeyword(
+                        endianness.clone(),
+                        format!("Expected '{}', '{}' or '{}'", keywords::ENDIANNESS_LITTLE, keywords::ENDIANNESS_BIG, keywords::ENDIANNESS_NATIVE),
+                    ));
+                },
+            };
+            
+            var_opts.set_endianness(value);
+            self.scanner.forward(1);
+        }
+        
         let var_type = match self.scanner.current() {
             Some(Token::VariableValueStart(_)) => {
                 self.scanner.forward(1);
                 
                 let ret = match self.scanner.current() {
-                    Some(Token::String(_)) => {
+                    Some(Token::String(_)) |
+                    Some(Token::RawString(_)) |
+                    Some(Token::HexString(_)) |
+                    Some(Token::File(_)) => {
                         let is_binary = match self.scanner.get_source(&type_name) {
                             keywords::TYPE_STRING => false,
                             keywords::TYPE_BYTES => true,
@@ -423,7 +889,12 @@ impl<'a> Parser<'a> {
                         };
                         
                         // parse string binary or not
-                        let id = self.parse_string_literal(grammar, is_binary)?;
+                        let id = match self.scanner.current() {
+                            Some(Token::RawString(_)) => self.parse_raw_string_literal(grammar)?,
+                            Some(Token::HexString(_)) => self.parse_hex_string_literal(grammar, is_binary)?,
+                            Some(Token::File(_)) => self.parse_file_literal(grammar, is_binary)?,
+                            _ => self.parse_string_literal(grammar, is_binary)?,
+                        };
                         
                         if is_binary {
                             VariableType::Bytes(BytearrayValue::Literal(id))
@@ -558,6 +1029,7 @@ impl<'a> Parser<'a> {
             keywords::TYPE_I32 => Ok(VariableType::I32(IntegerValue::Any)),
             keywords::TYPE_U64 => Ok(VariableType::U64(IntegerValue::Any)),
             keywords::TYPE_I64 => Ok(VariableType::I64(IntegerValue::Any)),
+            keywords::TYPE_EPSILON => Ok(VariableType::Epsilon),
             keywords::CONTAINER |
             keywords::TYPE_STRING |
             keywords::TYPE_BYTES |
@@ -571,7 +1043,7 @@ impl<'a> Parser<'a> {
     
     fn parse_numberset<T>(&mut self, allow_chars: bool) -> Result<Vec<Range<T>>, ParserError>
     where
-        T: Num + Copy + core::cmp::Ord + NumCast + std::fmt::Debug + FromBitPattern,
+        T: Num + Copy + core::cmp::Ord + NumCast + std::fmt::Debug + FromBitPattern + Bounded,
     {
         let numberset_start = if let Token::NumbersetStart(start) = self.scanner.expect(TokenId::NumbersetStart)? {
             *start
@@ -580,16 +1052,23 @@ impl<'a> Parser<'a> {
         };
         
         let mut ranges = Vec::<Range<T>>::new();
+        let mut excluded = Vec::<Range<T>>::new();
+        let mut exclude_next = false;
         
         while let Some(token) = self.scanner.current() {
-            match token {
+            let range = match token {
                 Token::NumbersetEnd => {
                     self.scanner.forward(1);
                     break;
                 },
+                Token::NumbersetExclude(_) => {
+                    exclude_next = true;
+                    self.scanner.forward(1);
+                    continue;
+                },
                 Token::Integer(literal) => {
                     let number = self.parse_single_integer(literal)?;
-                    ranges.push(Range::new(number, number));
+                    Range::new(number, number)
                 },
                 Token::IntegerRange(lower, upper) => {
                     let lower_number: T = self.parse_single_integer(lower)?;
@@ -602,7 +1081,7 @@ impl<'a> Parser<'a> {
                         ));
                     }
                     
-                    ranges.push(Range::new(lower_number, upper_number));
+                    Range::new(lower_number, upper_number)
                 },
                 Token::CharRange(lower, upper) => {
                     if !allow_chars {
@@ -631,7 +1110,7 @@ impl<'a> Parser<'a> {
                         return Err(ParserError::InvalidCharacter(upper.clone()));
                     };
                     
-                    ranges.push(Range::new(lower_t, upper_t));
+                    Range::new(lower_t, upper_t)
                 },
                 Token::Character(literal) => {
                     if !allow_chars {
@@ -643,40 +1122,46 @@ impl<'a> Parser<'a> {
                     let c = self.parse_char_literal(literal)?;
                     
                     if let Some(number) = T::from(c) {
-                        ranges.push(Range::new(number, number));
+                        Range::new(number, number)
                     } else {
                         return Err(ParserError::InvalidCharacter(literal.clone()));
                     }
                 },
                 _ => unreachable!(),
+            };
+            
+            // Entries either go into the numberset or get removed from it
+            if exclude_next {
+                excluded.push(range);
+                exclude_next = false;
+            } else {
+                ranges.push(range);
             }
             
             self.scanner.forward(1);
         }
         
+        // A numberset with only exclusions excludes from the whole range of the type
+        if ranges.is_empty() && !excluded.is_empty() {
+            ranges.push(Range::new(T::min_value(), T::max_value()));
+        }
+        
         if ranges.is_empty() {
             return Err(ParserError::InvalidNumberset(
                 numberset_start
             ));
         }
         
-        // Minimize ranges
-        ranges.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+        minimize_ranges(&mut ranges);
         
-        let mut i = 0;
-        while i < ranges.len() - 1 {
-            if ranges[i] == ranges[i + 1] {
-                ranges.remove(i + 1);
-                i = i.wrapping_sub(1)
-            } else if ranges[i].end >= ranges[i + 1].start || ranges[i].end + T::from(1).unwrap() == ranges[i + 1].start {
-                // combine adjacent ranges
-                let a = ranges.remove(i);
-                let b = ranges.remove(i);
-                ranges.insert(i, Range::new(a.start, b.end));
-                i = i.wrapping_sub(1)
-            }
+        if !excluded.is_empty() {
+            ranges = subtract_ranges(&ranges, &excluded);
             
-            i = i.wrapping_add(1);
+            if ranges.is_empty() {
+                return Err(ParserError::InvalidNumberset(
+                    numberset_start
+                ));
+            }
         }
         
         Ok(ranges)
@@ -686,8 +1171,21 @@ impl<'a> Parser<'a> {
     where
         T: Num + Copy + core::cmp::Ord + NumCast + FromBitPattern,
     {
+        let literal = &self.resolve_integer(literal)?;
         let source = self.scanner.get_source(literal);
         
+        // Negative numbers in a non-decimal format
+        if let Some(magnitude) = source.strip_prefix('-') {
+            for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+                if magnitude.len() > 2 && magnitude.starts_with(prefix) {
+                    return match i128::from_str_radix(&magnitude[2..], radix) {
+                        Ok(number) => T::from(-number).ok_or(ParserError::InvalidNumber(radix as usize, literal.clone())),
+                        Err(_) => Err(ParserError::InvalidNumber(radix as usize, literal.clone())),
+                    };
+                }
+            }
+        }
+        
         // Is it a hexadecimal number ?
         if source.len() > 2 && source.starts_with("0x") {
             if let Some(number) = T::from_hex_pattern(&source[2..]) {
@@ -765,15 +1263,22 @@ impl<'a> Parser<'a> {
             Token::String(literal) => literal,
             _ => unreachable!(),
         };
-        let source = self.scanner.get_source(&literal).as_bytes();
+        let buf = self.unescape_string(literal, is_binary, &mut Vec::new())?;
         
-        if source.is_empty() {
+        if buf.is_empty() {
             return Err(ParserError::InvalidString(
                 SourceRange::new(literal.start - 1, literal.end + 1),
                 "strings cannot be empty".to_string(),
             ));
         }
         
+        Ok(grammar.add_string(buf))
+    }
+    
+    /// Interpret escape sequences and replace references to constants.
+    /// `active` holds the string constants that are currently being expanded.
+    fn unescape_string(&self, literal: &SourceRange, is_binary: bool, active: &mut Vec<&'a str>) -> Result<Vec<u8>, ParserError> {
+        let source = self.scanner.get_source(literal).as_bytes();
         let mut buf = Vec::<u8>::new();
         let mut i = 0;
         
@@ -784,6 +1289,7 @@ impl<'a> Parser<'a> {
                     b'\\' => b'\\', 
                     b'r' => b'\r',
                     b'"' => b'"',
+                    b'$' => b'$',
                     b'n' => b'\n',
                     b't' => b'\t',
                     b'0' => 0,
@@ -817,6 +1323,42 @@ impl<'a> Parser<'a> {
                         ))
                     },
                 }
+            } else if source[i..].starts_with(keywords::CONSTANT_OPEN.as_bytes()) {
+                let name_start = i + keywords::CONSTANT_OPEN.len();
+                let name_len = match source[name_start..].iter().position(|c| *c == keywords::CONSTANT_CLOSE.as_bytes()[0]) {
+                    Some(len) => len,
+                    None => {
+                        return Err(ParserError::InvalidString(
+                            SourceRange::new(literal.start + i, literal.end),
+                            "Unclosed reference to a constant".to_string(),
+                        ));
+                    },
+                };
+                let name_range = SourceRange::new(literal.start + name_start, literal.start + name_start + name_len);
+                let name = self.scanner.get_source(&name_range);
+                
+                match self.find_constant(name) {
+                    Some(value) if self.is_string_constant(value) => {
+                        if active.contains(&name) {
+                            return Err(ParserError::InvalidString(
+                                name_range,
+                                format!("Constant '{}' refers to itself", name),
+                            ));
+                        }
+                        
+                        let inner = SourceRange::new(value.start + 1, value.end - 1);
+                        active.push(name);
+                        buf.extend(self.unescape_string(&inner, is_binary, active)?);
+                        active.pop();
+                    },
+                    Some(value) => buf.extend_from_slice(self.scanner.get_source(value).as_bytes()),
+                    None => {
+                        return Err(ParserError::UnknownConstant(name_range));
+                    },
+                }
+                
+                i = name_start + name_len + 1;
+                continue;
             } else {
                 source[i]
             };
@@ -826,6 +1368,137 @@ impl<'a> Parser<'a> {
             i += 1;
         }
         
+        Ok(buf)
+    }
+    
+    fn parse_raw_string_literal(&mut self, grammar: &mut Grammar) -> Result<StringId, ParserError> {
+        let literal = match self.scanner.expect(TokenId::RawString)? {
+            Token::RawString(literal) => literal,
+            _ => unreachable!(),
+        };
+        let source = self.scanner.get_source(literal).as_bytes();
+        
+        if source.is_empty() {
+            return Err(ParserError::InvalidString(
+                SourceRange::new(literal.start - 2, literal.end + 1),
+                "strings cannot be empty".to_string(),
+            ));
+        }
+        
+        Ok(grammar.add_string(source.to_vec()))
+    }
+    
+    fn parse_file_literal(&mut self, grammar: &mut Grammar, is_binary: bool) -> Result<StringId, ParserError> {
+        let literal = match self.scanner.expect(TokenId::File)? {
+            Token::File(literal) => literal,
+            _ => unreachable!(),
+        };
+        let path = self.scanner.get_source(literal);
+        
+        // Files may contain any byte, which strings can't hold
+        if !is_binary {
+            return Err(ParserError::InvalidString(
+                literal.clone(),
+                format!("File literals are only allowed in variables of type '{}'", keywords::TYPE_BYTES)
+            ));
+        }
+        
+        // Relative paths are relative to the grammar
+        let full_path = self.include_dir.join(path);
+        self.files.push(full_path.clone());
+        
+        let content = match std::fs::read(&full_path) {
+            Ok(content) => content,
+            Err(error) => {
+                return Err(ParserError::InvalidString(
+                    literal.clone(),
+                    format!("Cannot read '{}': {}", path, error),
+                ));
+            },
+        };
+        
+        if content.is_empty() {
+            return Err(ParserError::InvalidString(
+                literal.clone(),
+                "strings cannot be empty".to_string(),
+            ));
+        }
+        
+        Ok(grammar.add_string(content))
+    }
+    
+    fn parse_hex_string_literal(&mut self, grammar: &mut Grammar, is_binary: bool) -> Result<StringId, ParserError> {
+        let literal = match self.scanner.expect(TokenId::HexString)? {
+            Token::HexString(literal) => literal,
+            _ => unreachable!(),
+        };
+        let source = self.scanner.get_source(literal).as_bytes();
+        
+        if !is_binary {
+            return Err(ParserError::InvalidString(
+                SourceRange::new(literal.start - 2, literal.end + 1),
+                format!("Hex strings are only allowed in variables of type '{}'", keywords::TYPE_BYTES)
+            ));
+        }
+        
+        let mut buf = Vec::<u8>::new();
+        let mut i = 0;
+        
+        while i < source.len() {
+            if source[i] == b' ' || source[i] == b'\t' {
+                i += 1;
+                continue;
+            }
+            
+            // Digits always come in pairs
+            if i + 1 >= source.len() || !is_hex_char(source[i + 1]) {
+                return Err(ParserError::InvalidString(
+                    SourceRange::new(literal.start + i, literal.start + i + 1),
+                    "Incomplete byte in hex string".to_string(),
+                ));
+            }
+            
+            buf.push(hex_to_dec(source[i]) * 16 + hex_to_dec(source[i + 1]));
+            
+            i += 2;
+        }
+        
+        if buf.is_empty() {
+            return Err(ParserError::InvalidString(
+                SourceRange::new(literal.start - 2, literal.end + 1),
+                "strings cannot be empty".to_string(),
+            ));
+        }
+        
         Ok(grammar.add_string(buf))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::minimize_ranges;
+    use std::ops::Range;
+    use crate::frontend::range::NewRange;
+    use crate::frontend::{Lexer, Parser, ParserError, SourceView};
+    
+    #[test]
+    fn merge_adjacent_ranges() {
+        let mut ranges = vec![Range::new(5u8, 9), Range::new(0, 4), Range::new(0, 4)];
+        minimize_ranges(&mut ranges);
+        assert_eq!(ranges, vec![Range::new(0, 9)]);
+    }
+    
+    #[test]
+    fn merge_nested_ranges() {
+        let mut ranges = vec![Range::new(0u8, 100), Range::new(10, 20)];
+        minimize_ranges(&mut ranges);
+        assert_eq!(ranges, vec![Range::new(0, 100)]);
+    }
+    
+    #[test]
+    fn file_literal_in_string() {
+        let view = SourceView::new("struct Root { a: string = file(\"Cargo.toml\"); }");
+        let tokens = Lexer::new(&view).lex().unwrap();
+        assert!(matches!(Parser::new(&view, &tokens).parse(), Err(ParserError::InvalidString(..))));
+    }
+}
@@ -38,6 +38,13 @@ impl SourceView {
         SourceView::new(content)
     }
     
+    /// Add text to the end of the file. Positions of the existing content stay the same.
+    pub fn append(&mut self, text: &str) {
+        let offset = self.content.len();
+        self.graphemes.extend(UnicodeSegmentation::grapheme_indices(text, true).map(|(idx, _)| offset + idx));
+        self.content.push_str(text);
+    }
+    
     pub fn len(&self) -> usize {
         self.graphemes.len()
     }
@@ -1,23 +1,181 @@
-use crate::grammar::Grammar;
+use std::collections::BTreeMap;
+use crate::grammar::{
+    Grammar, ContainerId, ContainerType,
+    Variable, VariableType, BytearrayValue, IntegerValue,
+    NumbersetId, NumbersetType,
+};
+
+/// Share of the expected input size above which a struct
+/// is considered to dominate the generated inputs
+const DOMINANCE_THRESHOLD: f64 = 0.5;
+
+/// Mean and variance of a size in bytes
+#[derive(Clone, Copy, Default)]
+pub struct SizeEstimate {
+    pub mean: f64,
+    pub variance: f64,
+}
+impl SizeEstimate {
+    fn constant(size: usize) -> Self {
+        Self {
+            mean: size as f64,
+            variance: 0.0,
+        }
+    }
+    
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+    
+    fn second_moment(&self) -> f64 {
+        self.variance + self.mean * self.mean
+    }
+}
+
+/// How much a single struct contributes to the generated inputs
+pub struct ContainerShare {
+    pub id: ContainerId,
+    pub calls: f64,
+    pub size: SizeEstimate,
+    pub share: f64,
+}
+impl ContainerShare {
+    pub fn is_dominant(&self) -> bool {
+        self.share > DOMINANCE_THRESHOLD
+    }
+}
+
+/// Estimated number of distinct outputs of a struct, as log2
+pub struct ContainerChoices {
+    pub id: ContainerId,
+    pub bits: f64,
+}
+
+/// Size of the grammar itself, counting only what is reachable from the entrypoint
+#[derive(Default)]
+pub struct GrammarSize {
+    pub structs: usize,
+    pub oneofs: usize,
+    pub variables: usize,
+    pub max_variables: usize,
+    pub literals: usize,
+    pub literal_bytes: usize,
+    pub numbersets: usize,
+}
+impl GrammarSize {
+    /// Unlike the other statistics this works on grammars with cycles
+    pub fn from_grammar(grammar: &Grammar) -> Self {
+        let mut ret = Self::default();
+        let mut numbersets = std::collections::BTreeSet::new();
+        
+        for id in grammar.reachable_containers() {
+            let container = grammar.container(id).unwrap();
+            
+            match container.typ() {
+                ContainerType::Struct => ret.structs += 1,
+                ContainerType::Oneof => ret.oneofs += 1,
+            }
+            
+            ret.variables += container.variables().len();
+            ret.max_variables = std::cmp::max(ret.max_variables, container.variables().len());
+            
+            for var in container.variables() {
+                numbersets.extend(var.options().repeats().copied());
+                
+                match var.typ() {
+                    VariableType::String(BytearrayValue::Literal(id)) |
+                    VariableType::Bytes(BytearrayValue::Literal(id)) => {
+                        ret.literals += 1;
+                        ret.literal_bytes += grammar.strings().get(id).unwrap().len();
+                    },
+                    VariableType::String(BytearrayValue::Any(id)) |
+                    VariableType::Bytes(BytearrayValue::Any(id)) => {
+                        numbersets.insert(*id);
+                    },
+                    VariableType::U8(IntegerValue::FromSet(id)) |
+                    VariableType::I8(IntegerValue::FromSet(id)) |
+                    VariableType::U16(IntegerValue::FromSet(id)) |
+                    VariableType::I16(IntegerValue::FromSet(id)) |
+                    VariableType::U32(IntegerValue::FromSet(id)) |
+                    VariableType::I32(IntegerValue::FromSet(id)) |
+                    VariableType::U64(IntegerValue::FromSet(id)) |
+                    VariableType::I64(IntegerValue::FromSet(id)) => {
+                        numbersets.insert(*id);
+                    },
+                    _ => {},
+                }
+            }
+        }
+        
+        ret.numbersets = numbersets.len();
+        ret
+    }
+    
+    /// Average number of variables of a struct or oneof
+    pub fn avg_variables(&self) -> f64 {
+        let containers = self.structs + self.oneofs;
+        
+        if containers == 0 {
+            0.0
+        } else {
+            self.variables as f64 / containers as f64
+        }
+    }
+}
 
 /// Statistics about a grammar
 pub struct GrammarStats {
     num_paths: usize,
     max_input_size: usize,
     min_input_size: usize,
+    expected_input_size: SizeEstimate,
+    shares: Vec<ContainerShare>,
+    input_choices: f64,
+    choices: Vec<ContainerChoices>,
 }
 
 impl GrammarStats {
     pub fn from_grammar(grammar: &Grammar) -> Self {
-        let root = *grammar.root().unwrap();
+        let root = *grammar.entrypoint().unwrap();
+        
+        let mut estimator = SizeEstimator::new(grammar);
+        let expected_input_size = estimator.container(root);
+        let shares = estimator.shares(root);
+        
+        let mut estimator = ChoiceEstimator::new(grammar);
+        let input_choices = estimator.container(root);
+        let choices = estimator.choices(root);
         
         Self {
             num_paths: grammar.num_paths(root),
             max_input_size: grammar.container_size(root, true),
             min_input_size: grammar.container_size(root, false),
+            expected_input_size,
+            shares,
+            input_choices,
+            choices,
         }
     }
     
+    /// log2 of the estimated number of distinct inputs
+    pub fn input_choices(&self) -> f64 {
+        self.input_choices
+    }
+    
+    /// Structs sorted by the log2 of their estimated number of distinct outputs, largest first
+    pub fn choices(&self) -> &[ContainerChoices] {
+        &self.choices
+    }
+    
+    pub fn expected_input_size(&self) -> &SizeEstimate {
+        &self.expected_input_size
+    }
+    
+    /// Structs sorted by their share of the expected input size, largest first
+    pub fn shares(&self) -> &[ContainerShare] {
+        &self.shares
+    }
+    
     pub fn num_paths(&self) -> Option<usize> {
         if self.num_paths == usize::MAX {
             None
@@ -42,3 +200,498 @@ impl GrammarStats {
         }
     }
 }
+
+/// Estimates the distribution of input sizes assuming that every choice
+/// in a numberset or oneof is made uniformly at random and that the
+/// depth limit is not hit. Only works on acyclic grammars.
+struct SizeEstimator<'a> {
+    grammar: &'a Grammar,
+    cache: BTreeMap<ContainerId, SizeEstimate>,
+}
+impl<'a> SizeEstimator<'a> {
+    fn new(grammar: &'a Grammar) -> Self {
+        Self {
+            grammar,
+            cache: BTreeMap::new(),
+        }
+    }
+    
+    /// Mean and variance of a value drawn from a numberset
+    fn numberset(&self, id: NumbersetId) -> SizeEstimate {
+        let ranges = match self.grammar.numbersets().get(&id).unwrap() {
+            NumbersetType::U32(ranges) => ranges,
+            _ => panic!("Tried to estimate non-u32 numberset"),
+        };
+        let mut mean = 0.0;
+        let mut second_moment = 0.0;
+        
+        for range in ranges {
+            // discrete uniform distribution over [start, end]
+            let (start, end) = (range.start as f64, range.end as f64);
+            let width = end - start + 1.0;
+            let range_mean = (start + end) / 2.0;
+            mean += range_mean;
+            second_moment += (width * width - 1.0) / 12.0 + range_mean * range_mean;
+        }
+        
+        mean /= ranges.len() as f64;
+        second_moment /= ranges.len() as f64;
+        
+        SizeEstimate {
+            mean,
+            variance: (second_moment - mean * mean).max(0.0),
+        }
+    }
+    
+    /// Expected number of times the variable gets expanded
+    fn multiplicity(&self, var: &Variable) -> f64 {
+        let mut ret = 1.0;
+        
+        if var.options().optional() {
+            ret *= 0.5;
+        }
+        
+        if let Some(id) = var.options().repeats() {
+            ret *= self.numberset(*id).mean;
+        }
+        
+        ret
+    }
+    
+    fn variable(&mut self, var: &Variable) -> SizeEstimate {
+        let single = match var.typ() {
+            VariableType::Epsilon => SizeEstimate::constant(0),
+            VariableType::U8(_) |
+            VariableType::I8(_) => SizeEstimate::constant(1),
+            VariableType::U16(_) |
+            VariableType::I16(_) => SizeEstimate::constant(2),
+            VariableType::U32(_) |
+            VariableType::I32(_) => SizeEstimate::constant(4),
+            VariableType::U64(_) |
+            VariableType::I64(_) => SizeEstimate::constant(8),
+            VariableType::String(bytearray) |
+            VariableType::Bytes(bytearray) => match bytearray {
+                BytearrayValue::Any(id) => self.numberset(*id),
+                BytearrayValue::Literal(id) => SizeEstimate::constant(self.grammar.strings().get(id).unwrap().len()),
+            },
+            VariableType::Oneof(id) |
+            VariableType::ContainerRef(id) => self.container(*id),
+            // The output of external functions is unknown, don't count it
+            VariableType::ExternRef(_) => SizeEstimate::default(),
+            VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
+        };
+        let mut ret = single;
+        
+        // optional: mixture of `single` and 0
+        if var.options().optional() {
+            let second_moment = single.second_moment() / 2.0;
+            ret.mean = single.mean / 2.0;
+            ret.variance = second_moment - ret.mean * ret.mean;
+        }
+        
+        // repeats: sum of a random number of independent copies
+        if let Some(id) = var.options().repeats() {
+            let count = self.numberset(*id);
+            
+            // every copy but the last one is followed by the separator
+            let sep = match var.options().separator() {
+                Some(sep) => self.grammar.strings().get(sep).unwrap().len() as f64,
+                None => 0.0,
+            };
+            ret.mean += sep;
+            
+            let mean = count.mean * ret.mean;
+            ret.variance = count.mean * ret.variance + count.variance * ret.mean * ret.mean;
+            ret.mean = (mean - sep).max(0.0);
+        }
+        
+        ret
+    }
+    
+    fn container(&mut self, id: ContainerId) -> SizeEstimate {
+        if let Some(estimate) = self.cache.get(&id) {
+            return *estimate;
+        }
+        
+        let container = self.grammar.container(id).unwrap();
+        let mut ret = SizeEstimate::default();
+        
+        match container.typ() {
+            ContainerType::Struct => {
+                // sum of independent variables
+                for var in container.variables() {
+                    let estimate = self.variable(var);
+                    ret.mean += estimate.mean;
+                    ret.variance += estimate.variance;
+                }
+            },
+            ContainerType::Oneof => {
+                // uniform mixture of all variables
+                let n = container.variables().len() as f64;
+                let mut second_moment = 0.0;
+                
+                for var in container.variables() {
+                    let estimate = self.variable(var);
+                    ret.mean += estimate.mean / n;
+                    second_moment += estimate.second_moment() / n;
+                }
+                
+                ret.variance = (second_moment - ret.mean * ret.mean).max(0.0);
+            },
+        }
+        
+        self.cache.insert(id, ret);
+        ret
+    }
+    
+    /// Propagate the expected number of invocations from `id` to all its callees
+    fn count_calls(&self, id: ContainerId, calls: f64, result: &mut BTreeMap<ContainerId, f64>) {
+        let container = self.grammar.container(id).unwrap();
+        
+        let calls = match container.typ() {
+            ContainerType::Struct => {
+                *result.entry(id).or_insert(0.0) += calls;
+                calls
+            },
+            ContainerType::Oneof => calls / container.variables().len() as f64,
+        };
+        
+        for var in container.variables() {
+            match var.typ() {
+                VariableType::Oneof(callee) |
+                VariableType::ContainerRef(callee) => {
+                    self.count_calls(*callee, calls * self.multiplicity(var), result);
+                },
+                _ => {},
+            }
+        }
+    }
+    
+    fn shares(&mut self, root: ContainerId) -> Vec<ContainerShare> {
+        let total = self.container(root).mean;
+        let mut calls = BTreeMap::new();
+        self.count_calls(root, 1.0, &mut calls);
+        
+        let mut ret = Vec::new();
+        
+        for (id, calls) in calls {
+            if id == root {
+                continue;
+            }
+            
+            let size = self.container(id);
+            
+            ret.push(ContainerShare {
+                id,
+                calls,
+                size,
+                share: if total > 0.0 { calls * size.mean / total } else { 0.0 },
+            });
+        }
+        
+        ret.sort_by(|a, b| b.share.partial_cmp(&a.share).unwrap());
+        ret
+    }
+}
+
+/// log2(2^a + 2^b + ...) without overflowing for large exponents
+fn log2_sum<I: IntoIterator<Item = f64>>(values: I) -> f64 {
+    let values: Vec<f64> = values.into_iter().collect();
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    
+    if max.is_infinite() {
+        return max;
+    }
+    
+    max + values.iter().map(|value| (value - max).exp2()).sum::<f64>().log2()
+}
+
+/// log2(2^x - 1) for x > 0
+fn log2_exp2_minus_one(x: f64) -> f64 {
+    if x > 52.0 {
+        x
+    } else {
+        (x.exp2() - 1.0).log2()
+    }
+}
+
+/// log2 of the sum of 2^(n * bits) for all n in [start, end]
+fn log2_geometric(start: f64, end: f64, bits: f64) -> f64 {
+    if bits == f64::NEG_INFINITY {
+        // only n = 0 contributes
+        if start == 0.0 { 0.0 } else { f64::NEG_INFINITY }
+    } else if bits <= 0.0 {
+        (end - start + 1.0).log2()
+    } else {
+        start * bits + log2_exp2_minus_one((end - start + 1.0) * bits) - log2_exp2_minus_one(bits)
+    }
+}
+
+/// Inclusive ranges of a numberset as floats
+fn numberset_ranges(grammar: &Grammar, id: NumbersetId) -> Vec<(f64, f64)> {
+    fn convert<T: Copy + Into<i128>>(ranges: &[std::ops::Range<T>]) -> Vec<(f64, f64)> {
+        ranges.iter().map(|range| (range.start.into() as f64, range.end.into() as f64)).collect()
+    }
+    
+    match grammar.numbersets().get(&id).unwrap() {
+        NumbersetType::U8(ranges) => convert(ranges),
+        NumbersetType::I8(ranges) => convert(ranges),
+        NumbersetType::U16(ranges) => convert(ranges),
+        NumbersetType::I16(ranges) => convert(ranges),
+        NumbersetType::U32(ranges) => convert(ranges),
+        NumbersetType::I32(ranges) => convert(ranges),
+        NumbersetType::U64(ranges) => convert(ranges),
+        NumbersetType::I64(ranges) => convert(ranges),
+    }
+}
+
+/// Estimates how many distinct outputs every struct can generate, as log2 so that
+/// large grammars don't overflow. It assumes that different choices always lead to
+/// different outputs, so the result is an upper bound for ambiguous grammars.
+/// Only works on acyclic grammars.
+struct ChoiceEstimator<'a> {
+    grammar: &'a Grammar,
+    cache: BTreeMap<ContainerId, f64>,
+}
+impl<'a> ChoiceEstimator<'a> {
+    fn new(grammar: &'a Grammar) -> Self {
+        Self {
+            grammar,
+            cache: BTreeMap::new(),
+        }
+    }
+    
+    /// log2 of the number of values in a numberset
+    fn numberset(&self, id: NumbersetId) -> f64 {
+        log2_sum(numberset_ranges(self.grammar, id).into_iter().map(|(start, end)| (end - start + 1.0).log2()))
+    }
+    
+    /// Integers without a numberset can take every value of their type
+    fn integer(&self, value: &IntegerValue, width: usize) -> f64 {
+        match value {
+            IntegerValue::FromSet(id) => self.numberset(*id),
+            IntegerValue::Any => (width * 8) as f64,
+        }
+    }
+    
+    /// Random strings consist of 7-bit characters, random bytes of 8-bit values
+    fn bytearray(&self, value: &BytearrayValue, bits_per_byte: f64) -> f64 {
+        match value {
+            BytearrayValue::Literal(_) => 0.0,
+            BytearrayValue::Any(id) => log2_sum(
+                numberset_ranges(self.grammar, *id).into_iter().map(|(start, end)| log2_geometric(start, end, bits_per_byte))
+            ),
+        }
+    }
+    
+    fn variable(&mut self, var: &Variable) -> f64 {
+        let mut ret = match var.typ() {
+            VariableType::Epsilon => 0.0,
+            VariableType::U8(value) |
+            VariableType::I8(value) => self.integer(value, 1),
+            VariableType::U16(value) |
+            VariableType::I16(value) => self.integer(value, 2),
+            VariableType::U32(value) |
+            VariableType::I32(value) => self.integer(value, 4),
+            VariableType::U64(value) |
+            VariableType::I64(value) => self.integer(value, 8),
+            VariableType::String(value) => self.bytearray(value, 7.0),
+            VariableType::Bytes(value) => self.bytearray(value, 8.0),
+            VariableType::Oneof(id) |
+            VariableType::ContainerRef(id) => self.container(*id),
+            // The output of external functions is unknown, don't count it
+            VariableType::ExternRef(_) => 0.0,
+            VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
+        };
+        
+        // repeats: every count contributes its own set of outputs
+        if let Some(id) = var.options().repeats() {
+            let single = ret;
+            ret = log2_sum(
+                numberset_ranges(self.grammar, *id).into_iter().map(|(start, end)| log2_geometric(start, end, single))
+            );
+        }
+        
+        // optional: one more output, the empty one
+        if var.options().optional() {
+            ret = log2_sum([ret, 0.0]);
+        }
+        
+        ret
+    }
+    
+    fn container(&mut self, id: ContainerId) -> f64 {
+        if let Some(bits) = self.cache.get(&id) {
+            return *bits;
+        }
+        
+        let container = self.grammar.container(id).unwrap();
+        let mut ret = 0.0;
+        
+        match container.typ() {
+            ContainerType::Struct => {
+                // every combination of the variables
+                for var in container.variables() {
+                    ret += self.variable(var);
+                }
+            },
+            ContainerType::Oneof => {
+                // the outputs of all variables together
+                let bits: Vec<f64> = container.variables().iter().map(|var| self.variable(var)).collect();
+                ret = log2_sum(bits);
+            },
+        }
+        
+        self.cache.insert(id, ret);
+        ret
+    }
+    
+    fn choices(&mut self, root: ContainerId) -> Vec<ContainerChoices> {
+        let mut ret = Vec::new();
+        
+        for id in self.grammar.reachable_containers() {
+            if id == root || !matches!(self.grammar.container(id).unwrap().typ(), ContainerType::Struct) {
+                continue;
+            }
+            
+            ret.push(ContainerChoices {
+                id,
+                bits: self.container(id),
+            });
+        }
+        
+        ret.sort_by(|a, b| b.bits.partial_cmp(&a.bits).unwrap());
+        ret
+    }
+}
+
+/// log2 of the number of derivations of a variable given the derivations of all containers.
+/// Terminals have exactly one derivation, their values are not counted.
+fn variable_derivations(grammar: &Grammar, var: &Variable, counts: &BTreeMap<ContainerId, f64>) -> f64 {
+    let mut ret = match var.typ() {
+        VariableType::Oneof(id) |
+        VariableType::ContainerRef(id) => counts[id],
+        _ => 0.0,
+    };
+    
+    if let Some(id) = var.options().repeats() {
+        let single = ret;
+        ret = log2_sum(numberset_ranges(grammar, *id).into_iter().map(|(start, end)| log2_geometric(start, end, single)));
+    }
+    
+    if var.options().optional() {
+        ret = log2_sum([ret, 0.0]);
+    }
+    
+    ret
+}
+
+/// Count the derivation trees of every struct that nest at most `depth` structs and oneofs,
+/// as log2 so that large grammars don't overflow. Structs without any derivation within
+/// the depth have a count of -inf. Unlike the other statistics this works on grammars with cycles.
+/// Structs are sorted by their count, largest first.
+pub fn derivation_counts(grammar: &Grammar, depth: usize) -> Vec<ContainerChoices> {
+    let mut counts: BTreeMap<ContainerId, f64> = grammar.containers().map(|container| (container.id(), f64::NEG_INFINITY)).collect();
+    
+    for _ in 0..depth {
+        let mut next = BTreeMap::new();
+        
+        for container in grammar.containers() {
+            let vars = container.variables().iter().map(|var| variable_derivations(grammar, var, &counts));
+            
+            let bits = match container.typ() {
+                ContainerType::Struct => vars.sum(),
+                ContainerType::Oneof => log2_sum(vars),
+            };
+            
+            next.insert(container.id(), bits);
+        }
+        
+        counts = next;
+    }
+    
+    let mut ret: Vec<ContainerChoices> = grammar.reachable_containers().into_iter()
+        .filter(|id| matches!(grammar.container(*id).unwrap().typ(), ContainerType::Struct))
+        .map(|id| ContainerChoices {
+            id,
+            bits: counts[&id],
+        })
+        .collect();
+    
+    ret.sort_by(|a, b| b.bits.partial_cmp(&a.bits).unwrap());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GrammarStats;
+    use crate::frontend::{Lexer, Parser, SourceView};
+    use crate::grammar::Grammar;
+    
+    fn parse(source: &str) -> Grammar {
+        let view = SourceView::new(source);
+        let tokens = Lexer::new(&view).lex().unwrap();
+        Parser::new(&view, &tokens).parse().unwrap()
+    }
+    
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {} but got {}", expected, actual);
+    }
+    
+    #[test]
+    fn size_of_fixed_variables() {
+        let grammar = parse("struct Root { a: string = \"ab\"; b: u16; c: u64; }");
+        let size = *GrammarStats::from_grammar(&grammar).expected_input_size();
+        assert_close(size.mean, 12.0);
+        assert_close(size.variance, 0.0);
+    }
+    
+    #[test]
+    fn size_of_random_choices() {
+        // b is uniform over {1, 2, 3} and c is either 1 or 3 bytes
+        let grammar = parse("struct Root { b: bytes = 1..3; c: oneof { x: string = \"x\"; y: string = \"yyy\"; }; }");
+        let size = *GrammarStats::from_grammar(&grammar).expected_input_size();
+        assert_close(size.mean, 4.0);
+        assert_close(size.variance, 2.0 / 3.0 + 1.0);
+    }
+    
+    #[test]
+    fn size_of_optional_and_repeated_variables() {
+        // e is either empty or 2 bytes and d is either "z,z" or "z,z,z"
+        let grammar = parse("struct Root { optional e: string = \"ee\"; repeats 2..3 separated \",\" d: string = \"z\"; }");
+        let size = *GrammarStats::from_grammar(&grammar).expected_input_size();
+        assert_close(size.mean, 5.0);
+        assert_close(size.variance, 2.0);
+    }
+    
+    #[test]
+    fn share_of_structs() {
+        let grammar = parse("struct Root { a: string = \"abcd\"; repeats 3..4 b: Part; } struct Part { _: string = \"xy\"; }");
+        let stats = GrammarStats::from_grammar(&grammar);
+        let shares = stats.shares();
+        assert_eq!(shares.len(), 1);
+        assert_close(shares[0].calls, 3.5);
+        assert_close(shares[0].size.mean, 2.0);
+        assert_close(shares[0].share, 7.0 / 11.0);
+        assert!(shares[0].is_dominant());
+    }
+    
+    #[test]
+    fn number_of_distinct_outputs() {
+        // 4 values of a, 2 variables of b, c present or not and 2 or 4 outputs of d
+        let grammar = parse("struct Root { a: u8 = 0..3; b: oneof { x: string = \"x\"; y: string = \"y\"; }; optional c: string = \"c\"; repeats 1..2 d: u8 = 0, 1; }");
+        let stats = GrammarStats::from_grammar(&grammar);
+        assert_close(stats.input_choices(), (4.0f64 * 2.0 * 2.0 * (2.0 + 4.0)).log2());
+    }
+    
+    #[test]
+    fn number_of_distinct_outputs_per_struct() {
+        let grammar = parse("struct Root { a: Small; b: Large; } struct Small { _: u8 = 0..7; } struct Large { _: u16; }");
+        let stats = GrammarStats::from_grammar(&grammar);
+        let bits: Vec<f64> = stats.choices().iter().map(|choices| choices.bits).collect();
+        assert_eq!(bits.len(), 2);
+        assert_close(bits[0], 16.0);
+        assert_close(bits[1], 3.0);
+        assert_close(stats.input_choices(), 19.0);
+    }
+}
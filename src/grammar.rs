@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::collections::btree_map::{Values, Keys};
 use std::default::Default;
 use std::ops::Range;
@@ -14,7 +14,7 @@ pub type ContainerId = usize;
 pub type StringId = usize;
 
 /// Allowed values for the depth option
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Depth {
     Unlimited,
     Limited(usize),
@@ -26,7 +26,7 @@ impl Default for Depth {
 }
 
 /// Allowed values for the endianness option in containers
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Endianness {
     Big,
     Little,
@@ -39,10 +39,11 @@ impl Default for Endianness {
 }
 
 /// Allowed values for the scheduling option in containers
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Scheduling {
     RoundRobin,
     Random,
+    Adaptive,
 }
 impl Default for Scheduling {
     fn default() -> Self {
@@ -51,11 +52,13 @@ impl Default for Scheduling {
 }
 
 /// Storage for all possible options in a container
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct ContainerOptions {
     endianness: Endianness,
     scheduling: Scheduling,
     depth: Depth,
+    recursion: Option<(ContainerId, usize)>,
+    max_len: Option<(ContainerId, usize)>,
 }
 impl Default for ContainerOptions {
     fn default() -> Self {
@@ -63,6 +66,8 @@ impl Default for ContainerOptions {
             endianness: Endianness::default(),
             scheduling: Scheduling::default(),
             depth: Depth::default(),
+            recursion: None,
+            max_len: None,
         }
     }
 }
@@ -90,6 +95,24 @@ impl ContainerOptions {
     pub fn depth(&self) -> &Depth {
         &self.depth
     }
+    
+    /// Limit how many instances of the container `owner` may be active at the same time
+    pub fn set_recursion(&mut self, owner: ContainerId, limit: usize) {
+        self.recursion = Some((owner, limit));
+    }
+    
+    pub fn recursion(&self) -> Option<&(ContainerId, usize)> {
+        self.recursion.as_ref()
+    }
+    
+    /// Assert that the container `owner` never generates more than `limit` bytes
+    pub fn set_max_len(&mut self, owner: ContainerId, limit: usize) {
+        self.max_len = Some((owner, limit));
+    }
+    
+    pub fn max_len(&self) -> Option<&(ContainerId, usize)> {
+        self.max_len.as_ref()
+    }
 }
 
 pub trait HasOptions {
@@ -98,15 +121,20 @@ pub trait HasOptions {
 }
 
 /// Storage for all possible options of a variable
+#[derive(Debug)]
 pub struct VariableOptions {
     optional: bool,
     repeats: Option<NumbersetId>,
+    separator: Option<StringId>,
+    endianness: Option<Endianness>,
 }
 impl Default for VariableOptions {
     fn default() -> Self {
         Self {
             optional: false,
             repeats: None,
+            separator: None,
+            endianness: None,
         }
     }
 }
@@ -119,6 +147,10 @@ impl VariableOptions {
         self.repeats = Some(numberset);
     }
     
+    pub fn set_separator(&mut self, string: StringId) {
+        self.separator = Some(string);
+    }
+    
     pub fn optional(&self) -> bool {
         self.optional
     }
@@ -126,21 +158,38 @@ impl VariableOptions {
     pub fn repeats(&self) -> Option<&NumbersetId> {
         self.repeats.as_ref()
     }
+    
+    /// String that gets emitted between two repetitions of the variable
+    pub fn separator(&self) -> Option<&StringId> {
+        self.separator.as_ref()
+    }
+    
+    pub fn set_endianness(&mut self, value: Endianness) {
+        self.endianness = Some(value);
+    }
+    
+    /// Endianness of the variable if it overrides the endianness of the container
+    pub fn endianness(&self) -> Option<&Endianness> {
+        self.endianness.as_ref()
+    }
 }
 
 /// Possible values for an integer
+#[derive(Debug)]
 pub enum IntegerValue {
     FromSet(NumbersetId),
     Any,
 }
 
 /// Possible values for a string or bytes type
+#[derive(Debug)]
 pub enum BytearrayValue {
     Any(NumbersetId),
     Literal(StringId),
 }
 
 /// All possible types for variables
+#[derive(Debug)]
 pub enum VariableType {
     U8(IntegerValue),
     I8(IntegerValue),
@@ -154,7 +203,9 @@ pub enum VariableType {
     Bytes(BytearrayValue),
     Oneof(ContainerId),
     ContainerRef(ContainerId),
+    Epsilon,
     ResolveContainerRef(SourceRange),
+    ExternRef(String),
 }
 
 /// A single variable in a container
@@ -179,7 +230,7 @@ impl Variable {
     }
 }
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum ContainerType {
     Oneof,
     Struct,
@@ -220,6 +271,10 @@ impl Container {
         self.variables[var].typ = VariableType::ContainerRef(target);
     }
     
+    pub fn resolve_extern(&mut self, var: usize, name: String) {
+        self.variables[var].typ = VariableType::ExternRef(name);
+    }
+    
     pub fn variables(&self) -> &[Variable] {
         &self.variables
     }
@@ -327,6 +382,8 @@ pub struct Grammar {
     numbersets: BTreeMap<NumbersetId, NumbersetType>,
     strings: BTreeMap<StringId, Vec<u8>>,
     root: Option<ContainerId>,
+    focus: Option<ContainerId>,
+    extra_entrypoints: Vec<ContainerId>,
 }
 impl Grammar {
     pub fn new() -> Self {
@@ -337,6 +394,8 @@ impl Grammar {
             numbersets: BTreeMap::new(),
             strings: BTreeMap::new(),
             root: None,
+            focus: None,
+            extra_entrypoints: Vec::new(),
         }
     }
     
@@ -360,6 +419,30 @@ impl Grammar {
         (&self.root).as_ref()
     }
     
+    pub fn set_focus(&mut self, focus: ContainerId) {
+        self.focus = Some(focus);
+    }
+    
+    /// The struct where generation starts. This is the root unless
+    /// the grammar was focused on another struct.
+    pub fn entrypoint(&self) -> Option<&ContainerId> {
+        self.focus.as_ref().or(self.root.as_ref())
+    }
+    
+    /// Add a struct where generation can start besides the entrypoint
+    pub fn add_entrypoint(&mut self, id: ContainerId) {
+        if !self.extra_entrypoints.contains(&id) {
+            self.extra_entrypoints.push(id);
+        }
+    }
+    
+    /// All structs where generation can start, the entrypoint first
+    pub fn entrypoints(&self) -> Vec<ContainerId> {
+        let mut ret: Vec<ContainerId> = self.entrypoint().into_iter().copied().collect();
+        ret.extend(self.extra_entrypoints.iter().filter(|id| !ret.contains(id)).copied().collect::<Vec<_>>());
+        ret
+    }
+    
     pub fn container(&self, id: ContainerId) -> Option<&Container> {
         self.containers.get(&id)
     }
@@ -400,7 +483,10 @@ impl Grammar {
         }
     }
     
-    pub fn container_callees(&self, id: ContainerId, full: bool) -> Vec<ContainerId> {
+    /// Structs that the expansion of a container descends into. Given the result of
+    /// minimal_sizes() only the structs of its smallest expansion are returned.
+    pub fn container_callees(&self, id: ContainerId, minimal: Option<&BTreeMap<ContainerId, usize>>) -> Vec<ContainerId> {
+        let full = minimal.is_none();
         let mut ret = Vec::<ContainerId>::new();
         
         for var in &self.containers.get(&id).unwrap().variables {
@@ -492,7 +578,11 @@ impl Grammar {
                     }
                 },
                 VariableType::Oneof(id) => {
-                    let mut callees = self.container_callees(*id, true);
+                    // The smallest expansion of a oneof only descends into its cheapest variable
+                    let mut callees = match minimal {
+                        None => self.container_callees(*id, None),
+                        Some(sizes) => self.cheapest_callees(*id, sizes),
+                    };
                     ret.append(&mut callees);
                 },
                 _ => {},
@@ -502,6 +592,171 @@ impl Grammar {
         ret
     }
     
+    /// Structs that cannot be expanded into a finite string because every
+    /// way of expanding them leads back into a struct that has to be expanded again
+    pub fn unproductive_containers(&self) -> Vec<ContainerId> {
+        let mut productive = BTreeSet::<ContainerId>::new();
+        
+        loop {
+            let mut changed = false;
+            
+            for container in self.containers.values() {
+                if productive.contains(&container.id) {
+                    continue;
+                }
+                
+                let mut vars = container.variables.iter().map(|var| {
+                    if var.options.optional {
+                        return true;
+                    }
+                    
+                    if let Some(id) = &var.options.repeats {
+                        if self.get_numberset_bound(*id, false) == 0 {
+                            return true;
+                        }
+                    }
+                    
+                    match &var.typ {
+                        VariableType::ContainerRef(id) |
+                        VariableType::Oneof(id) => productive.contains(id),
+                        _ => true,
+                    }
+                });
+                
+                let is_productive = match container.typ {
+                    ContainerType::Struct => vars.all(|x| x),
+                    ContainerType::Oneof => vars.any(|x| x),
+                };
+                
+                if is_productive {
+                    productive.insert(container.id);
+                    changed = true;
+                }
+            }
+            
+            if !changed {
+                break;
+            }
+        }
+        
+        self.containers.values()
+            .filter(|c| c.typ == ContainerType::Struct && !productive.contains(&c.id))
+            .map(|c| c.id)
+            .collect()
+    }
+    
+    /// Size of the smallest expansion of every container.
+    /// Unproductive containers have a size of usize::MAX.
+    pub fn minimal_sizes(&self) -> BTreeMap<ContainerId, usize> {
+        let mut sizes: BTreeMap<ContainerId, usize> = self.containers.keys().map(|id| (*id, usize::MAX)).collect();
+        
+        loop {
+            let mut changed = false;
+            
+            for container in self.containers.values() {
+                let vars = container.variables.iter().map(|var| self.minimal_variable_size(var, &sizes));
+                
+                let size = match container.typ {
+                    ContainerType::Struct => vars.fold(0, usize::saturating_add),
+                    ContainerType::Oneof => vars.min().unwrap_or(0),
+                };
+                
+                if size < *sizes.get(&container.id).unwrap() {
+                    sizes.insert(container.id, size);
+                    changed = true;
+                }
+            }
+            
+            if !changed {
+                break;
+            }
+        }
+        
+        sizes
+    }
+    
+    /// How often a variable gets expanded in the smallest expansion of its container
+    pub fn minimal_variable_count(&self, var: &Variable) -> usize {
+        if var.options.optional {
+            0
+        } else if let Some(id) = &var.options.repeats {
+            self.get_numberset_bound(*id, false)
+        } else {
+            1
+        }
+    }
+    
+    /// Size of the smallest expansion of a variable given the minimal sizes of all containers
+    pub fn minimal_variable_size(&self, var: &Variable, sizes: &BTreeMap<ContainerId, usize>) -> usize {
+        let count = self.minimal_variable_count(var);
+        
+        if count == 0 {
+            return 0;
+        }
+        
+        let single = match &var.typ {
+            VariableType::Epsilon |
+            VariableType::ExternRef(_) => 0,
+            VariableType::U8(_) |
+            VariableType::I8(_) => 1,
+            VariableType::U16(_) |
+            VariableType::I16(_) => 2,
+            VariableType::U32(_) |
+            VariableType::I32(_) => 4,
+            VariableType::U64(_) |
+            VariableType::I64(_) => 8,
+            VariableType::String(bytearray) |
+            VariableType::Bytes(bytearray) => match bytearray {
+                BytearrayValue::Any(id) => self.get_numberset_bound(*id, false),
+                BytearrayValue::Literal(id) => self.strings.get(id).unwrap().len(),
+            },
+            VariableType::Oneof(id) |
+            VariableType::ContainerRef(id) => *sizes.get(id).unwrap(),
+            VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
+        };
+        let sep_size = match &var.options.separator {
+            Some(id) => self.strings.get(id).unwrap().len(),
+            None => 0,
+        };
+        
+        single.saturating_mul(count).saturating_add(sep_size.saturating_mul(count - 1))
+    }
+    
+    /// Index of the variable of a oneof with the smallest expansion.
+    /// Ties go to the variable that comes first.
+    pub fn cheapest_variable(&self, id: ContainerId, sizes: &BTreeMap<ContainerId, usize>) -> usize {
+        let container = self.containers.get(&id).unwrap();
+        let mut best = 0;
+        let mut best_size = usize::MAX;
+        
+        for (i, var) in container.variables.iter().enumerate() {
+            let size = self.minimal_variable_size(var, sizes);
+            
+            if size < best_size {
+                best = i;
+                best_size = size;
+            }
+        }
+        
+        best
+    }
+    
+    /// Structs that get expanded when a oneof selects its cheapest variable
+    fn cheapest_callees(&self, id: ContainerId, sizes: &BTreeMap<ContainerId, usize>) -> Vec<ContainerId> {
+        let container = self.containers.get(&id).unwrap();
+        let var = &container.variables[self.cheapest_variable(id, sizes)];
+        
+        if self.minimal_variable_count(var) == 0 {
+            return Vec::new();
+        }
+        
+        match &var.typ {
+            VariableType::ContainerRef(id) if self.containers.get(id).unwrap().typ() == ContainerType::Struct => vec![*id],
+            VariableType::Oneof(id) => self.cheapest_callees(*id, sizes),
+            _ => Vec::new(),
+        }
+    }
+    
     pub fn unresolved_names(&self) -> Vec<(ContainerId, usize, SourceRange)> {
         let mut ret = Vec::new();
         
@@ -593,6 +848,7 @@ impl Grammar {
         let mut var_size = match &var.typ {
             VariableType::Oneof(id) => self.container_size_bound(*id, calc_max),
             VariableType::ContainerRef(id) => self.container_size(*id, calc_max),
+            VariableType::Epsilon => 0,
             VariableType::U8(_) |
             VariableType::I8(_) => 1,
             VariableType::U16(_) |
@@ -612,11 +868,23 @@ impl Grammar {
                     },
                 }
             },
+            // Nothing is known about the output of external functions
+            VariableType::ExternRef(_) => if calc_max {
+                usize::MAX
+            } else {
+                0
+            },
             VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
         };
         
         if let Some(id) = &var.options.repeats {
-            var_size = var_size.saturating_mul(self.get_numberset_bound(*id, calc_max));
+            let count = self.get_numberset_bound(*id, calc_max);
+            var_size = var_size.saturating_mul(count);
+            
+            if let Some(sep) = &var.options.separator {
+                let sep_size = self.strings.get(sep).unwrap().len();
+                var_size = var_size.saturating_add(sep_size.saturating_mul(count.saturating_sub(1)));
+            }
         }
         
         var_size
@@ -652,6 +920,513 @@ impl Grammar {
     pub fn numbersets(&self) -> &BTreeMap<NumbersetId, NumbersetType> {
         &self.numbersets
     }
+    
+    /// Names of all external functions referenced in the grammar, without duplicates
+    pub fn extern_names(&self) -> Vec<&str> {
+        let mut ret = Vec::<&str>::new();
+        
+        for container in self.containers.values() {
+            for var in &container.variables {
+                if let VariableType::ExternRef(name) = &var.typ {
+                    if !ret.contains(&name.as_str()) {
+                        ret.push(name);
+                    }
+                }
+            }
+        }
+        
+        ret
+    }
+    
+    /// All containers that can be reached from the entrypoint, including oneofs
+    pub fn reachable_containers(&self) -> BTreeSet<ContainerId> {
+        let mut ret = BTreeSet::new();
+        let mut queue = self.entrypoints();
+        
+        while let Some(id) = queue.pop() {
+            if !ret.insert(id) {
+                continue;
+            }
+            
+            for var in &self.containers.get(&id).unwrap().variables {
+                match &var.typ {
+                    VariableType::ContainerRef(target) |
+                    VariableType::Oneof(target) => queue.push(*target),
+                    _ => {},
+                }
+            }
+        }
+        
+        ret
+    }
+    
+    /// Remove the given containers together with all variables that reference them.
+    /// Oneofs that lose all of their variables are removed too, and so is
+    /// everything that was only reachable through a removed container.
+    /// The entrypoint must not be removed.
+    pub fn prune(&mut self, ids: &[ContainerId]) {
+        let reachable = self.reachable_containers();
+        let mut removed: BTreeSet<ContainerId> = ids.iter().copied().collect();
+        
+        loop {
+            let mut emptied = Vec::new();
+            
+            for container in self.containers.values_mut() {
+                if removed.contains(&container.id) {
+                    continue;
+                }
+                
+                container.variables.retain(|var| match &var.typ {
+                    VariableType::ContainerRef(target) |
+                    VariableType::Oneof(target) => !removed.contains(target),
+                    _ => true,
+                });
+                
+                if container.typ == ContainerType::Oneof && container.variables.is_empty() {
+                    emptied.push(container.id);
+                }
+            }
+            
+            if emptied.is_empty() {
+                break;
+            }
+            
+            removed.extend(emptied);
+        }
+        
+        for id in &removed {
+            self.containers.remove(id);
+        }
+        
+        let still_reachable = self.reachable_containers();
+        
+        for id in reachable.difference(&still_reachable) {
+            self.containers.remove(id);
+        }
+        
+        self.remove_unused_constants();
+    }
+    
+    /// Signature of a variable where references are replaced by the classes of the referenced containers
+    fn variable_signature(var: &Variable, classes: &BTreeMap<ContainerId, usize>) -> String {
+        let typ = match &var.typ {
+            VariableType::ContainerRef(id) => format!("ContainerRef({})", classes[id]),
+            VariableType::Oneof(id) => format!("Oneof({})", classes[id]),
+            typ => format!("{:?}", typ),
+        };
+        
+        format!(" {} {:?};", typ, var.options)
+    }
+    
+    /// Assign every container a class such that containers of the same class generate the same inputs.
+    /// Containers for which `unique` returns true get a class of their own.
+    fn equivalence_classes<F: Fn(&Container) -> bool>(&self, unique: F) -> BTreeMap<ContainerId, usize> {
+        // Start with all containers in one class and split classes until
+        // all members of a class have the same signature
+        let mut classes: BTreeMap<ContainerId, usize> = self.containers.keys().map(|id| (*id, 0)).collect();
+        let mut num_classes = 1;
+        
+        loop {
+            let mut signatures = BTreeMap::<String, usize>::new();
+            let mut new_classes = BTreeMap::<ContainerId, usize>::new();
+            
+            for container in self.containers.values() {
+                let mut signature = format!("{:?} {:?}", container.typ, container.options);
+                
+                if unique(container) {
+                    signature.push_str(&format!(" #{}", container.id));
+                }
+                
+                for var in &container.variables {
+                    signature.push_str(&Self::variable_signature(var, &classes));
+                }
+                
+                let next = signatures.len();
+                let class = *signatures.entry(signature).or_insert(next);
+                new_classes.insert(container.id, class);
+            }
+            
+            classes = new_classes;
+            
+            if signatures.len() == num_classes {
+                break;
+            }
+            
+            num_classes = signatures.len();
+        }
+        
+        classes
+    }
+    
+    /// Pairs of variables in a oneof that generate the same inputs.
+    /// Selecting either of them wastes throughput on duplicate outputs.
+    pub fn duplicate_variables(&self) -> Vec<(ContainerId, usize, usize)> {
+        let classes = self.equivalence_classes(|_| false);
+        let mut ret = Vec::new();
+        
+        for container in self.containers.values() {
+            if container.typ != ContainerType::Oneof {
+                continue;
+            }
+            
+            let signatures: Vec<String> = container.variables.iter().map(|var| Self::variable_signature(var, &classes)).collect();
+            
+            for j in 1..signatures.len() {
+                if let Some(i) = signatures[..j].iter().position(|sig| *sig == signatures[j]) {
+                    ret.push((container.id, i, j));
+                }
+            }
+        }
+        
+        ret
+    }
+    
+    /// Integer variables whose numberset contains every value of their type,
+    /// which is the same as leaving out the assignment
+    pub fn unrestricted_numbers(&self) -> Vec<(ContainerId, usize)> {
+        fn covers<T: PartialEq>(ranges: &[Range<T>], min: T, max: T) -> bool {
+            matches!(ranges, [range] if range.start == min && range.end == max)
+        }
+        
+        let mut ret = Vec::new();
+        
+        for container in self.containers.values() {
+            for (i, var) in container.variables.iter().enumerate() {
+                let id = match &var.typ {
+                    VariableType::U8(IntegerValue::FromSet(id)) |
+                    VariableType::I8(IntegerValue::FromSet(id)) |
+                    VariableType::U16(IntegerValue::FromSet(id)) |
+                    VariableType::I16(IntegerValue::FromSet(id)) |
+                    VariableType::U32(IntegerValue::FromSet(id)) |
+                    VariableType::I32(IntegerValue::FromSet(id)) |
+                    VariableType::U64(IntegerValue::FromSet(id)) |
+                    VariableType::I64(IntegerValue::FromSet(id)) => id,
+                    _ => continue,
+                };
+                
+                let unrestricted = match self.numbersets.get(id).unwrap() {
+                    NumbersetType::U8(v) => covers(v, u8::MIN, u8::MAX),
+                    NumbersetType::I8(v) => covers(v, i8::MIN, i8::MAX),
+                    NumbersetType::U16(v) => covers(v, u16::MIN, u16::MAX),
+                    NumbersetType::I16(v) => covers(v, i16::MIN, i16::MAX),
+                    NumbersetType::U32(v) => covers(v, u32::MIN, u32::MAX),
+                    NumbersetType::I32(v) => covers(v, i32::MIN, i32::MAX),
+                    NumbersetType::U64(v) => covers(v, u64::MIN, u64::MAX),
+                    NumbersetType::I64(v) => covers(v, i64::MIN, i64::MAX),
+                };
+                
+                if unrestricted {
+                    ret.push((container.id, i));
+                }
+            }
+        }
+        
+        ret
+    }
+    
+    /// Anonymous structs with a single variable and the same options as the
+    /// container they are defined in. They can be replaced by their variable.
+    pub fn single_variable_structs(&self) -> Vec<ContainerId> {
+        let mut ret = Vec::new();
+        
+        for parent in self.containers.values() {
+            for var in &parent.variables {
+                if let VariableType::ContainerRef(id) = &var.typ {
+                    let container = self.containers.get(id).unwrap();
+                    
+                    if matches!(container.name(), Some(name) if name.is_empty()) &&
+                       container.variables.len() == 1 &&
+                       format!("{:?}", container.options) == format!("{:?}", parent.options) {
+                        ret.push(*id);
+                    }
+                }
+            }
+        }
+        
+        ret
+    }
+    
+    /// Pairs of named structs with identical contents, e.g. because a struct
+    /// was copied and renamed. The first element was defined first.
+    pub fn duplicate_structs(&self) -> Vec<(ContainerId, ContainerId)> {
+        let classes = self.equivalence_classes(|_| false);
+        let mut first_of_class = BTreeMap::<usize, ContainerId>::new();
+        let mut ret = Vec::new();
+        
+        for container in self.containers.values() {
+            if container.typ != ContainerType::Struct || !matches!(container.name(), Some(name) if !name.is_empty()) {
+                continue;
+            }
+            
+            match first_of_class.get(&classes[&container.id]) {
+                Some(first) => ret.push((*first, container.id)),
+                None => {
+                    first_of_class.insert(classes[&container.id], container.id);
+                },
+            }
+        }
+        
+        ret
+    }
+    
+    /// Merge containers that generate the same inputs because their variables are identical.
+    /// References to a merged container are redirected to the container it was merged into.
+    /// Named structs are never removed so that their IDs stay valid.
+    /// Oneofs with round-robin or adaptive scheduling keep a cursor or weights of their own,
+    /// so they are never merged. With `count_selections`, which --self-coverage needs,
+    /// no oneof is merged because every oneof counts its selections.
+    /// Returns the number of removed containers.
+    pub fn minimize(&mut self, count_selections: bool) -> usize {
+        let classes = self.equivalence_classes(|container| {
+            container.typ == ContainerType::Oneof && (count_selections || !matches!(container.options.scheduling, Scheduling::Random))
+        });
+        
+        // Every class is represented by the entrypoint or the named struct that
+        // was defined first. Without named structs the first container wins.
+        let mut representatives = BTreeMap::<usize, ContainerId>::new();
+        
+        for container in self.containers.values() {
+            let class = classes[&container.id];
+            let is_named = matches!(container.name(), Some(name) if !name.is_empty());
+            let is_entrypoint = self.root == Some(container.id) || self.focus == Some(container.id);
+            
+            match representatives.get(&class) {
+                None => {
+                    representatives.insert(class, container.id);
+                },
+                Some(current) => {
+                    let current = &self.containers[current];
+                    let current_named = matches!(current.name(), Some(name) if !name.is_empty());
+                    let current_entrypoint = self.root == Some(current.id) || self.focus == Some(current.id);
+                    
+                    if !current_entrypoint && (is_entrypoint || (is_named && !current_named)) {
+                        representatives.insert(class, container.id);
+                    }
+                }
+            }
+        }
+        
+        let mut replacements = BTreeMap::<ContainerId, ContainerId>::new();
+        
+        for container in self.containers.values() {
+            let representative = representatives[&classes[&container.id]];
+            let is_named = matches!(container.name(), Some(name) if !name.is_empty());
+            
+            if container.id != representative && !is_named {
+                replacements.insert(container.id, representative);
+            }
+        }
+        
+        for container in self.containers.values_mut() {
+            for var in &mut container.variables {
+                match &mut var.typ {
+                    VariableType::ContainerRef(id) |
+                    VariableType::Oneof(id) => {
+                        if let Some(representative) = replacements.get(id) {
+                            *id = *representative;
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        
+        for id in replacements.keys() {
+            self.containers.remove(id);
+        }
+        
+        replacements.len()
+    }
+    
+    /// Literal of a variable that always generates the same bytes, together with
+    /// whether it is a `bytes` literal
+    fn plain_literal(var: &Variable) -> Option<(StringId, bool)> {
+        let options = &var.options;
+        
+        if options.optional || options.repeats.is_some() || options.separator.is_some() {
+            return None;
+        }
+        
+        match &var.typ {
+            VariableType::String(BytearrayValue::Literal(id)) => Some((*id, false)),
+            VariableType::Bytes(BytearrayValue::Literal(id)) => Some((*id, true)),
+            _ => None,
+        }
+    }
+    
+    /// Concatenate consecutive string and bytes literals in structs into a single literal
+    /// so that the generator copies them at once. The result is a `bytes` literal
+    /// if any of the folded literals is one.
+    /// Returns the number of removed variables.
+    pub fn fold_literals(&mut self) -> usize {
+        let mut removed = 0;
+        let ids: Vec<ContainerId> = self.containers.values().filter(|c| matches!(c.typ, ContainerType::Struct)).map(|c| c.id).collect();
+        
+        for id in ids {
+            let variables = std::mem::take(&mut self.containers.get_mut(&id).unwrap().variables);
+            let mut folded = Vec::<Variable>::with_capacity(variables.len());
+            let mut pending: Option<(Vec<u8>, bool, Variable, usize)> = None;
+            
+            for var in variables {
+                let literal = Self::plain_literal(&var);
+                
+                match (&mut pending, literal) {
+                    (Some((content, is_binary, _, count)), Some((string, binary))) => {
+                        content.extend_from_slice(&self.strings[&string]);
+                        *is_binary |= binary;
+                        *count += 1;
+                        removed += 1;
+                        continue;
+                    },
+                    (None, Some((string, binary))) => {
+                        pending = Some((self.strings[&string].clone(), binary, var, 1));
+                        continue;
+                    },
+                    _ => {},
+                }
+                
+                if let Some(literal) = pending.take() {
+                    folded.push(self.folded_literal(literal));
+                }
+                
+                folded.push(var);
+            }
+            
+            if let Some(literal) = pending.take() {
+                folded.push(self.folded_literal(literal));
+            }
+            
+            self.containers.get_mut(&id).unwrap().variables = folded;
+        }
+        
+        if removed > 0 {
+            self.remove_unused_constants();
+        }
+        
+        removed
+    }
+    
+    /// Replace references to structs that only consist of literals with at most `max_len` bytes
+    /// in total by the literal itself, which saves a function call per reference.
+    /// The options of the referencing variable are kept. Anonymous containers that
+    /// become unreachable are removed, named structs stay so that their IDs stay valid.
+    /// Returns the number of replaced references.
+    pub fn inline_literals(&mut self, max_len: usize) -> usize {
+        let mut literals = BTreeMap::<ContainerId, (Vec<u8>, bool)>::new();
+        
+        for container in self.containers.values() {
+            if !matches!(container.typ, ContainerType::Struct) || container.variables.is_empty() || self.entrypoint() == Some(&container.id) {
+                continue;
+            }
+            
+            let mut content = Vec::new();
+            let mut is_binary = false;
+            let mut only_literals = true;
+            
+            for var in &container.variables {
+                match Self::plain_literal(var) {
+                    Some((id, binary)) => {
+                        content.extend_from_slice(&self.strings[&id]);
+                        is_binary |= binary;
+                    },
+                    None => {
+                        only_literals = false;
+                        break;
+                    },
+                }
+            }
+            
+            if only_literals && content.len() <= max_len {
+                literals.insert(container.id, (content, is_binary));
+            }
+        }
+        
+        let mut strings = BTreeMap::<ContainerId, StringId>::new();
+        
+        for (id, (content, _)) in &literals {
+            strings.insert(*id, self.add_string(content.clone()));
+        }
+        
+        let mut replaced = 0;
+        
+        for container in self.containers.values_mut() {
+            for var in &mut container.variables {
+                let target = match &var.typ {
+                    VariableType::ContainerRef(target) => *target,
+                    _ => continue,
+                };
+                
+                if let Some((_, is_binary)) = literals.get(&target) {
+                    let value = BytearrayValue::Literal(strings[&target]);
+                    var.typ = if *is_binary { VariableType::Bytes(value) } else { VariableType::String(value) };
+                    replaced += 1;
+                }
+            }
+        }
+        
+        let reachable = self.reachable_containers();
+        self.containers.retain(|id, container| reachable.contains(id) || matches!(container.name(), Some(name) if !name.is_empty()));
+        self.remove_unused_constants();
+        
+        replaced
+    }
+    
+    /// Variable for the literals that were collected by fold_literals().
+    /// A single literal is kept as it was.
+    fn folded_literal(&mut self, (content, is_binary, first, count): (Vec<u8>, bool, Variable, usize)) -> Variable {
+        if count == 1 {
+            return first;
+        }
+        
+        let id = self.add_string(content);
+        let value = BytearrayValue::Literal(id);
+        
+        Variable::new(
+            first.options,
+            if is_binary { VariableType::Bytes(value) } else { VariableType::String(value) },
+        )
+    }
+    
+    /// Remove numbersets and strings that are not referenced by any variable
+    fn remove_unused_constants(&mut self) {
+        let mut numbersets = BTreeSet::new();
+        let mut strings = BTreeSet::new();
+        
+        for container in self.containers.values() {
+            for var in &container.variables {
+                if let Some(id) = var.options.repeats {
+                    numbersets.insert(id);
+                }
+                
+                if let Some(id) = var.options.separator {
+                    strings.insert(id);
+                }
+                
+                match &var.typ {
+                    VariableType::U8(IntegerValue::FromSet(id)) |
+                    VariableType::I8(IntegerValue::FromSet(id)) |
+                    VariableType::U16(IntegerValue::FromSet(id)) |
+                    VariableType::I16(IntegerValue::FromSet(id)) |
+                    VariableType::U32(IntegerValue::FromSet(id)) |
+                    VariableType::I32(IntegerValue::FromSet(id)) |
+                    VariableType::U64(IntegerValue::FromSet(id)) |
+                    VariableType::I64(IntegerValue::FromSet(id)) |
+                    VariableType::String(BytearrayValue::Any(id)) |
+                    VariableType::Bytes(BytearrayValue::Any(id)) => {
+                        numbersets.insert(*id);
+                    },
+                    VariableType::String(BytearrayValue::Literal(id)) |
+                    VariableType::Bytes(BytearrayValue::Literal(id)) => {
+                        strings.insert(*id);
+                    },
+                    _ => {},
+                }
+            }
+        }
+        
+        self.numbersets.retain(|id, _| numbersets.contains(id));
+        self.strings.retain(|id, _| strings.contains(id));
+    }
 }
 
 impl HasOptions for Grammar {
@@ -663,3 +1438,84 @@ impl HasOptions for Grammar {
         &self.options
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Grammar;
+    use crate::frontend::{Lexer, Parser, SourceView, interpreter::Interpreter};
+    
+    const FIXTURES: [&str; 5] = [
+        include_str!("../tests/fixtures/expr.chm"),
+        include_str!("../tests/fixtures/header.chm"),
+        include_str!("../tests/fixtures/list.chm"),
+        include_str!("../tests/fixtures/recursion.chm"),
+        include_str!("../tests/fixtures/signed.chm"),
+    ];
+    
+    fn parse(source: &str) -> Grammar {
+        let view = SourceView::new(source);
+        let tokens = Lexer::new(&view).lex().unwrap();
+        Parser::new(&view, &tokens).parse().unwrap()
+    }
+    
+    fn samples(grammar: &Grammar) -> Vec<Vec<u8>> {
+        (1..=50).flat_map(|seed| {
+            let mut interpreter = Interpreter::new(grammar, seed, 4096);
+            (0..4).map(move |_| interpreter.generate())
+        }).collect()
+    }
+    
+    /// Apply a rewrite to a grammar and check that it generates exactly the same inputs afterwards.
+    /// Returns what the rewrite returned.
+    fn assert_same_inputs<F: Fn(&mut Grammar) -> usize>(source: &str, rewrite: F) -> usize {
+        let mut grammar = parse(source);
+        let before = samples(&grammar);
+        let ret = rewrite(&mut grammar);
+        assert!(before == samples(&grammar), "rewrite changed the inputs of\n{}", source);
+        ret
+    }
+    
+    #[test]
+    fn minimize_keeps_inputs() {
+        for source in FIXTURES {
+            assert_same_inputs(source, |grammar| grammar.minimize(false));
+        }
+        
+        let source = "option scheduling = random; struct Root { a: oneof { x: string = \"x\"; y: u8; }; b: oneof { x: string = \"x\"; y: u8; }; }";
+        assert_eq!(assert_same_inputs(source, |grammar| grammar.minimize(false)), 1);
+        assert_eq!(assert_same_inputs(source, |grammar| grammar.minimize(true)), 0);
+    }
+    
+    #[test]
+    fn minimize_keeps_oneofs_with_state() {
+        // Merged oneofs would share one cursor and alternate between "ab" and "ba"
+        let round_robin = "struct Root { a: oneof { x: string = \"a\"; y: string = \"b\"; }; b: oneof { x: string = \"a\"; y: string = \"b\"; }; }";
+        assert_eq!(assert_same_inputs(round_robin, |grammar| grammar.minimize(false)), 0);
+        
+        let nested = "struct Root { a: struct { c: oneof { x: string = \"a\"; y: u8; }; d: u8; }; b: struct { c: oneof { x: string = \"a\"; y: u8; }; d: u8; }; }";
+        assert_eq!(assert_same_inputs(nested, |grammar| grammar.minimize(false)), 0);
+        
+        let adaptive = "option scheduling = adaptive; struct Root { a: oneof { x: string = \"x\"; y: u8; }; b: oneof { x: string = \"x\"; y: u8; }; }";
+        assert_eq!(assert_same_inputs(adaptive, |grammar| grammar.minimize(false)), 0);
+    }
+    
+    #[test]
+    fn fold_literals_keeps_inputs() {
+        for source in FIXTURES {
+            assert_same_inputs(source, Grammar::fold_literals);
+        }
+        
+        let source = "struct Root { a: string = \"<\"; b: bytes = x\"00\"; c: u8; d: string = \">\"; e: string = \"!\"; optional f: string = \"?\"; }";
+        assert_eq!(assert_same_inputs(source, Grammar::fold_literals), 2);
+    }
+    
+    #[test]
+    fn inline_literals_keeps_inputs() {
+        for source in FIXTURES {
+            assert_same_inputs(source, |grammar| grammar.inline_literals(16));
+        }
+        
+        let source = "struct Tag { a: string = \"<\"; b: string = \"b>\"; } struct Root { repeats 1..3 t: Tag; c: u8; optional u: Tag; }";
+        assert_eq!(assert_same_inputs(source, |grammar| grammar.inline_literals(16)), 2);
+    }
+}
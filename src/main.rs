@@ -3,14 +3,19 @@
 use termcolor;
 use termcolor::WriteColor;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use clap::Parser;
+use grammar::HasOptions;
+use diagnostic::Diagnostic;
 
 mod grammar;
 mod frontend;
 mod backend;
+mod report;
+mod diff;
+mod diagnostic;
 
-#[derive(clap::Parser)]
+#[derive(clap::Parser, Clone)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
     #[clap(long, action, default_value_t = false)]
@@ -19,19 +24,144 @@ pub struct Args {
     #[clap(long, action, default_value_t = false)]
     print_stats: bool,
     
+    #[clap(long, action, default_value_t = false)]
+    deny_warnings: bool,
+    
+    #[clap(long, action, default_value_t = false)]
+    watch: bool,
+    
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    
     #[clap(long, action, default_value_t = false)]
     bench: bool,
     
     #[clap(short = 'o', value_parser)]
     outfile: Option<String>,
     
+    #[clap(long, value_parser, value_name = "FILE")]
+    shared: Option<String>,
+    
     #[clap(long, value_parser, default_value = "")]
     prefix: String,
     
+    #[clap(long, value_parser, value_name = "NAME")]
+    allow_undefined: Vec<String>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    report: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "NAME")]
+    focus: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "NAME", value_delimiter = ',')]
+    entrypoint: Vec<String>,
+    
+    #[clap(long, value_parser, value_name = "NAME")]
+    prune: Vec<String>,
+    
+    #[clap(long, value_parser, value_name = "NAME=VALUE")]
+    define: Vec<String>,
+    
+    #[clap(long, action, default_value_t = false)]
+    minimize: bool,
+    
+    #[clap(long, action, default_value_t = false)]
+    fold_literals: bool,
+    
+    #[clap(long, value_parser, value_name = "MAX_LEN")]
+    inline_literals: Option<usize>,
+    
+    #[clap(long, action, default_value_t = false)]
+    readable_names: bool,
+    
+    #[clap(long, action, default_value_t = false)]
+    profile: bool,
+    
+    #[clap(long, action, default_value_t = false)]
+    self_coverage: bool,
+    
+    #[clap(long, action, default_value_t = false)]
+    trace: bool,
+    
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..), value_name = "N")]
+    dedup: Option<u64>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    emit_grammar: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    dot: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    dict: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "OLD_GRAMMAR")]
+    diff: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    format: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    print_profile: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    print_trace: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "FILE")]
+    print_trace_stream: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "BYTES")]
+    check_size: Option<usize>,
+    
+    #[clap(long, value_parser, value_name = "DEPTH")]
+    print_derivations: Option<usize>,
+    
+    #[clap(long, value_parser, value_name = "DIR")]
+    seeds: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "DIR")]
+    samples: Option<String>,
+    
+    #[clap(long, value_parser, value_name = "N", default_value_t = 100)]
+    num_samples: usize,
+    
+    #[clap(long, value_parser, value_name = "NAME", default_value = "c")]
+    backend: String,
+    
+    #[clap(long, value_enum, value_name = "POLICY", default_value_t = OnExisting::Overwrite)]
+    on_existing: OnExisting,
+    
+    #[clap(long, value_enum, value_name = "FORMAT", default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
+    
     #[clap(value_parser)]
     grammar: String,
 }
 
+/// What to do with generated files from a previous run
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+pub enum OnExisting {
+    /// Replace them
+    Overwrite,
+    
+    /// Rename them to <FILE>.bak before writing the new files
+    Backup,
+    
+    /// Stop with an error
+    Keep,
+}
+
+/// How errors and warnings are printed
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    /// Colored messages with the lines around the location
+    Human,
+    
+    /// One JSON object per line. Reports like --print-stats stay plain text
+    Json,
+}
+
 /// Given a number `n`, return how many decimal digits are
 /// needed to represent this number
 fn get_decimal_length(mut n: usize) -> usize {
@@ -95,8 +225,24 @@ fn print_line_context(stream: &mut termcolor::StandardStream, view: &frontend::S
     Ok(())
 }
 
-/// Print a neat error message for the given lexer error
-fn print_lexing_error(view: &frontend::SourceView, error: &frontend::LexerError) -> Result<(), std::io::Error> {
+/// Diagnostics for the given lexer error
+fn lexing_diagnostics(error: &frontend::LexerError) -> Vec<Diagnostic> {
+    let at = |pos: &usize| Some(*pos..*pos + 1);
+    
+    vec![match error {
+        frontend::LexerError::EOF(message) => Diagnostic::error("UnexpectedEof", format!("Hit an unexpected EOF: {}", message), None),
+        frontend::LexerError::ExpectedIdentifier(pos) => Diagnostic::error("ExpectedIdentifier", "Expected an identifier".to_string(), at(pos)),
+        frontend::LexerError::MissingWhitespace(pos) => Diagnostic::error("MissingWhitespace", "A whitespace is missing".to_string(), at(pos)),
+        frontend::LexerError::ExpectedChar(pos, c) => Diagnostic::error("ExpectedChar", format!("Expected the character '{}'", c), at(pos)),
+        frontend::LexerError::ExpectedKeyword(pos, keyword) => Diagnostic::error("ExpectedKeyword", format!("Expected the keyword '{}'", keyword), at(pos)),
+        frontend::LexerError::InvalidNumber(pos) => Diagnostic::error("InvalidNumber", "Invalid number".to_string(), at(pos)),
+        frontend::LexerError::InvalidCharLiteral(pos) => Diagnostic::error("InvalidCharLiteral", "Invalid character constant".to_string(), at(pos)),
+        frontend::LexerError::ExpectedLiteral(pos, literals) => Diagnostic::error("ExpectedLiteral", format!("Expected literal '{}'", literals), at(pos)),
+    }]
+}
+
+/// Print the title of an error message
+fn print_error_title(stream: &mut termcolor::StandardStream, title: &str) -> Result<(), std::io::Error> {
     let mut bold_red = termcolor::ColorSpec::new();
     bold_red.set_bg(None);
     bold_red.set_bold(true);
@@ -105,180 +251,107 @@ fn print_lexing_error(view: &frontend::SourceView, error: &frontend::LexerError)
     slim_red.set_bg(None);
     slim_red.set_bold(false);
     slim_red.set_fg(Some(termcolor::Color::Red));
-    let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
     
     stream.set_color(&slim_red)?;
     stream.write_all(b" ----=== ")?;
     stream.set_color(&bold_red)?;
-    stream.write_all(b"Parsing Error")?;
+    stream.write_all(title.as_bytes())?;
     stream.set_color(&slim_red)?;
     stream.write_all(b" ===----")?;
     stream.reset()?;
-    writeln!(&mut stream, "")?;
-    
-    match error {
-        frontend::LexerError::EOF(message) => {
-            writeln!(&mut stream, "Hit an unexpected EOF: {}", message)?;
-        },
-        frontend::LexerError::ExpectedIdentifier(pos) => {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: Expected an identifier", line, col)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::LexerError::MissingWhitespace(pos) => {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: A whitespace is missing", line, col)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::LexerError::ExpectedChar(pos, c) => {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: Expected the character '{}'", line, col, c)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::LexerError::ExpectedKeyword(pos, keyword) =>  {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: Expected the keyword '{}'", line, col, keyword)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::LexerError::InvalidNumber(pos) => {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: Invalid number", line, col)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::LexerError::InvalidCharLiteral(pos) => {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: Invalid character constant", line, col)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::LexerError::ExpectedLiteral(pos, literals) => {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: Expected literal '{}'", line, col, literals)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
+    writeln!(stream)
+}
+
+/// Print a diagnostic with the lines around its location
+fn print_diagnostic(stream: &mut termcolor::StandardStream, view: &frontend::SourceView, diagnostic: &Diagnostic) -> Result<(), std::io::Error> {
+    match &diagnostic.span {
+        Some(span) => {
+            let (line, col) = view.lineinfo(span.start);
+            writeln!(stream, "In line {} column {}: {}", line, col, diagnostic.message)?;
+            print_line_context(stream, view, line, col, span.len())
         },
+        None => writeln!(stream, "{}", diagnostic.message),
+    }
+}
+
+/// Print a neat error message for the given lexer error
+fn print_lexing_error(view: &frontend::SourceView, error: &frontend::LexerError) -> Result<(), std::io::Error> {
+    let diagnostics = lexing_diagnostics(error);
+    
+    if diagnostic::emit_json(&diagnostics, Some(view)) {
+        return Ok(());
+    }
+    
+    let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
+    print_error_title(&mut stream, "Parsing Error")?;
+    
+    for diagnostic in &diagnostics {
+        print_diagnostic(&mut stream, view, diagnostic)?;
     }
     
     writeln!(&mut stream, "")?;
     Ok(())
 }
 
+/// Diagnostics for the given parser error
+fn parsing_diagnostics(view: &frontend::SourceView, error: &frontend::ParserError) -> Vec<Diagnostic> {
+    let at = |pos: &usize| Some(*pos..*pos + 1);
+    
+    let diagnostic = match error {
+        frontend::ParserError::UnknownOptionValue(value) => Diagnostic::error("UnknownOptionValue", "Unknown option value".to_string(), Some(value.clone())),
+        frontend::ParserError::UnknownOptionName(name) => Diagnostic::error("UnknownOptionName", "Unknown option name".to_string(), Some(name.clone())),
+        frontend::ParserError::DuplicateContainerName(name) => Diagnostic::error("DuplicateContainerName", format!("Name '{}' already exists", view.range(name)), Some(name.clone())),
+        frontend::ParserError::EOF(message) => Diagnostic::error("UnexpectedEof", format!("Hit an unexpected EOF: {}", message), None),
+        frontend::ParserError::UnexpectedToken(Some(pos), message) => Diagnostic::error("UnexpectedToken", message.to_string(), at(pos)),
+        frontend::ParserError::UnexpectedToken(None, message) => Diagnostic::error("UnexpectedToken", format!("Invalid token sequence by lexer: {}\nUnable to provide more info", message), None),
+        frontend::ParserError::InvalidKeyword(range, message) => Diagnostic::error("InvalidKeyword", message.to_string(), Some(range.clone())),
+        frontend::ParserError::InvalidNumber(base, num) => Diagnostic::error("InvalidNumber", format!("Invalid number of base {} given the type of the variable", base), Some(num.clone())),
+        frontend::ParserError::InvalidRange(range) => Diagnostic::error("InvalidRange", "Invalid bounds in range".to_string(), Some(range.clone())),
+        frontend::ParserError::CharacterNotAllowed(ch) => Diagnostic::error("CharacterNotAllowed", "Char literals are not allowed here".to_string(), Some(ch.clone())),
+        frontend::ParserError::InvalidCharacter(ch) => Diagnostic::error("InvalidCharacter", "Invalid char literal".to_string(), Some(ch.clone())),
+        frontend::ParserError::InvalidNumberset(pos) => Diagnostic::error("InvalidNumberset", "Invalid numberset".to_string(), at(pos)),
+        frontend::ParserError::InvalidTypeName(name) => Diagnostic::error("InvalidTypeName", "Specified type and value don't match".to_string(), Some(name.clone())),
+        frontend::ParserError::InvalidString(string, message) => Diagnostic::error("InvalidString", message.to_string(), Some(string.clone())),
+        frontend::ParserError::NoRoot => Diagnostic::error("NoRoot", format!("No root {0} was found. Name the {0} where generation shall start '{1}' or declare it with '{2} <name>;'.", frontend::keywords::CONTAINER, frontend::keywords::ROOT_CONTAINER, frontend::keywords::ENTRYPOINT), None),
+        frontend::ParserError::UnresolvedRef(reference) => Diagnostic::error("UnresolvedRef", "Couldn't find a struct with the given name".to_string(), Some(reference.clone())),
+        frontend::ParserError::UnresolvedRefs(references) => {
+            return references.iter().map(|reference| Diagnostic::error("UnresolvedRef", format!("Couldn't find a struct with the name '{}'", view.range(reference)), Some(reference.clone()))).collect();
+        },
+        frontend::ParserError::UnknownStruct(name) => Diagnostic::error("UnknownStruct", format!("There is no {} with the name '{}'", frontend::keywords::CONTAINER, name), None),
+        frontend::ParserError::DuplicateConstantName(name) => Diagnostic::error("DuplicateConstantName", format!("Constant '{}' already exists", view.range(name)), Some(name.clone())),
+        frontend::ParserError::UnknownConstant(name) => Diagnostic::error("UnknownConstant", "Couldn't find a constant with the given name".to_string(), Some(name.clone())),
+        frontend::ParserError::PrunedEntrypoint(name) => Diagnostic::error("PrunedEntrypoint", format!("The entrypoint '{}' cannot be skipped", name), None),
+        frontend::ParserError::EmptyBlock(block) => Diagnostic::error("EmptyBlock", "Blocks without variables are not allowed".to_string(), at(block)),
+        frontend::ParserError::IllegalContainerName(name) => Diagnostic::error("IllegalContainerName", format!("Illegal name for {}", frontend::keywords::CONTAINER), Some(name.clone())),
+        frontend::ParserError::NonLocalOption(name) => Diagnostic::error("NonLocalOption", "This option is only allowed on a global level".to_string(), Some(name.clone())),
+        frontend::ParserError::NonGlobalOption(name) => Diagnostic::error("NonGlobalOption", "This option is only allowed inside a block".to_string(), Some(name.clone())),
+        frontend::ParserError::IllegalOptionValue(value) => Diagnostic::error("IllegalOptionValue", "Invalid option value".to_string(), Some(value.clone())),
+    };
+    
+    vec![diagnostic]
+}
+
 /// Print a neat error message for a given parser error
 fn print_parsing_error(view: &frontend::SourceView, error: &frontend::ParserError) -> Result<(), std::io::Error> {
-    let mut bold_red = termcolor::ColorSpec::new();
-    bold_red.set_bg(None);
-    bold_red.set_bold(true);
-    bold_red.set_fg(Some(termcolor::Color::Red));
-    let mut slim_red = termcolor::ColorSpec::new();
-    slim_red.set_bg(None);
-    slim_red.set_bold(false);
-    slim_red.set_fg(Some(termcolor::Color::Red));
+    let diagnostics = parsing_diagnostics(view, error);
+    
+    if diagnostic::emit_json(&diagnostics, Some(view)) {
+        return Ok(());
+    }
+    
     let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
+    print_error_title(&mut stream, "Parsing Error")?;
     
-    stream.set_color(&slim_red)?;
-    stream.write_all(b" ----=== ")?;
-    stream.set_color(&bold_red)?;
-    stream.write_all(b"Parsing Error")?;
-    stream.set_color(&slim_red)?;
-    stream.write_all(b" ===----")?;
-    stream.reset()?;
-    writeln!(&mut stream, "")?;
+    if diagnostics.len() > 1 {
+        writeln!(&mut stream, "Couldn't find structs for {} references:", diagnostics.len())?;
+    }
     
-    match error {
-        frontend::ParserError::UnknownOptionValue(value) => {
-            let (line, col) = view.lineinfo(value.start);
-            writeln!(&mut stream, "In line {} column {}: Unknown option value", line, col)?;
-            print_line_context(&mut stream, view, line, col, value.len())?;
-        },
-        frontend::ParserError::UnknownOptionName(name) => {
-            let (line, col) = view.lineinfo(name.start);
-            writeln!(&mut stream, "In line {} column {}: Unknown option name", line, col)?;
-            print_line_context(&mut stream, view, line, col, name.len())?;
-        },
-        frontend::ParserError::DuplicateContainerName(name) => {
-            let (line, col) = view.lineinfo(name.start);
-            writeln!(&mut stream, "In line {} column {}: Name '{}' already exists", line, col, view.range(&name))?;
-            print_line_context(&mut stream, view, line, col, name.len())?;
-        },
-        frontend::ParserError::EOF(message) => {
-            writeln!(&mut stream, "Hit an unexpected EOF: {}", message)?;
-        },
-        frontend::ParserError::UnexpectedToken(pos, message) => {
-            if let Some(pos) = pos {
-                let (line, col) = view.lineinfo(*pos);
-                writeln!(&mut stream, "In line {} column {}: {}", line, col, message)?;
-                print_line_context(&mut stream, view, line, col, 1)?;
-            } else {
-                writeln!(&mut stream, "Invalid token sequence by lexer: {}", message)?;
-                writeln!(&mut stream, "Unable to provide more info")?;
-            }
-        },
-        frontend::ParserError::InvalidKeyword(range, message) => {
-            let (line, col) = view.lineinfo(range.start);
-            writeln!(&mut stream, "In line {} column {}: {}", line, col, message)?;
-            print_line_context(&mut stream, view, line, col, range.len())?;
-        },
-        frontend::ParserError::InvalidNumber(base, num) => {
-            let (line, col) = view.lineinfo(num.start);
-            writeln!(&mut stream, "In line {} column {}: Invalid number of base {} given the type of the variable", line, col, base)?;
-            print_line_context(&mut stream, view, line, col, num.len())?;
-        },
-        frontend::ParserError::InvalidRange(range) => {
-            let (line, col) = view.lineinfo(range.start);
-            writeln!(&mut stream, "In line {} column {}: Invalid bounds in range", line, col)?;
-            print_line_context(&mut stream, view, line, col, range.len())?;
-        },
-        frontend::ParserError::CharacterNotAllowed(ch) => {
-            let (line, col) = view.lineinfo(ch.start);
-            writeln!(&mut stream, "In line {} column {}: Char literals are not allowed here", line, col)?;
-            print_line_context(&mut stream, view, line, col, ch.len())?;
-        },
-        frontend::ParserError::InvalidCharacter(ch) => {
-            let (line, col) = view.lineinfo(ch.start);
-            writeln!(&mut stream, "In line {} column {}: Invalid char literal", line, col)?;
-            print_line_context(&mut stream, view, line, col, ch.len())?;
-        },
-        frontend::ParserError::InvalidNumberset(pos) => {
-            let (line, col) = view.lineinfo(*pos);
-            writeln!(&mut stream, "In line {} column {}: Invalid numberset", line, col)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::ParserError::InvalidTypeName(name) => {
-            let (line, col) = view.lineinfo(name.start);
-            writeln!(&mut stream, "In line {} column {}: Specified type and value don't match", line, col)?;
-            print_line_context(&mut stream, view, line, col, name.len())?;
-        },
-        frontend::ParserError::InvalidString(string, message) => {
-            let (line, col) = view.lineinfo(string.start);
-            writeln!(&mut stream, "In line {} column {}: {}", line, col, message)?;
-            print_line_context(&mut stream, view, line, col, string.len())?;
-        },
-        frontend::ParserError::NoRoot => {
-            writeln!(&mut stream, "No root {0} was found. Name the {0} where generation shall start '{1}'.", frontend::keywords::CONTAINER, frontend::keywords::ROOT_CONTAINER)?;
-        },
-        frontend::ParserError::UnresolvedRef(reference) => {
-            let (line, col) = view.lineinfo(reference.start);
-            writeln!(&mut stream, "In line {} column {}: Couldn't find a struct with the given name", line, col)?;
-            print_line_context(&mut stream, view, line, col, reference.len())?;
-        },
-        frontend::ParserError::EmptyBlock(block) => {
-            let (line, col) = view.lineinfo(*block);
-            writeln!(&mut stream, "In line {} column {}: Blocks without variables are not allowed", line, col)?;
-            print_line_context(&mut stream, view, line, col, 1)?;
-        },
-        frontend::ParserError::IllegalContainerName(name) => {
-            let (line, col) = view.lineinfo(name.start);
-            writeln!(&mut stream, "In line {} column {}: Illegal name for {}", line, col, frontend::keywords::CONTAINER)?;
-            print_line_context(&mut stream, view, line, col, name.len())?;
-        },
-        frontend::ParserError::NonLocalOption(name) => {
-            let (line, col) = view.lineinfo(name.start);
-            writeln!(&mut stream, "In line {} column {}: This option is only allowed on a global level", line, col)?;
-            print_line_context(&mut stream, view, line, col, name.len())?;
-        },
-        frontend::ParserError::IllegalOptionValue(value) => {
-            let (line, col) = view.lineinfo(value.start);
-            writeln!(&mut stream, "In line {} column {}: Invalid option value", line, col)?;
-            print_line_context(&mut stream, view, line, col, value.len())?;
-        },
+    for diagnostic in &diagnostics {
+        if diagnostics.len() > 1 {
+            writeln!(&mut stream)?;
+        }
+        
+        print_diagnostic(&mut stream, view, diagnostic)?;
     }
     
     writeln!(&mut stream, "")?;
@@ -286,24 +359,17 @@ fn print_parsing_error(view: &frontend::SourceView, error: &frontend::ParserErro
 }
 
 fn print_dead_containers(view: &frontend::SourceView, containers: Vec<grammar::ContainerId>, grammar: &grammar::Grammar) -> Result<(), std::io::Error> {
-    let mut bold_red = termcolor::ColorSpec::new();
-    bold_red.set_bg(None);
-    bold_red.set_bold(true);
-    bold_red.set_fg(Some(termcolor::Color::Red));
-    let mut slim_red = termcolor::ColorSpec::new();
-    slim_red.set_bg(None);
-    slim_red.set_bold(false);
-    slim_red.set_fg(Some(termcolor::Color::Red));
-    let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
+    let diagnostics: Vec<Diagnostic> = containers.iter().map(|id| {
+        let name = grammar.container(*id).unwrap().name().unwrap();
+        Diagnostic::error("UnusedStruct", format!("The {} is never used", describe_container(view, grammar, *id)), Some(name.clone()))
+    }).collect();
     
-    stream.set_color(&slim_red)?;
-    stream.write_all(b" ----=== ")?;
-    stream.set_color(&bold_red)?;
-    stream.write_all(b"Grammar Error")?;
-    stream.set_color(&slim_red)?;
-    stream.write_all(b" ===----")?;
-    stream.reset()?;
-    writeln!(&mut stream, "")?;
+    if diagnostic::emit_json(&diagnostics, Some(view)) {
+        return Ok(());
+    }
+    
+    let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
+    print_error_title(&mut stream, "Grammar Error")?;
     
     writeln!(&mut stream, "The following containers are never being used:")?;
     
@@ -321,25 +387,50 @@ fn print_dead_containers(view: &frontend::SourceView, containers: Vec<grammar::C
     Ok(())
 }
 
-fn print_cycle(view: &frontend::SourceView, cycle: (grammar::ContainerId, grammar::ContainerId), grammar: &grammar::Grammar) -> Result<(), std::io::Error> {
-    let mut bold_red = termcolor::ColorSpec::new();
-    bold_red.set_bg(None);
-    bold_red.set_bold(true);
-    bold_red.set_fg(Some(termcolor::Color::Red));
-    let mut slim_red = termcolor::ColorSpec::new();
-    slim_red.set_bg(None);
-    slim_red.set_bold(false);
-    slim_red.set_fg(Some(termcolor::Color::Red));
+fn print_unproductive_containers(view: &frontend::SourceView, containers: Vec<grammar::ContainerId>, grammar: &grammar::Grammar) -> Result<(), std::io::Error> {
+    let diagnostics: Vec<Diagnostic> = containers.iter().map(|id| {
+        let name = grammar.container(*id).unwrap().name().unwrap();
+        Diagnostic::error("InfiniteRecursion", format!("The {} recurses infinitely and can never be generated", describe_container(view, grammar, *id)), Some(name.clone()))
+    }).collect();
+    
+    if diagnostic::emit_json(&diagnostics, Some(view)) {
+        return Ok(());
+    }
+    
     let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
+    print_error_title(&mut stream, "Grammar Error")?;
     
-    stream.set_color(&slim_red)?;
-    stream.write_all(b" ----=== ")?;
-    stream.set_color(&bold_red)?;
-    stream.write_all(b"Grammar Error")?;
-    stream.set_color(&slim_red)?;
-    stream.write_all(b" ===----")?;
-    stream.reset()?;
-    writeln!(&mut stream, "")?;
+    writeln!(&mut stream, "The following containers recurse infinitely and can never be generated:")?;
+    
+    for id in containers {
+        let name = grammar.container(id).unwrap().name().unwrap();
+        let (line, col) = view.lineinfo(name.start);
+        if name.is_empty() {
+            writeln!(&mut stream, " - anonymous struct in line {} column {}", line, col)?;
+        } else {
+            writeln!(&mut stream, " - '{}' in line {} column {}", view.range(name), line, col)?;
+        }
+    }
+    
+    writeln!(&mut stream)?;
+    writeln!(&mut stream, "Make the recursion optional or add an alternative without recursion")?;
+    
+    Ok(())
+}
+
+fn print_cycle(view: &frontend::SourceView, cycle: (grammar::ContainerId, grammar::ContainerId), grammar: &grammar::Grammar) -> Result<(), std::io::Error> {
+    let diagnostics: Vec<Diagnostic> = vec![Diagnostic::error(
+        "Cycle",
+        format!("There exists a cycle between the {} and the {}", describe_container(view, grammar, cycle.0), describe_container(view, grammar, cycle.1)),
+        Some(grammar.container(cycle.0).unwrap().name().unwrap().clone()),
+    )];
+    
+    if diagnostic::emit_json(&diagnostics, Some(view)) {
+        return Ok(());
+    }
+    
+    let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
+    print_error_title(&mut stream, "Grammar Error")?;
     
     writeln!(&mut stream, "There exists a cycle between the two containers")?;
     
@@ -366,6 +457,10 @@ fn print_cycle(view: &frontend::SourceView, cycle: (grammar::ContainerId, gramma
 }
 
 fn warning(msg: &str) -> Result<(), std::io::Error> {
+    if diagnostic::emit_json(&[Diagnostic::warning("Other", msg.to_string(), None)], None) {
+        return Ok(());
+    }
+    
     let mut yellow = termcolor::ColorSpec::new();
     yellow.set_bg(None);
     yellow.set_bold(true);
@@ -378,7 +473,174 @@ fn warning(msg: &str) -> Result<(), std::io::Error> {
     Ok(())
 }
 
+/// Oneofs have no name so describe them by the container they are defined in
+fn describe_oneof(view: &frontend::SourceView, grammar: &grammar::Grammar, id: grammar::ContainerId) -> String {
+    let parent = grammar.containers().find(|parent| parent.variables().iter().any(|var| matches!(
+        var.typ(),
+        grammar::VariableType::Oneof(target) if *target == id
+    )));
+    
+    match parent {
+        None => "a oneof".to_string(),
+        Some(parent) => match parent.name() {
+            Some(name) if name.is_empty() => {
+                let (line, col) = view.lineinfo(name.start);
+                format!("the oneof in the anonymous struct in line {} column {}", line, col)
+            },
+            Some(name) => {
+                let (line, col) = view.lineinfo(name.start);
+                format!("the oneof in '{}' in line {} column {}", view.range(name), line, col)
+            },
+            None => format!("a oneof nested in {}", describe_oneof(view, grammar, parent.id())),
+        },
+    }
+}
+
+fn error(msg: &str) -> Result<(), std::io::Error> {
+    if diagnostic::emit_json(&[Diagnostic::error("Other", msg.to_string(), None)], None) {
+        return Ok(());
+    }
+    
+    let mut red = termcolor::ColorSpec::new();
+    red.set_bg(None);
+    red.set_bold(true);
+    red.set_fg(Some(termcolor::Color::Red));
+    let mut stream = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
+    stream.set_color(&red)?;
+    write!(&mut stream, "Error: ")?;
+    stream.reset()?;
+    writeln!(&mut stream, "{}", msg)?;
+    Ok(())
+}
+
+/// Check that all files of the backend can be written before any work
+/// is done and apply the policy for existing files
+fn prepare_outputs(args: &Args, backend: &dyn backend::Backend, outfile: &str) -> Result<(), String> {
+    if outfile == "-" {
+        return Ok(());
+    }
+    
+    let outputs = backend.outputs(outfile);
+    let grammar = std::fs::canonicalize(&args.grammar).ok();
+    
+    for path in &outputs {
+        let path = Path::new(path);
+        
+        if path.is_dir() {
+            return Err(format!("'{}' is a directory. Pass the name of the .c file to create with -o", path.display()));
+        }
+        
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        
+        if !parent.is_dir() {
+            return Err(format!("The directory '{}' does not exist", parent.display()));
+        }
+        
+        if grammar.is_some() && std::fs::canonicalize(path).ok() == grammar {
+            return Err(format!("Writing '{}' would overwrite the grammar", path.display()));
+        }
+        
+        if path.exists() {
+            if args.on_existing == OnExisting::Keep {
+                return Err(format!("'{}' already exists. Pass --on-existing overwrite or --on-existing backup to replace it", path.display()));
+            }
+            
+            if let Err(err) = std::fs::OpenOptions::new().write(true).open(path) {
+                return Err(format!("'{}' cannot be written: {}", path.display(), err));
+            }
+        } else {
+            // Create and remove the file to find out whether the directory is writable
+            match std::fs::OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(_) => {
+                    let _ = std::fs::remove_file(path);
+                },
+                Err(err) => {
+                    return Err(format!("'{}' cannot be created: {}", path.display(), err));
+                },
+            }
+        }
+    }
+    
+    if args.on_existing == OnExisting::Backup {
+        for path in &outputs {
+            if Path::new(path).exists() {
+                let backup = format!("{}.bak", path);
+                
+                if let Err(err) = std::fs::rename(path, &backup) {
+                    return Err(format!("Could not back up '{}' to '{}': {}", path, backup, err));
+                }
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Print warnings about constructs that are valid but likely not what the author intended.
+/// Returns the number of warnings.
+fn lint_grammar(view: &frontend::SourceView, grammar: &grammar::Grammar, unused_constants: &[frontend::SourceRange]) -> usize {
+    let mut warnings = Vec::<Diagnostic>::new();
+    let name_of = |id: grammar::ContainerId| grammar.container(id).unwrap().name().cloned();
+    
+    for (first, second) in grammar.duplicate_structs() {
+        let first = grammar.container(first).unwrap().name().unwrap();
+        let second = grammar.container(second).unwrap().name().unwrap();
+        let (first_line, first_col) = view.lineinfo(first.start);
+        let (second_line, second_col) = view.lineinfo(second.start);
+        warnings.push(Diagnostic::warning("DuplicateStruct", format!(
+            "'{}' in line {} column {} has the same contents as '{}' in line {} column {}",
+            view.range(second), second_line, second_col,
+            view.range(first), first_line, first_col,
+        ), Some(second.clone())));
+    }
+    
+    for (id, first, second) in grammar.duplicate_variables() {
+        warnings.push(Diagnostic::warning("DuplicateVariables", format!("Variables #{} and #{} of {} generate the same inputs", first + 1, second + 1, describe_oneof(view, grammar, id)), None));
+    }
+    
+    for (id, var) in grammar.unrestricted_numbers() {
+        warnings.push(Diagnostic::warning("UnrestrictedNumber", format!("Variable #{} of {} can take every value of its type. Leave out the assignment instead", var + 1, describe_container(view, grammar, id)), name_of(id)));
+    }
+    
+    for id in grammar.single_variable_structs() {
+        warnings.push(Diagnostic::warning("SingleVariable", format!("The {} contains only one variable. Use the variable directly instead", describe_container(view, grammar, id)), name_of(id)));
+    }
+    
+    let sizes = grammar.minimal_sizes();
+    
+    for container in grammar.containers() {
+        if let Some((owner, limit)) = container.options().max_len() {
+            if *owner == container.id() && sizes[owner] > *limit {
+                warnings.push(Diagnostic::warning("MaxLenExceeded", format!("Every output of {} exceeds its max_len of {} bytes", describe_container(view, grammar, *owner), limit), name_of(*owner)));
+            }
+        }
+    }
+    
+    for name in unused_constants {
+        let (line, col) = view.lineinfo(name.start);
+        warnings.push(Diagnostic::warning("UnusedConstant", format!("Constant '{}' in line {} column {} is never used", view.range(name), line, col), Some(name.clone())));
+    }
+    
+    if !diagnostic::emit_json(&warnings, Some(view)) {
+        for diagnostic in &warnings {
+            let _ = warning(&diagnostic.message);
+        }
+    }
+    
+    warnings.len()
+}
+
 fn verify_grammar(view: &frontend::SourceView, grammar: &grammar::Grammar, args: &Args) {
+    let unproductive = grammar.unproductive_containers();
+    
+    if !unproductive.is_empty() {
+        let _ = print_unproductive_containers(view, unproductive, grammar);
+        std::process::exit(1);
+    }
+    
     let graph = frontend::graph::GrammarGraph::minimal_graph(grammar);
     
     if let Some(cycle) = graph.cycle() {
@@ -404,15 +666,35 @@ fn verify_grammar(view: &frontend::SourceView, grammar: &grammar::Grammar, args:
     }
     
     if args.print_stats {
+        print_size(grammar);
+        
         if cycle.is_some() {
-            let _ = warning("Graph contains cycles so no stats will be printed");
+            let _ = warning("Graph contains cycles so only the size of the grammar will be printed");
+            
+            println!("Recursive structs:");
+            
+            for group in graph.recursive_groups() {
+                let names: Vec<String> = group.iter().map(|id| describe_container(view, grammar, *id)).collect();
+                println!("  - {}", names.join(", "));
+            }
         } else {
-            print_stats(grammar);
+            print_stats(view, grammar);
         }
     }
 }
 
-fn print_stats(grammar: &grammar::Grammar) {    
+fn print_size(grammar: &grammar::Grammar) {
+    let size = frontend::stats::GrammarSize::from_grammar(grammar);
+    
+    println!("Grammar size:");
+    println!("  - structs = {}", size.structs);
+    println!("  - oneofs = {}", size.oneofs);
+    println!("  - variables = {} (at most {}, {:.1} on average per struct or oneof)", size.variables, size.max_variables, size.avg_variables());
+    println!("  - literals = {} ({} bytes)", size.literals, size.literal_bytes);
+    println!("  - numbersets = {}", size.numbersets);
+}
+
+fn print_stats(view: &frontend::SourceView, grammar: &grammar::Grammar) {    
     let stats = frontend::stats::GrammarStats::from_grammar(grammar);
     
     println!("Grammar stats:");
@@ -437,6 +719,243 @@ fn print_stats(grammar: &grammar::Grammar) {
     } else {
         println!("too big");
     }
+    
+    let expected = stats.expected_input_size();
+    println!("  - expected input = {:.1} bytes (std. dev. {:.1})", expected.mean, expected.std_dev());
+    
+    if !stats.shares().is_empty() {
+        println!("  - share of expected input per struct:");
+    }
+    
+    for share in stats.shares() {
+        let name = grammar.container(share.id).unwrap().name().unwrap();
+        let description = if name.is_empty() {
+            let (line, col) = view.lineinfo(name.start);
+            format!("anonymous struct in line {} column {}", line, col)
+        } else {
+            format!("'{}'", view.range(name))
+        };
+        
+        println!(
+            "      {:5.1}% {} ({:.1} calls, {:.1} bytes each){}",
+            share.share * 100.0,
+            description,
+            share.calls,
+            share.size.mean,
+            if share.is_dominant() { " <- dominates input size" } else { "" }
+        );
+    }
+    
+    println!("  - distinct inputs = 2^{:.1}", stats.input_choices());
+    
+    if !stats.choices().is_empty() {
+        println!("  - distinct outputs per struct:");
+    }
+    
+    for choices in stats.choices() {
+        println!("      2^{:<6.1} {}", choices.bits, describe_container(view, grammar, choices.id));
+    }
+}
+
+/// Describe a struct or oneof for the user
+fn describe_container(view: &frontend::SourceView, grammar: &grammar::Grammar, id: grammar::ContainerId) -> String {
+    match grammar.container(id).unwrap().name() {
+        Some(name) if name.is_empty() => {
+            let (line, col) = view.lineinfo(name.start);
+            format!("anonymous struct in line {} column {}", line, col)
+        },
+        Some(name) => format!("'{}'", view.range(name)),
+        None => describe_oneof(view, grammar, id),
+    }
+}
+
+/// Print a table written by the profile() function of a generator that was created with --profile
+fn print_profile(view: &frontend::SourceView, grammar: &grammar::Grammar, path: &str) {
+    let content = std::fs::read_to_string(path).expect("Could not read profile");
+    let mut entries = Vec::<(grammar::ContainerId, u64, u64)>::new();
+    
+    for line in content.lines() {
+        let fields: Vec<u64> = line.split_whitespace().filter_map(|field| field.parse().ok()).collect();
+        
+        match fields.as_slice() {
+            [id, calls, cycles] if grammar.container(*id as grammar::ContainerId).is_some() => {
+                entries.push((*id as grammar::ContainerId, *calls, *cycles));
+            },
+            _ => {
+                let _ = warning(&format!("Ignoring line '{}' of the profile because it does not belong to this grammar", line));
+            },
+        }
+    }
+    
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.2));
+    
+    // Cycles include the cycles of callees so every entrypoint accounts for all
+    // cycles spent in its calls and the total is the sum over the entrypoints
+    let entrypoints = grammar.entrypoints();
+    let total: u64 = entries.iter().filter(|entry| entrypoints.contains(&entry.0)).map(|entry| entry.2).sum();
+    
+    println!("Profile (cycles include callees):");
+    
+    for (id, calls, cycles) in entries {
+        println!(
+            "  {:5.1}% {} ({} calls, {:.1} cycles each)",
+            if total > 0 { cycles as f64 * 100.0 / total as f64 } else { 0.0 },
+            describe_container(view, grammar, id),
+            calls,
+            if calls > 0 { cycles as f64 / calls as f64 } else { 0.0 },
+        );
+    }
+}
+
+/// Print the containers listed by the trace() function of a generator that was created with --trace
+fn print_trace(view: &frontend::SourceView, grammar: &grammar::Grammar, path: &str) {
+    let content = std::fs::read_to_string(path).expect("Could not read trace");
+    let mut depth = 0;
+    
+    println!("Trace (outermost container first):");
+    
+    for line in content.lines() {
+        let fields: Vec<u64> = line.split_whitespace().filter_map(|field| field.parse().ok()).collect();
+        
+        match fields.as_slice() {
+            [id, start, end] if grammar.container(*id as grammar::ContainerId).is_some() => {
+                println!(
+                    "  {}{} (bytes {}..{})",
+                    "  ".repeat(depth),
+                    describe_container(view, grammar, *id as grammar::ContainerId),
+                    start,
+                    end,
+                );
+                depth += 1;
+            },
+            _ => {
+                let _ = warning(&format!("Ignoring line '{}' of the trace because it does not belong to this grammar", line));
+            },
+        }
+    }
+}
+
+/// Write inputs generated by the built-in interpreter into `dir`, one file per input
+fn write_samples(grammar: &grammar::Grammar, dir: &str, count: usize) {
+    let mut interpreter = frontend::interpreter::Interpreter::new(grammar, 0, 4096);
+    
+    std::fs::create_dir_all(dir).expect("Could not create sample directory");
+    
+    for i in 0..count {
+        std::fs::write(Path::new(dir).join(format!("sample-{:04}", i)), interpreter.generate()).expect("Could not write sample");
+    }
+    
+    if !grammar.extern_names().is_empty() {
+        let _ = warning("The output of external functions is missing from the samples");
+    }
+    
+    println!("Wrote {} samples to {}", count, dir);
+}
+
+/// Write inputs that cover every variable of every oneof into `dir`, one file per input
+fn write_seeds(grammar: &grammar::Grammar, dir: &str) {
+    use frontend::derivation::{CoveringInputs, Fragment};
+    
+    let seeds = CoveringInputs::from_grammar(grammar);
+    let mut has_externs = false;
+    
+    std::fs::create_dir_all(dir).expect("Could not create seed directory");
+    
+    for (i, fragments) in seeds.inputs().iter().enumerate() {
+        let mut input = Vec::new();
+        
+        for fragment in fragments {
+            match fragment {
+                Fragment::Literal(bytes) => input.extend_from_slice(bytes),
+                Fragment::Native(width, value) => if cfg!(target_endian = "little") {
+                    input.extend_from_slice(&value.to_le_bytes()[..*width]);
+                } else {
+                    input.extend_from_slice(&value.to_be_bytes()[8 - *width..]);
+                },
+                Fragment::Extern(_) => has_externs = true,
+            }
+        }
+        
+        std::fs::write(Path::new(dir).join(format!("seed-{:04}", i)), input).expect("Could not write seed");
+    }
+    
+    if has_externs {
+        let _ = warning("The output of external functions is missing from the seeds");
+    }
+    
+    println!("Wrote {} seeds to {}", seeds.inputs().len(), dir);
+}
+
+fn print_derivations(view: &frontend::SourceView, grammar: &grammar::Grammar, depth: usize) {
+    println!("Derivations with a depth of at most {}:", depth);
+    
+    for count in frontend::stats::derivation_counts(grammar, depth) {
+        if count.bits == f64::NEG_INFINITY {
+            println!("  {:>9} {}", "none", describe_container(view, grammar, count.id));
+        } else {
+            println!("  2^{:<7.1} {}", count.bits, describe_container(view, grammar, count.id));
+        }
+    }
+}
+
+/// Tell whether the grammar can produce an input of at most `max_len` bytes.
+/// The smallest input is what the generator falls back to when the buffer gets tight.
+fn check_size(view: &frontend::SourceView, grammar: &grammar::Grammar, max_len: usize) -> bool {
+    let sizes = grammar.minimal_sizes();
+    let root = *grammar.entrypoint().unwrap();
+    let min_size = sizes[&root];
+    
+    if min_size == usize::MAX {
+        let _ = error(&format!("{} cannot produce any finite input", describe_container(view, grammar, root)));
+        return false;
+    }
+    
+    println!("Smallest input: {} bytes", min_size);
+    
+    if min_size > max_len {
+        let _ = error(&format!("The grammar cannot produce any input of at most {} bytes", max_len));
+        return false;
+    }
+    
+    println!("Inputs of at most {} bytes can be generated", max_len);
+    true
+}
+
+/// Print the records that the trace_stream() function of a generator that was created with --trace
+/// wrote, as one tree of containers per input
+fn print_trace_stream(view: &frontend::SourceView, grammar: &grammar::Grammar, path: &str) {
+    let content = std::fs::read(path).expect("Could not read trace stream");
+    let mut inputs = 0;
+    let mut depth = 0;
+    
+    if !content.len().is_multiple_of(8) {
+        let _ = warning("The trace stream is truncated, ignoring the incomplete record at the end");
+    }
+    
+    for record in content.chunks_exact(8) {
+        let word = u64::from_le_bytes(record.try_into().unwrap());
+        
+        if word == u64::MAX {
+            println!("Input #{}:", inputs);
+            inputs += 1;
+            depth = 0;
+            continue;
+        }
+        
+        let id = (word >> 1) as grammar::ContainerId;
+        
+        if grammar.container(id).is_none() {
+            let _ = warning(&format!("Ignoring container ID {} because it does not belong to this grammar", id));
+            continue;
+        }
+        
+        if word & 1 == 0 {
+            println!("  {}{}", "  ".repeat(depth), describe_container(view, grammar, id));
+            depth += 1;
+        } else {
+            depth = depth.saturating_sub(1);
+        }
+    }
 }
 
 fn run_benchmark(outfile: &str) {
@@ -634,44 +1153,496 @@ int main (int argc, char** argv) {{
         .wait();
 }
 
-fn main() {
-    let mut args = Args::parse();
+/// Compile the generator in `outfile` into a shared object with the system compiler.
+/// The compiler and its flags can be changed with the environment variables CC and CFLAGS.
+fn compile_shared(outfile: &str, path: &str) -> Result<(), String> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let cflags = std::env::var("CFLAGS").unwrap_or_else(|_| "-O3".to_string());
+    
+    let include_dir = match Path::new(outfile).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
     
-    if args.prefix.len() > 0 && !args.prefix.ends_with("_") {
-        args.prefix.push('_');
+    let status = std::process::Command::new(&cc)
+        .args(cflags.split_whitespace())
+        .arg("-shared")
+        .arg("-fPIC")
+        .arg("-I")
+        .arg(include_dir)
+        .arg("-o")
+        .arg(path)
+        .arg(outfile)
+        .status()
+        .map_err(|e| format!("Could not launch '{}': {}", cc, e))?;
+    
+    if !status.success() {
+        return Err(format!("'{}' could not compile {}", cc, outfile));
+    }
+    
+    Ok(())
+}
+
+/// Writes the messages of the `log` macros to stderr. Details of a stage
+/// are indented below the time of the stage.
+struct StderrLogger;
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        
+        if record.level() <= log::Level::Info {
+            eprintln!("{}", record.args());
+        } else {
+            eprintln!("               {}", record.args());
+        }
+    }
+    
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Install the logger with the level chosen by -v: the time of every stage
+/// with -v and details about the stages with -vv
+fn init_logger(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Log how long a stage of the translation took
+fn log_stage(stage: &str, start: std::time::Instant) {
+    log::info!("[{:9.3} ms] {}", start.elapsed().as_secs_f64() * 1000.0, stage);
+}
+
+/// Whether a value of --define can be used as an integer constant as it is
+fn is_integer_literal(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    
+    if let Some(hex) = digits.strip_prefix("0x") {
+        !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit())
+    } else if let Some(oct) = digits.strip_prefix("0o") {
+        !oct.is_empty() && oct.chars().all(|c| ('0'..='7').contains(&c))
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        !bin.is_empty() && bin.chars().all(|c| c == '0' || c == '1')
+    } else {
+        !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    }
+}
+
+/// Turn the values of --define into define directives. Values that are not integers become strings.
+fn define_directives(defines: &[String]) -> Result<String, String> {
+    let mut directives = String::new();
+    let mut names = Vec::new();
+    
+    for define in defines {
+        let (name, value) = match define.split_once('=') {
+            Some((name, value)) => (name, value),
+            None => return Err(format!("--define {} is missing a value. Use --define NAME=VALUE", define)),
+        };
+        
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("'{}' is not a valid name for a constant", name));
+        }
+        
+        if names.iter().any(|other| other == name) {
+            return Err(format!("The constant '{}' is defined more than once on the command line", name));
+        }
+        
+        let value = if is_integer_literal(value) {
+            value.to_string()
+        } else {
+            let mut literal = String::from(frontend::keywords::STRING_DELIM);
+            
+            for c in value.chars() {
+                match c {
+                    '\\' | '"' | '$' => {
+                        literal.push('\\');
+                        literal.push(c);
+                    },
+                    '\n' => literal.push_str("\\n"),
+                    '\r' => literal.push_str("\\r"),
+                    '\t' => literal.push_str("\\t"),
+                    c => literal.push(c),
+                }
+            }
+            
+            literal.push_str(frontend::keywords::STRING_DELIM);
+            literal
+        };
+        
+        directives.push_str(&format!("\n{} {} {}{}", frontend::keywords::DEFINE, name, value, frontend::keywords::TERMINATE_STATEMENT));
+        names.push(name.to_string());
     }
     
-    let view = frontend::SourceView::from_file(&args.grammar);
-    let mut lexer = frontend::Lexer::new(&view);
+    Ok(directives)
+}
+
+/// Read a grammar and add the constants of --define to it.
+/// Returns the grammar and the length of the file, after which the constants start.
+fn read_grammar(path: &str, defines: &[String]) -> (frontend::SourceView, usize) {
+    let mut view = frontend::SourceView::from_file(path);
+    let len = view.len();
+    
+    match define_directives(defines) {
+        Ok(directives) => view.append(&directives),
+        Err(msg) => {
+            let _ = error(&msg);
+            std::process::exit(1);
+        },
+    }
+    
+    (view, len)
+}
+
+/// Apply the parser settings of the command line
+fn configure_parser(parser: &mut frontend::Parser, path: &str, args: &Args) {
+    for pattern in &args.allow_undefined {
+        parser.allow_undefined(pattern);
+    }
+    
+    if let Some(name) = &args.focus {
+        parser.focus(name);
+    }
+    
+    for name in &args.entrypoint {
+        parser.entrypoint(name);
+    }
+    
+    for name in &args.prune {
+        parser.prune(name);
+    }
+    
+    for define in &args.define {
+        parser.override_constant(define.split_once('=').unwrap().0);
+    }
+    
+    if let Some(dir) = Path::new(path).parent() {
+        parser.include_dir(dir);
+    }
+}
+
+/// Lex and parse a grammar with the parser settings of the command line.
+/// Errors are printed and end the program.
+fn parse_grammar(view: &frontend::SourceView, path: &str, args: &Args) -> (grammar::Grammar, Vec<frontend::SourceRange>) {
+    let start = std::time::Instant::now();
+    let mut lexer = frontend::Lexer::new(view);
     
     let tokens = match lexer.lex() {
         Ok(tokens) => tokens,
         Err(error) => {
-            if let Err(_) = print_lexing_error(&view, &error) {
+            if print_lexing_error(view, &error).is_err() {
                 println!("{:?}", error);
             }
             std::process::exit(1);
         },
     };
     
-    let mut parser = frontend::Parser::new(&view, &tokens);
+    log_stage(&format!("Lexing {}", path), start);
+    log::debug!("{} tokens", tokens.len());
+    
+    let start = std::time::Instant::now();
+    let mut parser = frontend::Parser::new(view, &tokens);
+    configure_parser(&mut parser, path, args);
     
     let grammar = match parser.parse() {
         Ok(grammar) => grammar,
         Err(error) => {
-            if let Err(_) = print_parsing_error(&view, &error) {
+            if print_parsing_error(view, &error).is_err() {
                 println!("{:?}", error);
             }
             std::process::exit(1);
         },
     };
     
+    log_stage(&format!("Parsing {}", path), start);
+    log::debug!("{} structs and oneofs, {} numbersets, {} strings", grammar.containers().count(), grammar.numbersets().len(), grammar.strings().len());
+    
+    let unused_constants = parser.unused_constants();
+    (grammar, unused_constants)
+}
+
+/// Apply the transformations of the command line in the order the generator expects them
+fn transform_grammar(grammar: &mut grammar::Grammar, args: &Args) {
+    if let Some(max_len) = args.inline_literals {
+        let start = std::time::Instant::now();
+        let count = grammar.inline_literals(max_len);
+        log_stage("Inlining literals", start);
+        log::debug!("{} references inlined", count);
+    }
+    
+    if args.fold_literals {
+        let start = std::time::Instant::now();
+        let count = grammar.fold_literals();
+        log_stage("Folding literals", start);
+        log::debug!("{} variables removed", count);
+    }
+    
+    if args.minimize {
+        let start = std::time::Instant::now();
+        let count = grammar.minimize(args.self_coverage);
+        log_stage("Minimizing", start);
+        log::debug!("{} structs and oneofs removed", count);
+    }
+}
+
+/// Interval in which --watch checks the grammar for changes
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Files that the grammar embeds with `file()`. Errors are left to the runs of --watch,
+/// a grammar that can't be parsed only has the files that come before the error.
+fn file_dependencies(args: &Args) -> Vec<PathBuf> {
+    let mut view = match std::fs::read_to_string(&args.grammar) {
+        Ok(content) => frontend::SourceView::new(&content),
+        Err(_) => return Vec::new(),
+    };
+    view.append(&define_directives(&args.define).unwrap_or_default());
+    
+    let tokens = match frontend::Lexer::new(&view).lex() {
+        Ok(tokens) => tokens,
+        Err(_) => return Vec::new(),
+    };
+    
+    let mut parser = frontend::Parser::new(&view, &tokens);
+    configure_parser(&mut parser, &args.grammar, args);
+    let _ = parser.parse();
+    parser.files().to_vec()
+}
+
+/// Run chameleon with the same arguments except --watch every time the grammar
+/// or one of the files it embeds with `file()` changes.
+/// Every run is a separate process so that errors, which end the program, only end that run.
+fn watch(args: &Args) -> ! {
+    let exe = std::env::current_exe().expect("Could not find the chameleon executable");
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().skip(1).filter(|arg| arg != "--watch").collect();
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut files = Vec::new();
+    let mut last_grammar = None;
+    let mut last = Vec::new();
+    
+    loop {
+        let grammar = modified(Path::new(&args.grammar));
+        
+        // The dependencies can only change together with the grammar
+        if grammar != last_grammar {
+            files = file_dependencies(args);
+            last_grammar = grammar;
+        }
+        
+        let current: Vec<_> = std::iter::once(grammar).chain(files.iter().map(|file| modified(file))).collect();
+        
+        if grammar.is_some() && current != last {
+            last = current;
+            println!("----- {} -----", args.grammar);
+            
+            match std::process::Command::new(&exe).args(&argv).status() {
+                Ok(status) if status.success() => println!("Done"),
+                Ok(_) => println!("Failed. Waiting for changes..."),
+                Err(e) => {
+                    let _ = error(&format!("Could not run chameleon: {}", e));
+                    std::process::exit(1);
+                },
+            }
+        }
+        
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+fn main() {
+    let mut args = Args::parse();
+    init_logger(args.verbose);
+    
+    if args.watch {
+        watch(&args);
+    }
+    
+    if args.message_format == MessageFormat::Json {
+        let _ = diagnostic::JSON_MESSAGES.set(args.grammar.clone());
+    }
+    
+    if !args.prefix.is_empty() && !args.prefix.ends_with("_") {
+        args.prefix.push('_');
+    }
+    
+    let backend = match backend::find_backend(&args.backend) {
+        Some(backend) => backend,
+        None => {
+            let names: Vec<&str> = backend::BACKENDS.iter().map(|backend| backend.name()).collect();
+            let _ = error(&format!("Unknown backend '{}'. Available backends: {}", args.backend, names.join(", ")));
+            std::process::exit(1);
+        },
+    };
+    
+    let start = std::time::Instant::now();
+    let (view, grammar_len) = read_grammar(&args.grammar, &args.define);
+    log_stage(&format!("Reading {}", args.grammar), start);
+    
+    let (mut grammar, mut unused_constants) = parse_grammar(&view, &args.grammar, &args);
+    
+    // Constants from the command line are not part of the file
+    let unused_defines: Vec<frontend::SourceRange> = unused_constants.iter().filter(|name| name.start >= grammar_len).cloned().collect();
+    unused_constants.retain(|name| name.start < grammar_len);
+    
+    for name in &unused_defines {
+        let _ = warning(&format!("The constant '{}' of --define is never used", view.range(name)));
+    }
+    
+    let start = std::time::Instant::now();
     verify_grammar(&view, &grammar, &args);
+    let warnings = lint_grammar(&view, &grammar, &unused_constants) + unused_defines.len();
+    log_stage("Checking", start);
+    log::debug!("{} warnings", warnings);
+    
+    if args.deny_warnings && warnings > 0 {
+        let _ = error(&format!("Stopping because of {} warnings and --deny-warnings", warnings));
+        std::process::exit(1);
+    }
+    
+    transform_grammar(&mut grammar, &args);
     
     let mut did_action = false;
     
-    if args.outfile.is_some() {
-        backend::C::compile_grammar(&args, &grammar, &view);
+    if let Some(outfile) = &args.outfile {
+        if let Err(msg) = backend.check(&args, &grammar, &view).and_then(|_| prepare_outputs(&args, backend, outfile)) {
+            let _ = error(&msg);
+            std::process::exit(1);
+        }
+        
+        let start = std::time::Instant::now();
+        backend.render(&args, &grammar, &view);
+        log_stage(&format!("Rendering {}", outfile), start);
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.report {
+        let mut file = std::fs::File::create(path).expect("Could not create report file");
+        report::write_report(&mut file, &view, &grammar, &args.grammar).expect("Could not write report");
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.emit_grammar {
+        let mut file = std::fs::File::create(path).expect("Could not create grammar file");
+        backend::chm::write_grammar(&mut file, &view, &grammar, &args.grammar).expect("Could not write grammar");
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.format {
+        let formatted = frontend::format::format_grammar(view.slice(0, grammar_len));
+        std::fs::write(path, formatted).expect("Could not write formatted grammar");
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.diff {
+        // Compare the files as they are, without the constants of --define
+        let (old_view, _) = read_grammar(path, &[]);
+        let (mut old_grammar, _) = parse_grammar(&old_view, path, &args);
+        transform_grammar(&mut old_grammar, &args);
+        
+        let (new_view, _) = read_grammar(&args.grammar, &[]);
+        let (mut new_grammar, _) = parse_grammar(&new_view, &args.grammar, &args);
+        transform_grammar(&mut new_grammar, &args);
+        
+        let changes = diff::write_diff(&mut std::io::stdout(), (&old_view, &old_grammar), (&new_view, &new_grammar)).expect("Could not write diff");
+        println!("{} structs changed between {} and {}", changes, path, args.grammar);
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.dict {
+        let mut file = std::fs::File::create(path).expect("Could not create dictionary file");
+        backend::dict::write_dictionary(&mut file, &grammar, &args.grammar).expect("Could not write dictionary");
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.dot {
+        let mut file = std::fs::File::create(path).expect("Could not create graph file");
+        frontend::graph::GrammarGraph::full_graph(&grammar).write_dot(&mut file, &view).expect("Could not write graph");
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.print_profile {
+        print_profile(&view, &grammar, path);
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.print_trace {
+        print_trace(&view, &grammar, path);
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.print_trace_stream {
+        print_trace_stream(&view, &grammar, path);
+        did_action = true;
+    }
+    
+    if let Some(depth) = args.print_derivations {
+        print_derivations(&view, &grammar, depth);
+        did_action = true;
+    }
+    
+    if let Some(dir) = &args.samples {
+        write_samples(&grammar, dir, args.num_samples);
+        did_action = true;
+    }
+    
+    if let Some(dir) = &args.seeds {
+        write_seeds(&grammar, dir);
+        did_action = true;
+    }
+    
+    if let Some(max_len) = args.check_size {
+        if !check_size(&view, &grammar, max_len) {
+            std::process::exit(1);
+        }
+        did_action = true;
+    }
+    
+    if let Some(path) = &args.shared {
+        let outfile = match args.outfile.as_deref() {
+            Some("-") => {
+                let _ = error("--shared needs the generator in a file. Pass the name of a .c file to -o or leave -o out");
+                std::process::exit(1);
+            },
+            Some(outfile) => outfile.to_string(),
+            None => {
+                if let Err(msg) = backend.check(&args, &grammar, &view) {
+                    let _ = error(&msg);
+                    std::process::exit(1);
+                }
+                
+                let outfile = std::env::temp_dir().join(format!("chm-shared-{}.c", std::process::id())).to_str().unwrap().to_string();
+                let mut tmp_args = args.clone();
+                tmp_args.outfile = Some(outfile.clone());
+                backend.render(&tmp_args, &grammar, &view);
+                outfile
+            },
+        };
+        
+        let result = compile_shared(&outfile, path);
+        
+        if args.outfile.is_none() {
+            for output in backend.outputs(&outfile) {
+                let _ = std::fs::remove_file(output);
+            }
+        }
+        
+        if let Err(msg) = result {
+            let _ = error(&msg);
+            std::process::exit(1);
+        }
+        
         did_action = true;
     }
     
@@ -0,0 +1,331 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::ops::Range;
+use crate::{
+    frontend::{
+        SourceView,
+        graph::GrammarGraph,
+        stats::GrammarStats,
+    },
+    grammar::{
+        Grammar, Container, ContainerId, ContainerType,
+        Variable, VariableType, IntegerValue, BytearrayValue,
+        NumbersetId, NumbersetType, StringId,
+    },
+};
+
+/// Strings longer than this get truncated in the report
+const MAX_STRING_LEN: usize = 64;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+code { background: #f3f3f3; padding: 0 0.2em; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+td.num { text-align: right; }
+details { border-left: 3px solid #ccc; margin: 0.5em 0; padding-left: 0.8em; }
+summary { cursor: pointer; font-weight: bold; }
+.dominant { color: #b00; }
+.warning { color: #a60; }
+";
+
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            _ => ret.push(c),
+        }
+    }
+    
+    ret
+}
+
+fn anchor(id: ContainerId) -> String {
+    format!("container-{}", id)
+}
+
+fn container_title(view: &SourceView, container: &Container) -> String {
+    match container.name() {
+        Some(name) if !name.is_empty() => escape(view.range(name)),
+        Some(name) => {
+            let (line, col) = view.lineinfo(name.start);
+            format!("anonymous struct in line {} column {}", line, col)
+        },
+        None => format!("oneof #{}", container.id()),
+    }
+}
+
+fn container_link(view: &SourceView, grammar: &Grammar, id: ContainerId) -> String {
+    let container = grammar.container(id).unwrap();
+    format!("<a href=\"#{}\">{}</a>", anchor(id), container_title(view, container))
+}
+
+fn format_ranges<T: Display + PartialEq>(ranges: &[Range<T>]) -> String {
+    let mut ret = Vec::new();
+    
+    for range in ranges {
+        if range.start == range.end {
+            ret.push(format!("{}", range.start));
+        } else {
+            ret.push(format!("{}..{}", range.start, range.end));
+        }
+    }
+    
+    ret.join(", ")
+}
+
+fn format_numberset(grammar: &Grammar, id: NumbersetId) -> String {
+    match grammar.numbersets().get(&id).unwrap() {
+        NumbersetType::U8(v) => format_ranges(v),
+        NumbersetType::I8(v) => format_ranges(v),
+        NumbersetType::U16(v) => format_ranges(v),
+        NumbersetType::I16(v) => format_ranges(v),
+        NumbersetType::U32(v) => format_ranges(v),
+        NumbersetType::I32(v) => format_ranges(v),
+        NumbersetType::U64(v) => format_ranges(v),
+        NumbersetType::I64(v) => format_ranges(v),
+    }
+}
+
+fn format_string(grammar: &Grammar, id: StringId) -> String {
+    let bytes = grammar.strings().get(&id).unwrap();
+    let mut ret = String::new();
+    
+    for b in bytes.iter().take(MAX_STRING_LEN) {
+        match *b {
+            b'"' => ret.push_str("\\\""),
+            b'\\' => ret.push_str("\\\\"),
+            0x20..=0x7e => ret.push(*b as char),
+            _ => ret.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    
+    if bytes.len() > MAX_STRING_LEN {
+        ret.push_str("...");
+    }
+    
+    format!("\"{}\"", escape(&ret))
+}
+
+fn describe_variable(view: &SourceView, grammar: &Grammar, var: &Variable) -> String {
+    let integer = |name: &str, value: &IntegerValue| match value {
+        IntegerValue::FromSet(id) => format!("<code>{}</code> = {}", name, format_numberset(grammar, *id)),
+        IntegerValue::Any => format!("<code>{}</code>", name),
+    };
+    let bytearray = |name: &str, value: &BytearrayValue| match value {
+        BytearrayValue::Literal(id) => format!("<code>{}</code> = {}", name, format_string(grammar, *id)),
+        BytearrayValue::Any(id) => format!("<code>{}</code> of length {}", name, format_numberset(grammar, *id)),
+    };
+    
+    let mut ret = match var.typ() {
+        VariableType::U8(value) => integer("u8", value),
+        VariableType::I8(value) => integer("i8", value),
+        VariableType::U16(value) => integer("u16", value),
+        VariableType::I16(value) => integer("i16", value),
+        VariableType::U32(value) => integer("u32", value),
+        VariableType::I32(value) => integer("i32", value),
+        VariableType::U64(value) => integer("u64", value),
+        VariableType::I64(value) => integer("i64", value),
+        VariableType::String(value) => bytearray("string", value),
+        VariableType::Bytes(value) => bytearray("bytes", value),
+        VariableType::Oneof(id) |
+        VariableType::ContainerRef(id) => container_link(view, grammar, *id),
+        VariableType::Epsilon => "<code>eps</code>".to_string(),
+        VariableType::ExternRef(name) => format!("external <code>{}</code>", escape(name)),
+        VariableType::ResolveContainerRef(_) => panic!("Encountered container ref after parsing stage"),
+    };
+    
+    if var.options().optional() {
+        ret.push_str(" (optional)");
+    }
+    
+    if let Some(id) = var.options().repeats() {
+        ret.push_str(&format!(" (repeats {}", format_numberset(grammar, *id)));
+        
+        if let Some(sep) = var.options().separator() {
+            ret.push_str(&format!(" separated by {}", format_string(grammar, *sep)));
+        }
+        
+        ret.push(')');
+    }
+    
+    ret
+}
+
+/// All containers that reference the container `id`
+fn callers(grammar: &Grammar, id: ContainerId) -> Vec<ContainerId> {
+    let mut ret = Vec::new();
+    
+    for container in grammar.containers() {
+        for var in container.variables() {
+            match var.typ() {
+                VariableType::Oneof(target) |
+                VariableType::ContainerRef(target) if *target == id => {
+                    ret.push(container.id());
+                    break;
+                },
+                _ => {},
+            }
+        }
+    }
+    
+    ret
+}
+
+fn write_stats(out: &mut dyn Write, view: &SourceView, grammar: &Grammar) -> std::io::Result<()> {
+    writeln!(out, "<h2>Statistics</h2>")?;
+    
+    let graph = GrammarGraph::full_graph(grammar);
+    
+    if graph.cycle().is_some() {
+        writeln!(out, "<p class=\"warning\">The grammar contains cycles so no statistics can be computed.</p>")?;
+        writeln!(out, "<h3>Recursive structs</h3>")?;
+        writeln!(out, "<ul>")?;
+        
+        for group in graph.recursive_groups() {
+            let links: Vec<String> = group.iter().map(|id| container_link(view, grammar, *id)).collect();
+            writeln!(out, "<li>{}</li>", links.join(", "))?;
+        }
+        
+        return writeln!(out, "</ul>");
+    }
+    
+    let stats = GrammarStats::from_grammar(grammar);
+    let or_unknown = |value: Option<usize>, fallback: &str| match value {
+        Some(value) => value.to_string(),
+        None => fallback.to_string(),
+    };
+    let expected = stats.expected_input_size();
+    
+    writeln!(out, "<table>")?;
+    writeln!(out, "<tr><th>Number of paths</th><td class=\"num\">{}</td></tr>", or_unknown(stats.num_paths(), "too many"))?;
+    writeln!(out, "<tr><th>Largest input</th><td class=\"num\">{} bytes</td></tr>", or_unknown(stats.max_input_size(), "too big"))?;
+    writeln!(out, "<tr><th>Smallest input</th><td class=\"num\">{} bytes</td></tr>", or_unknown(stats.min_input_size(), "too big"))?;
+    writeln!(out, "<tr><th>Expected input</th><td class=\"num\">{:.1} bytes (std. dev. {:.1})</td></tr>", expected.mean, expected.std_dev())?;
+    writeln!(out, "<tr><th>Distinct inputs</th><td class=\"num\">2<sup>{:.1}</sup></td></tr>", stats.input_choices())?;
+    writeln!(out, "</table>")?;
+    
+    if stats.shares().is_empty() {
+        return Ok(());
+    }
+    
+    writeln!(out, "<h3>Distinct outputs per struct</h3>")?;
+    writeln!(out, "<table>")?;
+    writeln!(out, "<tr><th>Struct</th><th>Outputs</th></tr>")?;
+    
+    for choices in stats.choices() {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"num\">2<sup>{:.1}</sup></td></tr>",
+            container_link(view, grammar, choices.id),
+            choices.bits,
+        )?;
+    }
+    
+    writeln!(out, "</table>")?;
+    
+    writeln!(out, "<h3>Share of expected input per struct</h3>")?;
+    writeln!(out, "<table>")?;
+    writeln!(out, "<tr><th>Struct</th><th>Share</th><th>Calls</th><th>Bytes per call</th></tr>")?;
+    
+    for share in stats.shares() {
+        writeln!(
+            out,
+            "<tr{}><td>{}</td><td class=\"num\">{:.1}%</td><td class=\"num\">{:.1}</td><td class=\"num\">{:.1}</td></tr>",
+            if share.is_dominant() { " class=\"dominant\"" } else { "" },
+            container_link(view, grammar, share.id),
+            share.share * 100.0,
+            share.calls,
+            share.size.mean,
+        )?;
+    }
+    
+    writeln!(out, "</table>")
+}
+
+fn write_container(out: &mut dyn Write, view: &SourceView, grammar: &Grammar, container: &Container) -> std::io::Result<()> {
+    let is_root = grammar.entrypoint() == Some(&container.id());
+    
+    writeln!(out, "<details id=\"{}\"{}>", anchor(container.id()), if is_root { " open" } else { "" })?;
+    writeln!(
+        out,
+        "<summary>{}{}</summary>",
+        container_title(view, container),
+        if is_root { " (entrypoint)" } else { "" },
+    )?;
+    
+    let kind = match container.typ() {
+        ContainerType::Struct => "Variables in order",
+        ContainerType::Oneof => "Alternatives",
+    };
+    writeln!(out, "<p>{}:</p>", kind)?;
+    writeln!(out, "<ol>")?;
+    
+    for var in container.variables() {
+        writeln!(out, "<li>{}</li>", describe_variable(view, grammar, var))?;
+    }
+    
+    writeln!(out, "</ol>")?;
+    
+    let callers = callers(grammar, container.id());
+    
+    if !callers.is_empty() {
+        let links: Vec<String> = callers.iter().map(|id| container_link(view, grammar, *id)).collect();
+        writeln!(out, "<p>Used by: {}</p>", links.join(", "))?;
+    }
+    
+    writeln!(out, "</details>")
+}
+
+/// Write a self-contained HTML page describing the grammar
+pub fn write_report(out: &mut dyn Write, view: &SourceView, grammar: &Grammar, title: &str) -> std::io::Result<()> {
+    let num_structs = grammar.containers().filter(|c| c.typ() == ContainerType::Struct).count();
+    let num_oneofs = grammar.containers().filter(|c| c.typ() == ContainerType::Oneof).count();
+    
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>Chameleon report: {}</title>", escape(title))?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>Grammar <code>{}</code></h1>", escape(title))?;
+    
+    writeln!(out, "<h2>Overview</h2>")?;
+    writeln!(out, "<table>")?;
+    writeln!(out, "<tr><th>Structs</th><td class=\"num\">{}</td></tr>", num_structs)?;
+    writeln!(out, "<tr><th>Oneofs</th><td class=\"num\">{}</td></tr>", num_oneofs)?;
+    writeln!(out, "<tr><th>Numbersets</th><td class=\"num\">{}</td></tr>", grammar.numbersets().len())?;
+    writeln!(out, "<tr><th>Strings</th><td class=\"num\">{}</td></tr>", grammar.strings().len())?;
+    writeln!(out, "</table>")?;
+    
+    let externs = grammar.extern_names();
+    
+    if !externs.is_empty() {
+        let names: Vec<String> = externs.iter().map(|name| format!("<code>{}</code>", escape(name))).collect();
+        writeln!(out, "<p>External functions: {}</p>", names.join(", "))?;
+    }
+    
+    write_stats(out, view, grammar)?;
+    
+    writeln!(out, "<h2>Structure</h2>")?;
+    
+    // Entrypoint first, then everything else in order of definition
+    let root = *grammar.entrypoint().unwrap();
+    write_container(out, view, grammar, grammar.container(root).unwrap())?;
+    
+    for container in grammar.containers() {
+        if container.id() != root {
+            write_container(out, view, grammar, container)?;
+        }
+    }
+    
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
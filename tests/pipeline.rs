@@ -0,0 +1,976 @@
+//! End-to-end tests that translate the grammars in `tests/fixtures`, compile the
+//! generators with the system C compiler and check the generated inputs.
+//! A C compiler must be available as `cc`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tests run in parallel so every generator gets its own directory
+static BUILD_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// C program that prints the inputs of a generator as hex strings, one per line
+const DRIVER: &str = r#"
+#include <stdio.h>
+#include <stdlib.h>
+#include "generator.c.h"
+
+int main (int argc, char** argv) {
+    unsigned char buf[4096];
+    
+    if (argc != 3) {
+        return 1;
+    }
+    
+    size_t count = strtoul(argv[2], NULL, 0);
+    
+    if (argv[1][0] == 'm') {
+        count = 1;
+    } else {
+        seed(strtoul(argv[1], NULL, 0));
+    }
+    
+    for (size_t i = 0; i < count; ++i) {
+        size_t len = (argv[1][0] == 'm') ? min_input(buf, sizeof(buf)) : generate(buf, sizeof(buf));
+        
+        for (size_t j = 0; j < len; ++j) {
+            printf("%02x", buf[j]);
+        }
+        printf("\n");
+    }
+    
+    return 0;
+}
+"#;
+
+fn fixture_path(fixture: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(fixture)
+}
+
+/// A new empty directory for the files of a single test
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chameleon-test-{}-{}", std::process::id(), BUILD_ID.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+struct Generator {
+    dir: PathBuf,
+    binary: PathBuf,
+}
+impl Generator {
+    fn build(fixture: &str) -> Self {
+        Self::build_from(&fixture_path(fixture))
+    }
+    
+    fn build_from(grammar: &Path) -> Self {
+        Self::build_with(grammar, &[], DRIVER)
+    }
+    
+    /// Translate a grammar with additional command line arguments and link it with a custom driver
+    fn build_with(grammar: &Path, args: &[&str], driver: &str) -> Self {
+        let dir = temp_dir();
+        let fixture = grammar.display();
+        
+        let source = dir.join("generator.c");
+        let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+            .args(args)
+            .arg("-o")
+            .arg(&source)
+            .arg(grammar)
+            .status()
+            .unwrap();
+        assert!(status.success(), "translating {} failed", fixture);
+        
+        let driver_source = dir.join("driver.c");
+        std::fs::write(&driver_source, driver).unwrap();
+        
+        let binary = dir.join("generator");
+        let status = Command::new("cc")
+            .arg("-o")
+            .arg(&binary)
+            .arg("-Wall")
+            .arg("-Wextra")
+            .arg("-Werror")
+            .arg("-Wno-unused-function")
+            .arg(&driver_source)
+            .arg(&source)
+            .arg(format!("-I{}", dir.display()))
+            .status()
+            .expect("the end-to-end tests need a C compiler called cc");
+        assert!(status.success(), "compiling {} failed", fixture);
+        
+        Self {
+            dir,
+            binary,
+        }
+    }
+    
+    /// Run the driver and return what it printed
+    fn output(&self, args: &[&str]) -> String {
+        let output = Command::new(&self.binary).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+    
+    fn run(&self, mode: &str, count: usize) -> Vec<Vec<u8>> {
+        self.output(&[mode, &count.to_string()])
+            .lines()
+            .map(|line| (0..line.len()).step_by(2).map(|i| u8::from_str_radix(&line[i..i + 2], 16).unwrap()).collect())
+            .collect()
+    }
+    
+    fn generate(&self, seed: usize, count: usize) -> Vec<Vec<u8>> {
+        self.run(&seed.to_string(), count)
+    }
+    
+    fn min_input(&self) -> Vec<u8> {
+        self.run("min", 1).remove(0)
+    }
+}
+impl Drop for Generator {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn list_inputs() {
+    let generator = Generator::build("list.chm");
+    
+    for input in generator.generate(1, 500) {
+        assert!(input.len() >= 3 && input.len() <= 11);
+        assert_eq!(input[0], b'[');
+        assert_eq!(input[input.len() - 1], b']');
+        
+        for (i, c) in input[1..input.len() - 1].iter().enumerate() {
+            if i % 2 == 0 {
+                assert!(c.is_ascii_digit());
+            } else {
+                assert_eq!(*c, b',');
+            }
+        }
+    }
+    
+    assert_eq!(generator.min_input(), b"[0]");
+}
+
+#[test]
+fn header_inputs() {
+    let generator = Generator::build("header.chm");
+    
+    for input in generator.generate(2, 500) {
+        assert!(input.len() >= 7 && input.len() <= 17);
+        assert_eq!(&input[..4], b"\x7fELF");
+        
+        let length = u16::from_be_bytes([input[4], input[5]]);
+        assert!((1..=16).contains(&length));
+    }
+    
+    assert_eq!(generator.min_input(), b"\x7fELF\x00\x01A");
+}
+
+#[test]
+fn deterministic_per_seed() {
+    let generator = Generator::build("list.chm");
+    
+    assert_eq!(generator.generate(1234, 100), generator.generate(1234, 100));
+    assert_ne!(generator.generate(1234, 100), generator.generate(4321, 100));
+}
+
+#[test]
+fn recursion_limit() {
+    let generator = Generator::build("recursion.chm");
+    let mut deepest = 0;
+    
+    for input in generator.generate(3, 500) {
+        let mut depth = 0;
+        
+        for c in input {
+            if c == b'(' {
+                depth += 1;
+                deepest = deepest.max(depth);
+            } else {
+                assert_eq!(c, b')');
+                depth -= 1;
+            }
+        }
+        
+        assert_eq!(depth, 0);
+    }
+    
+    assert_eq!(deepest, 3);
+    assert_eq!(generator.min_input(), b"()");
+}
+
+#[test]
+fn signed_inputs() {
+    let generator = Generator::build("signed.chm");
+    
+    for input in generator.generate(4, 1000) {
+        assert_eq!(input.len(), 17);
+        
+        let around_zero = input[0] as i8;
+        let upper_half = input[1] as i8;
+        let both_ends = input[2] as i8;
+        let hex = i16::from_be_bytes([input[3], input[4]]);
+        let wide = i32::from_be_bytes(input[5..9].try_into().unwrap());
+        let large = i64::from_be_bytes(input[9..17].try_into().unwrap());
+        
+        assert!((-1..=1).contains(&around_zero));
+        assert!(upper_half >= 0);
+        assert!(both_ends <= -100 || both_ends >= 100);
+        assert!((-16..=16).contains(&hex));
+        assert_ne!(wide, 0);
+        assert!((-5..=5).contains(&large));
+    }
+    
+    assert_eq!(generator.min_input(), b"\xff\x00\x80\xff\xf0\x80\x00\x00\x00\xff\xff\xff\xff\xff\xff\xff\xfb");
+}
+
+/// Parses an expression of expr.chm and returns the rest of the input
+fn parse_expr(input: &[u8]) -> &[u8] {
+    let rest = match input.first() {
+        Some(b'(') => {
+            let rest = parse_expr(&input[1..]);
+            assert_eq!(rest[0], b')');
+            &rest[1..]
+        },
+        Some(c) => {
+            assert!(c.is_ascii_digit());
+            &input[1..]
+        },
+        None => panic!("Unexpected end of expression"),
+    };
+    
+    match rest.first() {
+        Some(b'+') => parse_expr(&rest[1..]),
+        _ => rest,
+    }
+}
+
+#[test]
+fn recursive_oneof() {
+    let generator = Generator::build("expr.chm");
+    
+    for input in generator.generate(5, 1000) {
+        assert!(parse_expr(&input).is_empty());
+    }
+    
+    assert_eq!(generator.min_input(), b"0");
+}
+
+#[test]
+fn emitted_grammar_round_trips() {
+    for fixture in ["header.chm", "list.chm", "recursion.chm", "signed.chm", "expr.chm"] {
+        let dir = temp_dir();
+        let emitted = dir.join("emitted.chm");
+        let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+            .arg("--emit-grammar")
+            .arg(&emitted)
+            .arg(fixture_path(fixture))
+            .status()
+            .unwrap();
+        assert!(status.success(), "emitting {} failed", fixture);
+        
+        let original = Generator::build(fixture);
+        let normalized = Generator::build_from(&emitted);
+        let _ = std::fs::remove_dir_all(&dir);
+        
+        assert_eq!(original.generate(6, 200), normalized.generate(6, 200), "{} changed after emitting it", fixture);
+        assert_eq!(original.min_input(), normalized.min_input());
+    }
+}
+
+#[test]
+fn invalid_prefix_is_rejected() {
+    for prefix in ["my-gen", "1gen", "_gen"] {
+        let dir = temp_dir();
+        let source = dir.join("generator.c");
+        
+        let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+            .arg("--prefix")
+            .arg(prefix)
+            .arg("-o")
+            .arg(&source)
+            .arg(fixture_path("header.chm"))
+            .status()
+            .unwrap();
+        let written = source.exists();
+        let _ = std::fs::remove_dir_all(&dir);
+        
+        assert!(!status.success(), "prefix '{}' was accepted", prefix);
+        assert!(!written, "generator was written despite prefix '{}'", prefix);
+    }
+}
+
+#[test]
+fn seeds_cover_every_variable() {
+    let dir = temp_dir();
+    let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--seeds")
+        .arg(&dir)
+        .arg(fixture_path("expr.chm"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    
+    let mut seeds: Vec<Vec<u8>> = std::fs::read_dir(&dir).unwrap().map(|entry| std::fs::read(entry.unwrap().path()).unwrap()).collect();
+    let _ = std::fs::remove_dir_all(&dir);
+    seeds.sort();
+    
+    for seed in &seeds {
+        assert!(parse_expr(seed).is_empty());
+    }
+    
+    assert!(seeds.iter().any(|seed| seed.contains(&b'(')));
+    assert!(seeds.iter().any(|seed| seed.contains(&b'+')));
+    assert!(seeds.iter().any(|seed| seed == b"0"));
+}
+
+#[test]
+fn interpreter_samples() {
+    let dir = temp_dir();
+    let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--samples")
+        .arg(&dir)
+        .arg("--num-samples")
+        .arg("50")
+        .arg(fixture_path("expr.chm"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    
+    let samples: Vec<Vec<u8>> = std::fs::read_dir(&dir).unwrap().map(|entry| std::fs::read(entry.unwrap().path()).unwrap()).collect();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert_eq!(samples.len(), 50);
+    
+    for sample in &samples {
+        assert!(parse_expr(sample).is_empty());
+    }
+}
+
+#[test]
+fn shared_object_is_built() {
+    let dir = temp_dir();
+    let library = dir.join("generator.so");
+    
+    let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--shared")
+        .arg(&library)
+        .arg(fixture_path("expr.chm"))
+        .status()
+        .unwrap();
+    let written = library.exists();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(status.success());
+    assert!(written);
+}
+
+#[test]
+fn formatting_restores_indentation() {
+    for fixture in ["header.chm", "list.chm", "recursion.chm", "signed.chm", "expr.chm"] {
+        let original: String = std::fs::read_to_string(fixture_path(fixture)).unwrap().lines().map(|line| format!("{}\n", line.trim_end())).collect();
+        let flattened: String = original.lines().map(|line| format!("{}  \n", line.trim_start())).collect();
+        
+        let dir = temp_dir();
+        let input = dir.join("flat.chm");
+        let output = dir.join("formatted.chm");
+        std::fs::write(&input, flattened).unwrap();
+        
+        let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+            .arg("--format")
+            .arg(&output)
+            .arg(&input)
+            .status()
+            .unwrap();
+        let formatted = std::fs::read_to_string(&output).ok();
+        let _ = std::fs::remove_dir_all(&dir);
+        
+        assert!(status.success(), "formatting {} failed", fixture);
+        assert_eq!(formatted.as_deref(), Some(original.as_str()), "{} changed after formatting it", fixture);
+    }
+}
+
+#[test]
+fn json_diagnostics() {
+    let dir = temp_dir();
+    let grammar = dir.join("broken.chm");
+    std::fs::write(&grammar, "struct Root {\n    a: u8;\n    b: Missing;\n}\n").unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--message-format")
+        .arg("json")
+        .arg("--check-size")
+        .arg("16")
+        .arg(&grammar)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stderr);
+    assert!(lines[0].contains("\"line\":3,\"column\":8,\"length\":7"), "{}", lines[0]);
+    assert!(lines[0].contains("\"severity\":\"error\",\"code\":\"UnresolvedRef\""), "{}", lines[0]);
+}
+
+#[test]
+fn multiple_entrypoints() {
+    let dir = temp_dir();
+    let grammar = dir.join("messages.chm");
+    let source = dir.join("generator.c");
+    std::fs::write(&grammar, "struct Request {\n    _: string = \"GET\";\n}\n\nstruct Response {\n    _: string = \"200\";\n}\n").unwrap();
+    
+    let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--entrypoint")
+        .arg("Request,Response")
+        .arg("-o")
+        .arg(&source)
+        .arg(&grammar)
+        .status()
+        .unwrap();
+    let header = std::fs::read_to_string(dir.join("generator.c.h")).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(status.success());
+    assert!(header.contains("size_t generate_Request(unsigned char* buf, size_t len);"));
+    assert!(header.contains("size_t generate_Response(unsigned char* buf, size_t len);"));
+}
+
+#[test]
+fn constants_from_the_command_line() {
+    let dir = temp_dir();
+    let grammar = dir.join("constants.chm");
+    let samples = dir.join("samples");
+    std::fs::write(&grammar, "define HOST \"example.com\";\n\nstruct Root {\n    _: string = \"${HOST}:${PORT}\";\n}\n").unwrap();
+    
+    let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--define")
+        .arg("HOST=fuzz.test")
+        .arg("--define")
+        .arg("PORT=8080")
+        .arg("--samples")
+        .arg(&samples)
+        .arg("--num-samples")
+        .arg("1")
+        .arg(&grammar)
+        .status()
+        .unwrap();
+    let inputs: Vec<Vec<u8>> = std::fs::read_dir(&samples).map(|entries| entries.map(|entry| std::fs::read(entry.unwrap().path()).unwrap()).collect()).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(status.success());
+    assert_eq!(inputs, vec![b"fuzz.test:8080".to_vec()]);
+}
+
+#[test]
+fn formatting_ignores_defines() {
+    let dir = temp_dir();
+    let grammar = dir.join("constants.chm");
+    let output = dir.join("formatted.chm");
+    std::fs::write(&grammar, "define X 5;\n\nstruct Root {\n_: u8 = $X;\n}\n").unwrap();
+    
+    let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--define")
+        .arg("X=7")
+        .arg("--format")
+        .arg(&output)
+        .arg(&grammar)
+        .status()
+        .unwrap();
+    let formatted = std::fs::read_to_string(&output).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(status.success());
+    assert_eq!(formatted, "define X 5;\n\nstruct Root {\n    _: u8 = $X;\n}\n");
+}
+
+/// Driver with an external function that claims to write more than `len` bytes if asked to
+const EXTERN_DRIVER: &str = r#"
+#include <stdio.h>
+#include <string.h>
+#include "generator.c.h"
+
+static int lying = 0;
+
+size_t extern_Payload(unsigned char* buf, size_t len) {
+    size_t written = (len < 2) ? len : 2;
+    memset(buf, 'x', written);
+    return lying ? len + 1000 : written;
+}
+
+int main (int argc, char** argv) {
+    unsigned char buf[64];
+    (void) argv;
+    lying = argc > 1;
+    size_t len = generate(buf, lying ? 3 : sizeof(buf));
+    printf("%zu %.*s\n", len, (int) len, buf);
+    return 0;
+}
+"#;
+
+#[test]
+fn external_functions() {
+    let dir = temp_dir();
+    let grammar = dir.join("extern.chm");
+    std::fs::write(&grammar, "struct Root {\n    head: string = \"<\";\n    payload: Payload;\n    tail: string = \">\";\n}\n").unwrap();
+    
+    let generator = Generator::build_with(&grammar, &["--allow-undefined", "Payload"], EXTERN_DRIVER);
+    let header = std::fs::read_to_string(generator.dir.join("generator.c.h")).unwrap();
+    let honest = generator.output(&[]);
+    let lying = generator.output(&["lying"]);
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(header.contains("size_t extern_Payload(unsigned char* buf, size_t len);"));
+    assert_eq!(honest, "4 <xx>\n");
+    
+    // The generator must not write past the 3 bytes it was given
+    let len: usize = lying.split_whitespace().next().unwrap().parse().unwrap();
+    assert!(len <= 3, "{}", lying);
+}
+
+/// Driver that counts how often the first variable of a oneof is picked before and after rewarding it
+const REWARD_DRIVER: &str = r#"
+#include <stdio.h>
+#include "generator.c.h"
+
+int main (void) {
+    unsigned char buf[16];
+    size_t before = 0;
+    size_t after = 0;
+    
+    seed(1);
+    
+    for (int i = 0; i < 1000; ++i) {
+        generate(buf, sizeof(buf));
+        before += buf[0] == 'a';
+    }
+    
+    for (int i = 0; i < 1000; ++i) {
+        generate(buf, sizeof(buf));
+        reward(buf[0] == 'a' ? 1.0 : 0.0);
+    }
+    
+    for (int i = 0; i < 1000; ++i) {
+        generate(buf, sizeof(buf));
+        after += buf[0] == 'a';
+    }
+    
+    printf("%zu %zu\n", before, after);
+    return 0;
+}
+"#;
+
+#[test]
+fn rewards_raise_weights() {
+    let dir = temp_dir();
+    let grammar = dir.join("adaptive.chm");
+    std::fs::write(&grammar, "struct Root {\n    option scheduling = adaptive;\n    \n    v: oneof {\n        a: string = \"a\";\n        b: string = \"b\";\n    };\n}\n").unwrap();
+    
+    let generator = Generator::build_with(&grammar, &[], REWARD_DRIVER);
+    let output = generator.output(&[]);
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    let counts: Vec<usize> = output.split_whitespace().map(|count| count.parse().unwrap()).collect();
+    assert!((350..=650).contains(&counts[0]), "{}", output);
+    assert!(counts[1] > 800, "{}", output);
+}
+
+#[test]
+fn report_lists_every_struct() {
+    for fixture in ["header.chm", "list.chm", "recursion.chm", "signed.chm", "expr.chm"] {
+        let dir = temp_dir();
+        let report = dir.join("report.html");
+        
+        let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+            .arg("--report")
+            .arg(&report)
+            .arg(fixture_path(fixture))
+            .status()
+            .unwrap();
+        let html = std::fs::read_to_string(&report).unwrap_or_default();
+        let _ = std::fs::remove_dir_all(&dir);
+        
+        assert!(status.success(), "writing the report of {} failed", fixture);
+        assert!(html.starts_with("<!DOCTYPE html>") && html.trim_end().ends_with("</html>"), "report of {} is incomplete", fixture);
+        
+        for line in std::fs::read_to_string(fixture_path(fixture)).unwrap().lines() {
+            if let Some(name) = line.strip_prefix("struct ").and_then(|rest| rest.split_whitespace().next()) {
+                assert!(html.contains(&format!("<summary>{}", name)), "report of {} has no section for {}", fixture, name);
+            }
+        }
+    }
+}
+
+#[test]
+fn diff_lists_added_and_removed_structs() {
+    let dir = temp_dir();
+    let old = dir.join("old.chm");
+    let new = dir.join("new.chm");
+    std::fs::write(&old, "struct Old {\n    x: string = \"old\";\n}\n\nstruct Root {\n    a: Old;\n    b: u8;\n}\n").unwrap();
+    std::fs::write(&new, "struct New {\n    x: string = \"new\";\n}\n\nstruct Root {\n    a: New;\n    b: u8;\n}\n").unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--diff")
+        .arg(&old)
+        .arg(&new)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[..12], [
+        "removed Old",
+        "  - struct Old {",
+        "  -     _: string = \"old\";",
+        "  - }",
+        "added New",
+        "  + struct New {",
+        "  +     _: string = \"new\";",
+        "  + }",
+        "changed Root",
+        "    struct Root {",
+        "  -     _: Old;",
+        "  +     _: New;",
+    ]);
+    assert!(lines[12..].contains(&"        _: u8;"));
+    assert!(lines.last().unwrap().starts_with("3 structs changed"));
+}
+
+#[test]
+fn dictionary_entries() {
+    let dir = temp_dir();
+    let grammar = dir.join("tokens.chm");
+    let dict = dir.join("tokens.dict");
+    std::fs::write(&grammar, "struct Root {\n    repeats 1..3 separated \", \" word: string = \"a\\\"b\";\n    port: u16:le = 80, 8000..8080;\n    flag: u8 = 1..200;\n}\n").unwrap();
+    
+    let status = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--dict")
+        .arg(&dict)
+        .arg(&grammar)
+        .status()
+        .unwrap();
+    let content = std::fs::read_to_string(&dict).unwrap_or_default();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(status.success());
+    
+    // Single bytes like the bounds of flag are left out
+    let entries: Vec<&str> = content.lines().skip(1).collect();
+    assert_eq!(entries, [
+        "\", \"",
+        "\"@\\x1F\"",
+        "\"P\\x00\"",
+        "\"a\\x22b\"",
+        "\"\\x90\\x1F\"",
+    ]);
+}
+
+/// Check a grammar with JSON diagnostics and return whether that succeeded
+/// together with the codes of all diagnostics
+fn diagnostic_codes(source: &str, args: &[&str]) -> (bool, Vec<String>) {
+    let dir = temp_dir();
+    let grammar = dir.join("grammar.chm");
+    std::fs::write(&grammar, source).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--message-format")
+        .arg("json")
+        .args(args)
+        .arg(&grammar)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    let codes = String::from_utf8(output.stderr).unwrap()
+        .lines()
+        .map(|line| line.split("\"code\":\"").nth(1).unwrap().split('"').next().unwrap().to_string())
+        .filter(|code| code != "Other")
+        .collect();
+    (output.status.success(), codes)
+}
+
+#[test]
+fn infinite_recursion_is_rejected() {
+    let (success, codes) = diagnostic_codes("struct Expr {\n    open: string = \"(\";\n    inner: Expr;\n}\n\nstruct Root {\n    e: Expr;\n    b: u8;\n}\n", &[]);
+    assert!(!success);
+    assert_eq!(codes, ["InfiniteRecursion", "InfiniteRecursion"]);
+    
+    let (success, codes) = diagnostic_codes("struct Expr {\n    open: string = \"(\";\n    optional inner: Expr;\n}\n\nstruct Root {\n    e: Expr;\n    b: u8;\n}\n", &[]);
+    assert!(success);
+    assert!(codes.is_empty(), "{:?}", codes);
+}
+
+#[test]
+fn lint_warnings() {
+    let cases = [
+        ("DuplicateStruct", "struct A {\n    x: string = \"a\";\n    y: u8;\n}\n\nstruct B {\n    x: string = \"a\";\n    y: u8;\n}\n\nstruct Root {\n    a: A;\n    b: B;\n}\n"),
+        ("DuplicateVariables", "struct Root {\n    a: oneof {\n        x: string = \"x\";\n        y: string = \"x\";\n    };\n    b: u8;\n}\n"),
+        ("UnrestrictedNumber", "struct Root {\n    a: u8 = 0..255;\n    b: u8;\n}\n"),
+        ("SingleVariable", "struct Root {\n    a: struct {\n        x: u8;\n    };\n    b: u8;\n}\n"),
+        ("UnusedConstant", "define UNUSED 1;\n\nstruct Root {\n    a: u8;\n    b: u8;\n}\n"),
+    ];
+    
+    for (code, source) in cases {
+        let (success, codes) = diagnostic_codes(source, &[]);
+        assert!(success, "{} is not a warning", code);
+        assert_eq!(codes, [code]);
+        
+        let (success, _) = diagnostic_codes(source, &["--deny-warnings"]);
+        assert!(!success, "--deny-warnings accepted a grammar with {}", code);
+    }
+    
+    let (success, codes) = diagnostic_codes("struct Root {\n    a: u8 = 1;\n    b: u8;\n}\n", &["--deny-warnings"]);
+    assert!(success);
+    assert!(codes.is_empty(), "{:?}", codes);
+}
+
+/// Driver that prints the containers that wrote a byte of the first input of a seed
+const TRACE_DRIVER: &str = r#"
+#include <stdio.h>
+#include <stdlib.h>
+#include "generator.c.h"
+
+int main (int argc, char** argv) {
+    unsigned char buf[4096];
+    char list[1024];
+    
+    if (argc != 3) {
+        return 1;
+    }
+    
+    seed(strtoul(argv[1], NULL, 0));
+    generate(buf, sizeof(buf));
+    trace(strtoul(argv[2], NULL, 0), list, sizeof(list));
+    printf("%s", list);
+    return 0;
+}
+"#;
+
+#[test]
+fn trace_maps_bytes_to_containers() {
+    let generator = Generator::build_with(&fixture_path("header.chm"), &["--trace"], TRACE_DRIVER);
+    
+    // The magic only belongs to the root, the byte after the length to the oneof
+    let magic = generator.output(&["1", "0"]);
+    let kind = generator.output(&["1", "6"]);
+    let lines: Vec<&str> = kind.lines().collect();
+    assert_eq!(magic.lines().count(), 1, "{}", magic);
+    assert!(magic.starts_with("0 0 "), "{}", magic);
+    assert_eq!(lines.len(), 2, "{}", kind);
+    assert_eq!(lines[0], magic.trim_end());
+    assert!(lines[1].starts_with("1 6 "), "{}", kind);
+    
+    let trace = generator.dir.join("trace.txt");
+    std::fs::write(&trace, &kind).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--print-trace")
+        .arg(&trace)
+        .arg(fixture_path("header.chm"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert!(lines[1].starts_with("  'Root' (bytes 0.."), "{}", stdout);
+    assert!(lines[2].starts_with("    the oneof in 'Root' in line 3 column 8 (bytes 6.."), "{}", stdout);
+}
+
+/// Driver that streams the trace of three inputs into a file
+const TRACE_STREAM_DRIVER: &str = r#"
+#include <fcntl.h>
+#include <unistd.h>
+#include "generator.c.h"
+
+int main (int argc, char** argv) {
+    unsigned char buf[4096];
+    
+    if (argc != 2) {
+        return 1;
+    }
+    
+    int fd = open(argv[1], O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    
+    if (fd < 0) {
+        return 1;
+    }
+    
+    seed(1);
+    trace_stream(fd);
+    
+    for (int i = 0; i < 3; ++i) {
+        generate(buf, sizeof(buf));
+    }
+    
+    trace_stream(-1);
+    close(fd);
+    return 0;
+}
+"#;
+
+#[test]
+fn trace_stream_round_trips() {
+    let generator = Generator::build_with(&fixture_path("header.chm"), &["--trace"], TRACE_STREAM_DRIVER);
+    let stream = generator.dir.join("trace.bin");
+    generator.output(&[stream.to_str().unwrap()]);
+    
+    let content = std::fs::read(&stream).unwrap();
+    assert_eq!(content.len(), 3 * 5 * 8);
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--print-trace-stream")
+        .arg(&stream)
+        .arg(fixture_path("header.chm"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected: String = (0..3).map(|i| format!("Input #{}:\n  'Root'\n    the oneof in 'Root' in line 3 column 8\n", i)).collect();
+    assert_eq!(stdout, expected);
+    
+    // A truncated record is reported and skipped
+    std::fs::write(&stream, &content[..content.len() - 3]).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--print-trace-stream")
+        .arg(&stream)
+        .arg(fixture_path("header.chm"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("truncated"));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn dedup_avoids_repeated_inputs() {
+    let dir = temp_dir();
+    let grammar = dir.join("letters.chm");
+    std::fs::write(&grammar, "struct Root {\n    letter: u8 = 'a'..'h';\n    rest: string = \"!\";\n}\n").unwrap();
+    
+    let repeats = |args: &[&str]| {
+        let inputs = Generator::build_with(&grammar, args, DRIVER).generate(1, 300);
+        inputs.windows(2).filter(|pair| pair[0] == pair[1]).count()
+    };
+    let plain = repeats(&[]);
+    let dedup = repeats(&["--dedup", "1"]);
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    // Without --dedup every eighth input repeats the one before
+    assert!(plain > 10, "{}", plain);
+    assert!(dedup <= 1, "{}", dedup);
+}
+
+/// Driver that generates into the first bytes of a larger buffer and fails if it wrote past them
+const SMALL_BUFFER_DRIVER: &str = r#"
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include "generator.c.h"
+
+int main (int argc, char** argv) {
+    unsigned char buf[4096];
+    
+    if (argc != 3) {
+        return 1;
+    }
+    
+    size_t size = strtoul(argv[1], NULL, 0);
+    size_t count = strtoul(argv[2], NULL, 0);
+    
+    for (size_t i = 0; i < count; ++i) {
+        memset(buf, 0xA5, sizeof(buf));
+        size_t len = generate(buf, size);
+        
+        for (size_t j = size; j < sizeof(buf); ++j) {
+            if (buf[j] != 0xA5) {
+                return 2;
+            }
+        }
+        
+        printf("%zu\n", len);
+    }
+    
+    return 0;
+}
+"#;
+
+#[test]
+fn lengths_are_clamped_to_the_buffer() {
+    let dir = temp_dir();
+    let grammar = dir.join("clamp.chm");
+    std::fs::write(&grammar, "struct Root {\n    head: string = \"ab\";\n    body: bytes = 10..20, 100..200;\n    tail: string = \"!\";\n}\n").unwrap();
+    
+    let generator = Generator::build_with(&grammar, &[], SMALL_BUFFER_DRIVER);
+    let lengths = |size: &str| -> Vec<usize> {
+        generator.output(&[size, "100"]).lines().map(|line| line.parse().unwrap()).collect()
+    };
+    
+    // 100..200 never fits, so the body falls back to 10..20 and the input stays complete
+    for len in lengths("60") {
+        assert!((13..=23).contains(&len), "{}", len);
+    }
+    
+    // Only 10 bytes are left for the body, which uses all of them
+    for len in lengths("12") {
+        assert_eq!(len, 12);
+    }
+    
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn clashing_entrypoint_is_rejected() {
+    let dir = temp_dir();
+    let grammar = dir.join("from.chm");
+    let source = dir.join("generator.c");
+    std::fs::write(&grammar, "struct from {\n    a: string = \"f\";\n    b: u8;\n}\n\nstruct Root {\n    a: string = \"r\";\n    b: u8;\n}\n").unwrap();
+    
+    // generate_from() is already part of the API
+    let output = Command::new(env!("CARGO_BIN_EXE_chameleon"))
+        .arg("--entrypoint")
+        .arg("Root,from")
+        .arg("-o")
+        .arg(&source)
+        .arg(&grammar)
+        .output()
+        .unwrap();
+    let written = source.exists();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    assert!(!written);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'generate_from' would be defined twice"));
+}
+
+/// Driver that uses the C library next to a generator with the prefix "free"
+const PREFIX_DRIVER: &str = r#"
+#include <stdio.h>
+#include <stdlib.h>
+#include "generator.c.h"
+
+int main (void) {
+    unsigned char* buf = malloc(64);
+    free_seed(1);
+    size_t len = free_generate(buf, 64);
+    printf("%zu\n", len);
+    free(buf);
+    return 0;
+}
+"#;
+
+#[test]
+fn prefix_of_a_libc_function() {
+    let generator = Generator::build_with(&fixture_path("list.chm"), &["--prefix", "free"], PREFIX_DRIVER);
+    let len: usize = generator.output(&[]).trim().parse().unwrap();
+    assert!((3..=11).contains(&len));
+}